@@ -0,0 +1,923 @@
+// 2A03 apu
+//
+// $4000-$4003  pulse 1
+// $4004-$4007  pulse 2
+// $4008-$400B  triangle
+// $400C-$400F  noise
+// $4010-$4013  dmc
+// $4015        status
+// $4017        frame counter
+use crate::board::{ self, Region, Signal, IRQ_DMC, IRQ_FRAME_COUNTER };
+use crate::resampler::Resampler;
+
+
+// default output sample rate
+pub const SAMPLE_RATE: f64 = 44_100.0;
+
+
+// length counter load values, indexed by the top 5 bits of $4003/$4007
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20,  2, 40,  4, 80,  6, 160,  8, 60, 10, 14, 12, 26, 14,
+    12,  16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// pulse duty sequences
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+// triangle sequence
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+// noise timer periods in cpu cycles
+const NOISE_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const NOISE_TABLE_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+// dmc timer periods in cpu cycles
+const DMC_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const DMC_TABLE_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// frame sequencer steps in cpu cycles
+const FRAME_STEPS: [u32; 6] = [7457, 14913, 22371, 29828, 29829, 29830];
+const FRAME_STEPS_5: [u32; 5] = [7457, 14913, 22371, 37281, 37282];
+const FRAME_STEPS_PAL: [u32; 6] = [8313, 16627, 24939, 33252, 33253, 33254];
+const FRAME_STEPS_5_PAL: [u32; 5] = [8313, 16627, 24939, 41565, 41566];
+
+
+// envelope generator
+#[derive(Default, Debug)]
+struct Envelope {
+    // start flag, set by writes to the 4th channel register
+    start: bool,
+    // loop flag, shared with length counter halt
+    looping: bool,
+    // constant volume flag
+    constant: bool,
+    // volume or envelope divider period
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+
+    fn write(&mut self, val: u8) {
+        self.looping = val & 0x20 != 0;
+        self.constant = val & 0x10 != 0;
+        self.volume = val & 0x0f;
+    }
+
+    // clocked by quarter frames
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+            return;
+        }
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.volume;
+        if self.decay > 0 {
+            self.decay -= 1;
+        } else if self.looping {
+            self.decay = 15;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        match self.constant {
+            true => self.volume,
+            false => self.decay,
+        }
+    }
+}
+
+
+// length counter
+#[derive(Default, Debug)]
+struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+
+    fn load(&mut self, index: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(index & 0x1f) as usize];
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    // clocked by half frames
+    fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+
+// sweep unit
+#[derive(Default, Debug)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+    divider: u8,
+    // pulse 1 negates with ones' complement, pulse 2 with two's complement
+    ones_complement: bool,
+}
+
+impl Sweep {
+
+    fn write(&mut self, val: u8) {
+        self.enabled = val & 0x80 != 0;
+        self.period = (val >> 4) & 0x07;
+        self.negate = val & 0x08 != 0;
+        self.shift = val & 0x07;
+        self.reload = true;
+    }
+
+    // the target period is calculated continuously
+    fn target(&self, period: u16) -> u16 {
+        let change = period >> self.shift;
+        if self.negate {
+            match self.ones_complement {
+                true => period.wrapping_sub(change).wrapping_sub(1),
+                false => period.wrapping_sub(change),
+            }
+        } else {
+            period + change
+        }
+    }
+
+    // channel is silenced when the period is too low or the target overflows
+    fn muting(&self, period: u16) -> bool {
+        period < 8 || (!self.negate && self.target(period) > 0x7ff)
+    }
+
+    // clocked by half frames, returns the new timer period
+    fn clock(&mut self, period: u16) -> u16 {
+        let mut period = period;
+        if self.divider == 0 && self.enabled && self.shift > 0 && !self.muting(period) {
+            period = self.target(period);
+        }
+        if self.divider == 0 || self.reload {
+            self.divider = self.period;
+            self.reload = false;
+        } else {
+            self.divider -= 1;
+        }
+        period
+    }
+}
+
+
+// pulse channel
+#[derive(Default, Debug)]
+struct Pulse {
+    duty: u8,
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Sweep,
+}
+
+impl Pulse {
+
+    fn new(ones_complement: bool) -> Self {
+        Self {
+            sweep: Sweep {
+                ones_complement,
+                ..Sweep::default()
+            },
+            ..Self::default()
+        }
+    }
+
+    fn write_u8(&mut self, reg: u16, val: u8) {
+        match reg {
+            // DDLC VVVV
+            0 => {
+                self.duty = (val >> 6) & 0x03;
+                self.length.halt = val & 0x20 != 0;
+                self.envelope.write(val);
+            },
+            // EPPP NSSS
+            1 => self.sweep.write(val),
+            // LLLL LLLL
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | val as u16;
+            },
+            // llll lHHH
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((val as u16 & 0x07) << 8);
+                self.length.load(val >> 3);
+                self.sequence = 0;
+                self.envelope.start = true;
+            },
+            _ => (),
+        }
+    }
+
+    // clocked every apu cycle (2 cpu cycles)
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence = (self.sequence + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_quarter(&mut self) {
+        self.envelope.clock();
+    }
+
+    fn clock_half(&mut self) {
+        self.length.clock();
+        self.timer_period = self.sweep.clock(self.timer_period);
+    }
+
+    fn output(&self) -> u8 {
+        if !self.length.active()
+            || DUTY_TABLE[self.duty as usize][self.sequence as usize] == 0
+            || self.sweep.muting(self.timer_period) {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+
+// triangle channel
+#[derive(Default, Debug)]
+struct Triangle {
+    sequence: u8,
+    timer_period: u16,
+    timer: u16,
+    length: LengthCounter,
+    // linear counter
+    linear_period: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    // control flag, shared with length counter halt
+    control: bool,
+}
+
+impl Triangle {
+
+    fn write_u8(&mut self, reg: u16, val: u8) {
+        match reg {
+            // CRRR RRRR
+            0 => {
+                self.control = val & 0x80 != 0;
+                self.length.halt = self.control;
+                self.linear_period = val & 0x7f;
+            },
+            // LLLL LLLL
+            2 => {
+                self.timer_period = (self.timer_period & 0x0700) | val as u16;
+            },
+            // llll lHHH
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | ((val as u16 & 0x07) << 8);
+                self.length.load(val >> 3);
+                self.linear_reload = true;
+            },
+            _ => (),
+        }
+    }
+
+    // clocked every cpu cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            // the sequencer only advances when both counters are non-zero.
+            // periods below 2 are ultrasonic, hold the output instead of popping
+            if self.length.active() && self.linear_counter > 0 && self.timer_period >= 2 {
+                self.sequence = (self.sequence + 1) & 0x1f;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_quarter(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    fn clock_half(&mut self) {
+        self.length.clock();
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE_TABLE[self.sequence as usize]
+    }
+}
+
+
+// noise channel
+#[derive(Debug)]
+struct Noise {
+    // 15 bit linear feedback shift register
+    shift: u16,
+    // mode flag, feedback from bit 6 instead of bit 1
+    mode: bool,
+    timer_period: u16,
+    timer: u16,
+    // period table of the region
+    periods: &'static [u16; 16],
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            // loaded with 1 on power up
+            shift: 1,
+            mode: false,
+            timer_period: NOISE_TABLE[0],
+            timer: 0,
+            periods: &NOISE_TABLE,
+            length: LengthCounter::default(),
+            envelope: Envelope::default(),
+        }
+    }
+}
+
+impl Noise {
+
+    fn write_u8(&mut self, reg: u16, val: u8) {
+        match reg {
+            // --LC VVVV
+            0 => {
+                self.length.halt = val & 0x20 != 0;
+                self.envelope.write(val);
+            },
+            // M--- PPPP
+            2 => {
+                self.mode = val & 0x80 != 0;
+                self.timer_period = self.periods[(val & 0x0f) as usize];
+            },
+            // llll l---
+            3 => {
+                self.length.load(val >> 3);
+                self.envelope.start = true;
+            },
+            _ => (),
+        }
+    }
+
+    // clocked every cpu cycle
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = match self.mode {
+                true => 6,
+                false => 1,
+            };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_quarter(&mut self) {
+        self.envelope.clock();
+    }
+
+    fn clock_half(&mut self) {
+        self.length.clock();
+    }
+
+    fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 0x01 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+
+// delta modulation channel
+#[derive(Debug)]
+struct Dmc {
+    irq_enabled: bool,
+    irq_flag: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    // rate table of the region
+    periods: &'static [u16; 16],
+    // memory reader
+    sample_addr: u16,
+    sample_length: u16,
+    current_addr: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+    // output unit
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    level: u8,
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self {
+            irq_enabled: false,
+            irq_flag: false,
+            looping: false,
+            timer_period: DMC_TABLE[0],
+            timer: 0,
+            periods: &DMC_TABLE,
+            sample_addr: 0xc000,
+            sample_length: 1,
+            current_addr: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            level: 0,
+        }
+    }
+}
+
+impl Dmc {
+
+    fn write_u8(&mut self, reg: u16, val: u8) {
+        match reg {
+            // IL-- RRRR
+            0 => {
+                self.irq_enabled = val & 0x80 != 0;
+                self.looping = val & 0x40 != 0;
+                self.timer_period = self.periods[(val & 0x0f) as usize];
+                if !self.irq_enabled {
+                    self.irq_flag = false;
+                }
+            },
+            // -DDD DDDD
+            1 => self.level = val & 0x7f,
+            // AAAA AAAA, sample address = %11AAAAAA.AA000000
+            2 => self.sample_addr = 0xc000 | ((val as u16) << 6),
+            // LLLL LLLL, sample length = %LLLL.LLLL0001
+            3 => self.sample_length = ((val as u16) << 4) | 0x0001,
+            _ => (),
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    // address the memory reader wants to fetch, if the sample buffer is empty
+    fn fetch_addr(&self) -> Option<u16> {
+        match (self.sample_buffer, self.bytes_remaining) {
+            (None, 1..) => Some(self.current_addr),
+            _ => None,
+        }
+    }
+
+    fn load_sample(&mut self, val: u8) {
+        self.sample_buffer = Some(val);
+        self.current_addr = match self.current_addr {
+            0xffff => 0x8000,
+            _ => self.current_addr + 1,
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    // clocked every cpu cycle
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+        // output unit
+        if !self.silence {
+            match self.shift & 0x01 {
+                0 if self.level >= 2 => self.level -= 2,
+                1 if self.level <= 125 => self.level += 2,
+                _ => (),
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            // start a new output cycle
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(val) => {
+                    self.silence = false;
+                    self.shift = val;
+                },
+                None => self.silence = true,
+            }
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+
+// frame counter
+#[derive(Debug)]
+struct FrameCounter {
+    // 0: 4-step, 1: 5-step
+    five_step: bool,
+    irq_inhibit: bool,
+    irq_flag: bool,
+    // position in cpu cycles
+    cycle: u32,
+    // step tables of the region
+    steps: &'static [u32; 6],
+    steps_5: &'static [u32; 5],
+    // $4017 write takes effect 3 or 4 cpu cycles later
+    write_delay: u8,
+    pending: u8,
+}
+
+impl Default for FrameCounter {
+    fn default() -> Self {
+        Self {
+            five_step: false,
+            irq_inhibit: false,
+            irq_flag: false,
+            cycle: 0,
+            steps: &FRAME_STEPS,
+            steps_5: &FRAME_STEPS_5,
+            write_delay: 0,
+            pending: 0,
+        }
+    }
+}
+
+
+// apu
+pub struct APU {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame: FrameCounter,
+    // cpu cycles
+    cycles: u64,
+    // irq signal line
+    irq: Signal,
+    // nonlinear mixer lookup tables
+    pulse_table: Vec<f32>,
+    tnd_table: Vec<f32>,
+    // band-limited output
+    resampler: Resampler,
+    // cpu cycles since the last end_frame
+    frame_clock: u32,
+    last_output: f32,
+    // cartridge expansion audio
+    expansion: f32,
+}
+
+
+impl APU {
+
+    pub fn new(irq: Signal) -> Self {
+        Self {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            frame: FrameCounter::default(),
+            cycles: 0,
+            irq,
+            // pulse_out = 95.52 / (8128.0 / (pulse1 + pulse2) + 100)
+            pulse_table: (0..31).map(|n| match n {
+                0 => 0.0,
+                n => 95.52 / (8128.0 / n as f32 + 100.0),
+            }).collect(),
+            // tnd_out = 163.67 / (24329.0 / (3 * triangle + 2 * noise + dmc) + 100)
+            tnd_table: (0..203).map(|n| match n {
+                0 => 0.0,
+                n => 163.67 / (24329.0 / n as f32 + 100.0),
+            }).collect(),
+            resampler: Resampler::new(Region::Ntsc.cpu_clock(), SAMPLE_RATE),
+            frame_clock: 0,
+            last_output: 0.0,
+            expansion: 0.0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        match region {
+            Region::Ntsc => {
+                self.noise.periods = &NOISE_TABLE;
+                self.dmc.periods = &DMC_TABLE;
+                self.frame.steps = &FRAME_STEPS;
+                self.frame.steps_5 = &FRAME_STEPS_5;
+            },
+            Region::Pal => {
+                self.noise.periods = &NOISE_TABLE_PAL;
+                self.dmc.periods = &DMC_TABLE_PAL;
+                self.frame.steps = &FRAME_STEPS_PAL;
+                self.frame.steps_5 = &FRAME_STEPS_5_PAL;
+            },
+        }
+        let sample_rate = self.resampler.sample_rate();
+        self.resampler.set_rates(region.cpu_clock(), sample_rate);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        let clock_rate = self.resampler.clock_rate();
+        self.resampler.set_rates(clock_rate, sample_rate);
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.resampler.sample_rate()
+    }
+
+    // make the samples of the cycles run so far available
+    pub fn end_frame(&mut self) {
+        self.resampler.end_frame(self.frame_clock);
+        self.frame_clock = 0;
+    }
+
+    // read 16bit mono pcm samples, returns the number of samples written
+    pub fn read_samples(&mut self, out: &mut [i16]) -> usize {
+        self.end_frame();
+        self.resampler.read_samples(out)
+    }
+
+    pub fn samples_avail(&self) -> usize {
+        self.resampler.samples_avail()
+    }
+
+    // read apu registers
+    pub fn read_u8(&mut self, addr: u16) -> u8 {
+        match addr {
+            // status
+            //             7  bit  0
+            // ---- ----
+            // IF-D NT21
+            // |||| ||||
+            // |||| |||+- pulse 1 length counter > 0
+            // |||| ||+-- pulse 2 length counter > 0
+            // |||| |+--- triangle length counter > 0
+            // |||| +---- noise length counter > 0
+            // |||+------ dmc bytes remaining > 0
+            // ||+------- open bus
+            // |+-------- frame interrupt, cleared by this read
+            // +--------- dmc interrupt
+            0x4015 => {
+                let mut ret = 0;
+                if self.pulse1.length.active() {
+                    ret |= 0x01;
+                }
+                if self.pulse2.length.active() {
+                    ret |= 0x02;
+                }
+                if self.triangle.length.active() {
+                    ret |= 0x04;
+                }
+                if self.noise.length.active() {
+                    ret |= 0x08;
+                }
+                if self.dmc.bytes_remaining > 0 {
+                    ret |= 0x10;
+                }
+                if self.frame.irq_flag {
+                    ret |= 0x40;
+                }
+                if self.dmc.irq_flag {
+                    ret |= 0x80;
+                }
+                self.frame.irq_flag = false;
+                self.update_irq();
+                ret
+            },
+            _ => 0,
+        }
+    }
+
+    // write apu registers
+    pub fn write_u8(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write_u8(addr - 0x4000, val),
+            0x4004..=0x4007 => self.pulse2.write_u8(addr - 0x4004, val),
+            0x4008..=0x400b => self.triangle.write_u8(addr - 0x4008, val),
+            0x400c..=0x400f => self.noise.write_u8(addr - 0x400c, val),
+            0x4010..=0x4013 => self.dmc.write_u8(addr - 0x4010, val),
+            // status
+            0x4015 => {
+                self.pulse1.length.set_enabled(val & 0x01 != 0);
+                self.pulse2.length.set_enabled(val & 0x02 != 0);
+                self.triangle.length.set_enabled(val & 0x04 != 0);
+                self.noise.length.set_enabled(val & 0x08 != 0);
+                self.dmc.set_enabled(val & 0x10 != 0);
+                self.dmc.irq_flag = false;
+            },
+            // frame counter
+            // MI-- ----
+            0x4017 => {
+                self.frame.pending = val;
+                // the write is applied after 3 cpu cycles if it happens on an apu cycle, otherwise 4
+                self.frame.write_delay = match self.cycles & 0x01 {
+                    0 => 3,
+                    _ => 4,
+                };
+                self.frame.irq_inhibit = val & 0x40 != 0;
+                if self.frame.irq_inhibit {
+                    self.frame.irq_flag = false;
+                }
+            },
+            _ => (),
+        }
+        self.update_irq();
+    }
+
+    // drive the irq line from the frame counter and dmc flags
+    fn update_irq(&self) {
+        board::set_irq(&self.irq, IRQ_FRAME_COUNTER, self.frame.irq_flag);
+        board::set_irq(&self.irq, IRQ_DMC, self.dmc.irq_flag);
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter();
+        self.pulse2.clock_quarter();
+        self.triangle.clock_quarter();
+        self.noise.clock_quarter();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse1.clock_half();
+        self.pulse2.clock_half();
+        self.triangle.clock_half();
+        self.noise.clock_half();
+    }
+
+    fn set_frame_irq(&mut self) {
+        if !self.frame.irq_inhibit {
+            self.frame.irq_flag = true;
+            self.update_irq();
+        }
+    }
+
+    fn clock_frame_counter(&mut self) {
+        // delayed $4017 write
+        if self.frame.write_delay > 0 {
+            self.frame.write_delay -= 1;
+            if self.frame.write_delay == 0 {
+                self.frame.five_step = self.frame.pending & 0x80 != 0;
+                self.frame.cycle = 0;
+                // 5-step mode clocks all units immediately
+                if self.frame.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+        }
+        self.frame.cycle += 1;
+        let (steps, steps_5) = (self.frame.steps, self.frame.steps_5);
+        if self.frame.five_step {
+            match self.frame.cycle {
+                c if c == steps_5[0] || c == steps_5[2] => {
+                    self.clock_quarter_frame();
+                },
+                c if c == steps_5[1] || c == steps_5[3] => {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                },
+                c if c == steps_5[4] => self.frame.cycle = 0,
+                _ => (),
+            }
+        } else {
+            match self.frame.cycle {
+                c if c == steps[0] || c == steps[2] => {
+                    self.clock_quarter_frame();
+                },
+                c if c == steps[1] => {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                },
+                c if c == steps[3] => self.set_frame_irq(),
+                c if c == steps[4] => {
+                    self.set_frame_irq();
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                },
+                c if c == steps[5] => {
+                    self.set_frame_irq();
+                    self.frame.cycle = 0;
+                },
+                _ => (),
+            }
+        }
+    }
+
+    // address of the next dmc sample byte, if the dmc needs one
+    pub fn dmc_fetch_addr(&self) -> Option<u16> {
+        self.dmc.fetch_addr()
+    }
+
+    // feed a sample byte read from the cpu bus to the dmc
+    pub fn dmc_load_sample(&mut self, val: u8) {
+        self.dmc.load_sample(val);
+        self.update_irq();
+    }
+
+    // expansion audio level of the mapper, added to the mix on the next tick
+    pub fn set_expansion_output(&mut self, output: f32) {
+        self.expansion = output;
+    }
+
+    // nonlinear channel mix
+    pub fn output(&self) -> f32 {
+        let pulse = self.pulse1.output() + self.pulse2.output();
+        let tnd = 3 * self.triangle.output() as usize + 2 * self.noise.output() as usize + self.dmc.output() as usize;
+        self.pulse_table[pulse as usize] + self.tnd_table[tnd] + self.expansion
+    }
+
+    // step simulation, called once per cpu cycle
+    pub fn tick(&mut self) {
+        self.cycles = self.cycles.wrapping_add(1);
+        if self.cycles & 0x01 == 0 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        self.clock_frame_counter();
+        // feed amplitude changes to the resampler
+        let output = self.output();
+        if output != self.last_output {
+            self.resampler.add_delta(self.frame_clock, output - self.last_output);
+            self.last_output = output;
+        }
+        self.frame_clock += 1;
+    }
+}
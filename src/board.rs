@@ -18,6 +18,14 @@ impl Memory {
     }
 }
 
+impl From<Vec<u8>> for Memory {
+    fn from(data: Vec<u8>) -> Self {
+        Self {
+            data,
+        }
+    }
+}
+
 impl Deref for Memory {
 
     type Target = [u8];
@@ -54,4 +62,81 @@ impl Memory {
 }
 
 
-pub type Signal = Rc<RefCell<u8>>;
\ No newline at end of file
+pub type Signal = Rc<RefCell<u8>>;
+
+
+// the irq line is wired-or, every source drives its own bit and the cpu
+// sees the line asserted while any of them is set
+pub const IRQ_FRAME_COUNTER: u8 = 0x01;
+pub const IRQ_DMC: u8 = 0x02;
+pub const IRQ_MAPPER: u8 = 0x04;
+
+
+// assert or release one source of the irq line
+pub fn set_irq(irq: &Signal, source: u8, active: bool) {
+    let mut line = irq.borrow_mut();
+    match active {
+        true => *line |= source,
+        false => *line &= !source,
+    }
+}
+
+
+// console region, decides the master clock and frame timing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+
+impl Region {
+
+    // cpu clock rate in Hz
+    pub fn cpu_clock(&self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+        }
+    }
+
+    // frames per second
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            Region::Pal => 50.0070,
+        }
+    }
+
+    // ppu dots to run for the given cpu cycle. ntsc runs 3 dots per cycle, pal runs 3.2
+    pub fn ppu_dots(&self, cpu_cycle: u64) -> u32 {
+        match self {
+            Region::Ntsc => 3,
+            Region::Pal if cpu_cycle.is_multiple_of(5) => 4,
+            Region::Pal => 3,
+        }
+    }
+
+    // vblank scanlines in addition to the 20 ntsc ones
+    pub fn extra_vblank_lines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 0,
+            Region::Pal => 50,
+        }
+    }
+}
+
+
+impl std::str::FromStr for Region {
+
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            _ => Err(format!("unknown region {}", s)),
+        }
+    }
+}
@@ -1,54 +1,424 @@
 
 use std::error::Error;
 use std::fs::File;
+use std::io::{self, Read};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::fmt;
 use byteorder::ReadBytesExt;
-use crate::board::{ Memory, Signal };
-use crate::mapper::{MirroMode, Mapper, NRom, UxRom, MMC3, PRG_BANK_SIZE, CHR_BANK_SIZE};
+use crate::board::{ Memory, Region, Signal };
+use crate::mapper::{MirroMode, Mapper, NRom, MMC1, UxRom, CNRom, MMC3, MMC3Board, MMC3Revision, MMC5, AxRom, MMC2, ColorDreams, GxRom, VRC4, VRC6, VRC7, FME7, Namco163, PRG_BANK_SIZE, CHR_BANK_SIZE};
+
+
+const MAGIC: [u8; 4] = [b'N', b'E', b'S', 0x1a];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const KB: usize = 1024;
+// nes 2.0 sizes past this can only come from the exponent form, no board
+// addresses that much
+const MAX_ROM_SIZE: usize = 64 * KB * KB;
+
+
+// cartridge loading errors
+#[derive(Debug)]
+pub enum CartridgeError {
+    // file could not be read
+    Io(io::Error),
+    // not an ines file
+    BadMagic([u8; 4]),
+    // the file ended before a section was complete
+    Truncated {
+        section: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    // no mapper implementation for this board
+    UnsupportedMapper {
+        number: u16,
+        name: &'static str,
+    },
+    // header sizes that don't fit the board
+    InconsistentSize(String),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeError::Io(e) => write!(f, "{}", e),
+            CartridgeError::BadMagic(magic) => write!(f, "not an ines file, bad magic {:02x?}", magic),
+            CartridgeError::Truncated { section, expected, actual } => {
+                write!(f, "truncated {}, expected {} bytes but got {}", section, expected, actual)
+            },
+            CartridgeError::UnsupportedMapper { number, name } => {
+                write!(f, "unsupported mapper {} ({})", number, name)
+            },
+            CartridgeError::InconsistentSize(reason) => write!(f, "inconsistent size, {}", reason),
+        }
+    }
+}
+
+impl Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CartridgeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CartridgeError {
+    fn from(e: io::Error) -> Self {
+        CartridgeError::Io(e)
+    }
+}
+
+
+// common board name of a mapper number
+pub fn mapper_name(number: u16) -> &'static str {
+    match number {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        19 => "Namco 163",
+        21 | 22 | 23 | 25 => "VRC2/VRC4",
+        24 | 26 => "VRC6",
+        66 => "GxROM",
+        69 => "FME-7",
+        85 => "VRC7",
+        118 => "TxSROM",
+        119 => "TQROM",
+        206 => "DxROM",
+        _ => "unknown",
+    }
+}
+
+
+// read exactly `size` bytes of a rom section, the buffer grows with the data
+// so a bad size in the header can't allocate more than the file holds
+fn read_section<T: Read>(reader: &mut T, section: &'static str, size: usize) -> Result<Memory, CartridgeError> {
+    let mut data = Vec::new();
+    reader.take(size as u64).read_to_end(&mut data)?;
+    if data.len() < size {
+        return Err(CartridgeError::Truncated { section, expected: size, actual: data.len() });
+    }
+    Ok(Memory::from(data))
+}
+
+
+// rom sizes a board can address
+struct RomLimits {
+    prg_bank: usize,
+    prg_min: usize,
+    prg_max: usize,
+    chr_bank: usize,
+    chr_max: usize,
+}
+
+fn rom_limits(mapper_number: u16) -> Option<RomLimits> {
+    // in K: prg bank, smallest and largest prg rom, chr bank, largest chr rom
+    let (prg_bank, prg_min, prg_max, chr_bank, chr_max) = match mapper_number {
+        0 => (16, 16, 32, 8, 8),
+        1 => (16, 16, 512, 4, 128),
+        2 => (16, 16, 4096, 8, 8),
+        3 => (16, 16, 32, 8, 2048),
+        // the last two 8K banks are fixed
+        4 | 118 | 119 | 206 => (8, 16, 2048, 1, 256),
+        5 => (8, 8, 1024, 1, 1024),
+        7 => (32, 32, 512, 8, 8),
+        // the last three 8K banks are fixed
+        9 => (8, 32, 128, 4, 128),
+        10 => (16, 32, 256, 4, 128),
+        11 => (32, 32, 128, 8, 128),
+        19 => (8, 8, 512, 1, 256),
+        21 | 22 | 23 | 25 => (8, 16, 256, 1, 512),
+        24 | 26 => (16, 16, 256, 1, 256),
+        66 => (32, 32, 128, 8, 32),
+        69 | 85 => (8, 8, 512, 1, 256),
+        _ => return None,
+    };
+    Some(RomLimits {
+        prg_bank: prg_bank * KB,
+        prg_min: prg_min * KB,
+        prg_max: prg_max * KB,
+        chr_bank: chr_bank * KB,
+        chr_max: chr_max * KB,
+    })
+}
+
+// reject rom sizes before anything is allocated for them
+fn check_rom_sizes(header: &CartridgeHeader) -> Result<(), CartridgeError> {
+    let (prg, chr) = (header.prg_rom_size(), header.chr_rom_size());
+    if prg == 0 {
+        return Err(CartridgeError::InconsistentSize("header declares no PRG ROM".to_string()));
+    }
+    if prg > MAX_ROM_SIZE || chr > MAX_ROM_SIZE {
+        return Err(CartridgeError::InconsistentSize(format!("ROM size past {}M, PRG {} bytes, CHR {} bytes", MAX_ROM_SIZE / KB / KB, prg, chr)));
+    }
+    let number = header.mapper_number();
+    let Some(limits) = rom_limits(number) else {
+        return Ok(());
+    };
+    let name = mapper_name(number);
+    if prg < limits.prg_min || prg > limits.prg_max || prg % limits.prg_bank != 0 {
+        return Err(CartridgeError::InconsistentSize(format!("{} has {}K to {}K PRG ROM in {}K banks, got {} bytes",
+            name, limits.prg_min / KB, limits.prg_max / KB, limits.prg_bank / KB, prg)));
+    }
+    if chr > limits.chr_max || chr % limits.chr_bank != 0 {
+        return Err(CartridgeError::InconsistentSize(format!("{} has up to {}K CHR ROM in {}K banks, got {} bytes",
+            name, limits.chr_max / KB, limits.chr_bank / KB, chr)));
+    }
+    Ok(())
+}
+
+
+// cpu/ppu timing declared by the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+// console type declared by the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice,
+    // extended console type from byte 13
+    Extended(u8),
+}
 
 
 // cartridge header
+//
+// byte    ines 1.0                 nes 2.0
+// 0-3     NES<EOF>                 NES<EOF>
+// 4       prg rom 16K units        prg rom size lsb
+// 5       chr rom 8K units         chr rom size lsb
+// 6       flags 6                  flags 6
+// 7       flags 7                  flags 7, bits 2-3 = 10 marks nes 2.0
+// 8       prg ram 8K units         mapper msb / submapper
+// 9                                prg/chr rom size msb
+// 10                               prg ram / prg nvram shift
+// 11                               chr ram / chr nvram shift
+// 12                               cpu/ppu timing
+// 13                               vs system type / extended console type
+// 14                               misc roms
+// 15                               default expansion device
 #[derive(Default, Debug)]
-struct CartridgeHeader {
+pub struct CartridgeHeader {
     magic:    [u8; 4],
     num_prg:  u8,
     num_chr:  u8,
     flag1:    u8,
     flag2:    u8,
     num_ram:  u8,
-    reserved: [u8; 7],
+    // nes 2.0 fields, padding in ines 1.0
+    ext:      [u8; 7],
 }
 
 impl CartridgeHeader {
 
     // load cartridge header from reader
-    fn read<T: ReadBytesExt>(reader: &mut T)  -> Result<Self, Box<dyn Error>> {
+    fn read<T: ReadBytesExt>(reader: &mut T)  -> Result<Self, CartridgeError> {
+        let data = read_section(reader, "header", HEADER_SIZE)?;
+        let mut data = &data[..];
         let mut header = CartridgeHeader::default();
-        reader.read_exact(&mut header.magic)?;
-        header.num_prg = reader.read_u8()?;
-        header.num_chr = reader.read_u8()?;
-        header.flag1 = reader.read_u8()?;
-        header.flag2 = reader.read_u8()?;
-        header.num_ram = reader.read_u8()?;
-        reader.read_exact(&mut header.reserved)?;
+        data.read_exact(&mut header.magic)?;
+        if header.magic != MAGIC {
+            return Err(CartridgeError::BadMagic(header.magic));
+        }
+        header.num_prg = data.read_u8()?;
+        header.num_chr = data.read_u8()?;
+        header.flag1 = data.read_u8()?;
+        header.flag2 = data.read_u8()?;
+        header.num_ram = data.read_u8()?;
+        data.read_exact(&mut header.ext)?;
 
         Ok(header)
     }
+
+    pub fn is_nes2(&self) -> bool {
+        self.flag2 & 0x0c == 0x08
+    }
+
+    // old dumps have garbage like "DiskDude!" in bytes 7-15, the upper mapper nibble can't be trusted
+    fn is_archaic(&self) -> bool {
+        !self.is_nes2() && self.ext[3..].iter().any(|x| *x != 0)
+    }
+
+    pub fn mapper_number(&self) -> u16 {
+        let low = ((self.flag1 >> 4) & 0x0f) as u16;
+        if self.is_nes2() {
+            low | (self.flag2 & 0xf0) as u16 | ((self.num_ram & 0x0f) as u16) << 8
+        } else if self.is_archaic() {
+            low
+        } else {
+            low | (self.flag2 & 0xf0) as u16
+        }
+    }
+
+    pub fn submapper(&self) -> u8 {
+        match self.is_nes2() {
+            true => self.num_ram >> 4,
+            false => 0,
+        }
+    }
+
+    // rom size from the lsb, the msb nibble and the unit size.
+    // msb 0xf selects the exponent-multiplier form, 2^E * (MM * 2 + 1)
+    fn rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+        match msb {
+            0x0f => {
+                let exponent = (lsb >> 2) as u32;
+                let multiplier = (lsb & 0x03) as usize * 2 + 1;
+                2usize.checked_pow(exponent).unwrap_or(0).saturating_mul(multiplier)
+            },
+            _ => ((msb as usize) << 8 | lsb as usize) * unit,
+        }
+    }
+
+    pub fn prg_rom_size(&self) -> usize {
+        match self.is_nes2() {
+            true => Self::rom_size(self.num_prg, self.ext[0] & 0x0f, PRG_BANK_SIZE),
+            false => self.num_prg as usize * PRG_BANK_SIZE,
+        }
+    }
+
+    pub fn chr_rom_size(&self) -> usize {
+        match self.is_nes2() {
+            true => Self::rom_size(self.num_chr, self.ext[0] >> 4, CHR_BANK_SIZE),
+            false => self.num_chr as usize * CHR_BANK_SIZE,
+        }
+    }
+
+    // ram size from a nes 2.0 shift count, 64 << shift or nothing
+    fn ram_size(shift: u8) -> usize {
+        match shift {
+            0 => 0,
+            shift => 64 << shift,
+        }
+    }
+
+    // volatile prg ram
+    pub fn prg_ram_size(&self) -> usize {
+        match self.is_nes2() {
+            true => Self::ram_size(self.ext[1] & 0x0f),
+            // ines 1.0 assumes 8K when no size is given
+            false if self.has_battery() => 0,
+            false => self.num_ram.max(1) as usize * 8192,
+        }
+    }
+
+    // battery backed prg ram
+    pub fn prg_nvram_size(&self) -> usize {
+        match self.is_nes2() {
+            true => Self::ram_size(self.ext[1] >> 4),
+            false if self.has_battery() => self.num_ram.max(1) as usize * 8192,
+            false => 0,
+        }
+    }
+
+    pub fn chr_ram_size(&self) -> usize {
+        match self.is_nes2() {
+            true => Self::ram_size(self.ext[2] & 0x0f),
+            // ines 1.0 carts without chr rom have 8K chr ram
+            false if self.num_chr == 0 => CHR_BANK_SIZE,
+            false => 0,
+        }
+    }
+
+    pub fn chr_nvram_size(&self) -> usize {
+        match self.is_nes2() {
+            true => Self::ram_size(self.ext[2] >> 4),
+            false => 0,
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        match self.is_nes2() {
+            true => match self.ext[3] & 0x03 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                2 => Timing::MultiRegion,
+                _ => Timing::Dendy,
+            },
+            // ines 1.0 byte 9 bit 0 is rarely set, but honor it
+            false if !self.is_archaic() && self.ext[0] & 0x01 != 0 => Timing::Pal,
+            false => Timing::Ntsc,
+        }
+    }
+
+    // region to emulate for the header timing
+    pub fn region(&self) -> Region {
+        match self.timing() {
+            Timing::Pal => Region::Pal,
+            _ => Region::Ntsc,
+        }
+    }
+
+    pub fn console_type(&self) -> ConsoleType {
+        match self.flag2 & 0x03 {
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::Playchoice,
+            _ if self.is_nes2() => ConsoleType::Extended(self.ext[4] & 0x0f),
+            _ => ConsoleType::Extended(0),
+        }
+    }
+
+    // default expansion device, 0 is unspecified and 1 is the standard controllers
+    pub fn expansion_device(&self) -> u8 {
+        match self.is_nes2() {
+            true => self.ext[6] & 0x3f,
+            false => 0,
+        }
+    }
+
+    // hardwired mirroring, four screen vram overrides the mirroring bit
+    pub fn mirror_mode(&self) -> MirroMode {
+        match self.flag1 & 0x09 {
+            0 => MirroMode::Horizontal,
+            1 => MirroMode::Vertical,
+            _ => MirroMode::FourScreen,
+        }
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.flag1 & 0x02 != 0
+    }
+
+    pub fn has_trainer(&self) -> bool {
+        self.flag1 & 0x04 != 0
+    }
 }
 
 impl fmt::Display for CartridgeHeader {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mirror = match self.flag1 & 0x01 {
-            0 => "h",
-            _ => "v",
+        let mirror = match self.mirror_mode() {
+            MirroMode::Horizontal => "h",
+            MirroMode::Vertical => "v",
+            _ => "4",
         };
-        let mapper_number = ((self.flag1 >> 4) & 0x0f) | (self.flag2 & 0xf0);
-        let has_trainer = self.flag1 & 0x04 != 0;
+        let mapper_number = self.mapper_number();
 
-        write!(f, "prg: {}K, chr: {}K, trainer: {}, mirror: {}, mapper: {}",
-            self.num_prg * 16, self.num_chr * 8, has_trainer, mirror, mapper_number)?;
+        write!(f, "prg: {}K, chr: {}K, trainer: {}, mirror: {}, mapper: {} ({})",
+            self.prg_rom_size() / 1024, self.chr_rom_size() / 1024, self.has_trainer(), mirror, mapper_number, mapper_name(mapper_number))?;
+        if self.is_nes2() {
+            write!(f, ", nes 2.0 submapper: {}, prg ram: {}K, prg nvram: {}K, chr ram: {}K, chr nvram: {}K, timing: {:?}, console: {:?}, expansion: {}",
+                self.submapper(), self.prg_ram_size() / 1024, self.prg_nvram_size() / 1024,
+                self.chr_ram_size() / 1024, self.chr_nvram_size() / 1024,
+                self.timing(), self.console_type(), self.expansion_device())?;
+        }
         Ok(())
     }
 }
@@ -57,6 +427,8 @@ impl fmt::Display for CartridgeHeader {
 #[derive(Default)]
 pub struct Cartridge {
     header: CartridgeHeader,
+    // 512 bytes loaded at $7000 before the game starts
+    trainer: Option<Memory>,
     mapper: Option<Box<dyn Mapper>>,
 }
 
@@ -65,55 +437,141 @@ impl Cartridge {
     fn default() -> Self {
         Self {
             header: CartridgeHeader::default(),
+            trainer: None,
             mapper: None,
         }
     }
 
 
     // load cartridge data from reader
-    fn read<T: ReadBytesExt>(reader: &mut T, irq: Signal) -> Result<Self, Box<dyn Error>> {
+    pub fn read<T: ReadBytesExt>(reader: &mut T, irq: Signal) -> Result<Self, CartridgeError> {
 
         let mut cartridge = Cartridge::default();
         let header = CartridgeHeader::read(reader)?;
-
-        println!("cartridge info {}", header);
-
-        let mut prg: Memory = Memory::new(PRG_BANK_SIZE * 1);
-        let mut chr: Memory = Memory::new(CHR_BANK_SIZE * 1);
-        if header.num_prg > 0 {
-            prg = Memory::new(PRG_BANK_SIZE * header.num_prg as usize);
-            reader.read_exact(&mut prg)?;
+        check_rom_sizes(&header)?;
+        // the trainer sits between the header and prg rom
+        if header.has_trainer() {
+            cartridge.trainer = Some(read_section(reader, "trainer", TRAINER_SIZE)?);
         }
-        if header.num_chr > 0 {
-            chr = Memory::new(CHR_BANK_SIZE * header.num_chr as usize);
-            reader.read_exact(&mut chr)?;
-        }
-        let mirror_mode = match header.flag1 & 0x01 {
-            0 => MirroMode::Horizontal,
-            _ => MirroMode::Vertical,
+        let prg = read_section(reader, "PRG ROM", header.prg_rom_size())?;
+        // no chr rom means chr ram, 8K unless the header says otherwise
+        let chr = match (header.chr_rom_size(), header.chr_ram_size() + header.chr_nvram_size()) {
+            (0, 0) => Memory::new(CHR_BANK_SIZE),
+            (0, size) => Memory::new(size),
+            (size, _) => read_section(reader, "CHR ROM", size)?,
         };
+        let mut prg_ram_size = header.prg_ram_size() + header.prg_nvram_size();
+        // the trainer needs ram at $7000-$71FF
+        if cartridge.trainer.is_some() {
+            prg_ram_size = prg_ram_size.max(8192);
+        }
+        let mirror_mode = header.mirror_mode();
         // get mapper id
-        let mapper_number = ((header.flag1 >> 4) & 0x0f) | (header.flag2 & 0xf0);
+        let mapper_number = header.mapper_number();
+        let submapper = header.submapper();
         cartridge.header = header;
 
         let mapper: Box<dyn Mapper> = match mapper_number {
-            0 => Box::new(NRom::new(prg, chr, mirror_mode)),
-            2 => Box::new(UxRom::new(prg, chr, mirror_mode)),
-            4 => Box::new(MMC3::new(prg, chr, mirror_mode, irq)),
-            _ => panic!("unsupported mapper {}", mapper_number),
+            0 => Box::new(NRom::new(prg, chr, mirror_mode, prg_ram_size)),
+            1 => {
+                let mmc1 = MMC1::new(prg, chr, mirror_mode, prg_ram_size);
+                // chr ram, 8K of prg ram and up to 256K prg rom is SNROM
+                let snrom = cartridge.header.chr_rom_size() == 0 && prg_ram_size == 8192 && cartridge.header.prg_rom_size() <= 0x40000;
+                match snrom {
+                    true => Box::new(mmc1.with_snrom()),
+                    false => Box::new(mmc1),
+                }
+            },
+            2 => Box::new(UxRom::new(prg, chr, mirror_mode, prg_ram_size)),
+            // nes 2.0 submapper 1 is the no bus conflict variant
+            3 => Box::new(CNRom::new(prg, chr, mirror_mode, prg_ram_size, submapper != 1)),
+            4 => {
+                // nes 2.0 submapper 4 is the MMC3A irq behavior
+                let revision = match submapper {
+                    4 => MMC3Revision::A,
+                    _ => MMC3Revision::B,
+                };
+                Box::new(MMC3::new(prg, chr, mirror_mode, prg_ram_size, irq).with_revision(revision))
+            },
+            5 => Box::new(MMC5::new(prg, chr, mirror_mode, prg_ram_size, irq)),
+            // bus conflicts only when submapper 2 says so, AOROM games break with them
+            7 => Box::new(AxRom::new(prg, chr, prg_ram_size, submapper == 2)),
+            9 => Box::new(MMC2::new(prg, chr, mirror_mode, prg_ram_size, false)),
+            10 => Box::new(MMC2::new(prg, chr, mirror_mode, prg_ram_size, true)),
+            11 => Box::new(ColorDreams::new(prg, chr, mirror_mode, prg_ram_size)),
+            19 => Box::new(Namco163::new(prg, chr, mirror_mode, prg_ram_size, irq)),
+            21 | 22 | 23 | 25 => {
+                // address bits on the chip's A0 and A1, both candidates are or'ed when
+                // the submapper doesn't say which board it is
+                let (a0, a1, vrc2) = match (mapper_number, submapper) {
+                    // VRC4a and VRC4c
+                    (21, 1) => (0x02, 0x04, false),
+                    (21, 2) => (0x40, 0x80, false),
+                    (21, _) => (0x42, 0x84, false),
+                    // VRC2a
+                    (22, _) => (0x02, 0x01, true),
+                    // VRC4f, VRC4e and VRC2b
+                    (23, 1) => (0x01, 0x02, false),
+                    (23, 2) => (0x04, 0x08, false),
+                    (23, 3) => (0x01, 0x02, true),
+                    (23, _) => (0x05, 0x0a, false),
+                    // VRC4b, VRC4d and VRC2c
+                    (25, 1) => (0x02, 0x01, false),
+                    (25, 2) => (0x08, 0x04, false),
+                    (25, 3) => (0x02, 0x01, true),
+                    _ => (0x0a, 0x05, false),
+                };
+                let vrc = VRC4::new(prg, chr, mirror_mode, prg_ram_size, irq).with_lines(a0, a1);
+                match vrc2 {
+                    true => Box::new(vrc.with_vrc2(mapper_number == 22)),
+                    false => Box::new(vrc),
+                }
+            },
+            24 => Box::new(VRC6::new(prg, chr, mirror_mode, prg_ram_size, false, irq)),
+            26 => Box::new(VRC6::new(prg, chr, mirror_mode, prg_ram_size, true, irq)),
+            66 => Box::new(GxRom::new(prg, chr, mirror_mode, prg_ram_size)),
+            69 => Box::new(FME7::new(prg, chr, mirror_mode, prg_ram_size, irq)),
+            85 => {
+                // VRC7b uses A3 and VRC7a A4 for the second register, both when unknown
+                let line = match submapper {
+                    1 => 0x08,
+                    2 => 0x10,
+                    _ => 0x18,
+                };
+                Box::new(VRC7::new(prg, chr, mirror_mode, prg_ram_size, line, irq))
+            },
+            118 => Box::new(MMC3::new(prg, chr, mirror_mode, prg_ram_size, irq).with_board(MMC3Board::TxSROM)),
+            119 => Box::new(MMC3::new(prg, chr, mirror_mode, prg_ram_size, irq).with_board(MMC3Board::TQROM)),
+            206 => Box::new(MMC3::new(prg, chr, mirror_mode, prg_ram_size, irq).with_board(MMC3Board::DxROM)),
+            _ => return Err(CartridgeError::UnsupportedMapper {
+                number: mapper_number,
+                name: mapper_name(mapper_number),
+            }),
         };
         cartridge.mapper = Some(mapper);
         Ok(cartridge)
     }
 
     // load cartridge from nes file
-    pub fn load(file: &str, irq: Signal) -> Result<Cartridge, Box<dyn Error>> {
+    pub fn load(file: &str, irq: Signal) -> Result<Cartridge, CartridgeError> {
         let mut file = File::open(file)?;
         let cartridge = Cartridge::read(&mut file, irq)?;
         Ok(cartridge)
     }
 
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
     pub fn to_mapper(self) -> Rc<RefCell<Box<dyn Mapper>>> {
-        Rc::new(RefCell::new(self.mapper.unwrap()))
+        let mut mapper = self.mapper.unwrap();
+        // the trainer is copied to $7000 as the cpu sees it, into the selected
+        // ram bank and only if the board has the ram enabled
+        if let Some(trainer) = &self.trainer {
+            for (i, val) in trainer.iter().enumerate() {
+                mapper.write_u8(0x7000 + i as u16, *val);
+            }
+        }
+        Rc::new(RefCell::new(mapper))
     }
 }
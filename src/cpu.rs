@@ -2,6 +2,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::fmt;
 use crate::ppu::PPU;
+use crate::apu::APU;
 use crate::board::{ Memory, Signal };
 use crate::controller::Controller;
 use crate::mapper::Mapper;
@@ -19,7 +20,14 @@ const STATUS_NEG     :u8 = 0x80;
 
 const STACK_BASE     :u16 = 0x0100;
 
-#[derive(Default, Debug)]
+// interrupt vectors
+const NMI_VECTOR     :u16 = 0xfffa;
+const IRQ_VECTOR     :u16 = 0xfffe;
+
+// or'ed into a by the unstable xaa and lxa
+const UNSTABLE_MAGIC :u8 = 0xee;
+
+#[derive(Default, Debug, Clone, Copy)]
 struct Registers {
     // accumulator
     acc: u8,
@@ -53,17 +61,16 @@ pub struct CPUBus {
 
     // 2000-2007
     ppu: Rc<RefCell<PPU>>,
-    // TODO: apu registers, 4000-401f
+    // 4000-4013, 4015, 4017 apu registers
+    apu: Rc<RefCell<APU>>,
     // 4016-4017 controller
     controller: Rc<RefCell<Controller>>,
-    apu: Option<Memory>,
-    // 4020-5fff 8K-20h
-    rom: Option<Memory>,
-    // 6000-7fff 8K
-    sram: Option<Memory>,
-    // 8000-bfff 16K
-    // c000-ffff 16K
+    // 4020-5fff expansion
+    // 6000-7fff prg ram
+    // 8000-ffff prg rom
     mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    // page written to $4014, picked up by the dma unit
+    oam_dma: Option<u8>,
 }
 
 
@@ -71,16 +78,15 @@ pub struct CPUBus {
 impl CPUBus {
 
     // new cpu bus
-    pub fn new(ppu: Rc<RefCell<PPU>>, mapper: Rc<RefCell<Box<dyn Mapper>>>, controller: Rc<RefCell<Controller>>) -> Self {
+    pub fn new(ppu: Rc<RefCell<PPU>>, apu: Rc<RefCell<APU>>, mapper: Rc<RefCell<Box<dyn Mapper>>>, controller: Rc<RefCell<Controller>>) -> Self {
         // internal ram
         Self {
             internal_ram: Some(Memory::new(8192)),
             ppu: ppu,
-            apu: Some(Memory::new(32)),
-            rom: Some(Memory::new(8159)),
-            sram: Some(Memory::new(8192)),
+            apu,
             mapper: mapper,
             controller: controller,
+            oam_dma: None,
         }
     }
 
@@ -100,39 +106,19 @@ impl CPUBus {
                 let addr = ((addr - 0x2000) & 0x07) + 0x2000;
                 self.ppu.borrow_mut().read_u8(addr)
             },
-            // apu registers
-            0x4000..=0x4013 => {
-                if let Some(mem) = &self.apu {
-                    mem[usize::from(addr-0x4000)]
-                } else {
-                    0
-                }
-            },
             // oam dma
             0x4014 => {
                 0
             },
+            // apu status
+            0x4015 => {
+                self.apu.borrow_mut().read_u8(addr)
+            },
             0x4016..=0x4017 => {
                 self.controller.borrow_mut().read_u8(addr)
             },
-            // cartridge rom
-            0x4020..=0x5fff => {
-                if let Some(mem) = &self.rom {
-                    mem[usize::from(addr-0x4020)]
-                } else {
-                    0
-                }
-            },
-            // cartridge sram
-            0x6000..=0x7fff => {
-                if let Some(mem) = &self.sram {
-                    mem[usize::from(addr-0x6000)]
-                } else {
-                    0
-                }
-            },
-            // cartridge program
-            0x8000..=0xffff => {
+            // cartridge space
+            0x4020..=0xffff => {
                 self.mapper.borrow_mut().read_u8(addr)
             },
             _ => 0,
@@ -160,39 +146,21 @@ impl CPUBus {
             0x2000..=0x3fff => {
                 let addr = ((addr - 0x2000) & 0x07) + 0x2000;
                 self.ppu.borrow_mut().write_u8(addr, data);
+                self.mapper.borrow_mut().ppu_register_write(addr, data);
             },
-            // oam dma
+            // oam dma, the cpu halts while the page is copied to $2004
             0x4014 => {
-                // oam dma will stale cpu for 513+ cycles
-                if let Some(mem) = &mut self.internal_ram {
-                    let start = (data as u16 * 0x100) as usize;
-                    let src =  &mem[start..start+256];
-                    self.ppu.borrow_mut().oam_dma(src);
-                }
+                self.oam_dma = Some(data);
             },
-            // apu registers
-            0x4000..=0x4013 => {
-                if let Some(mem) = &mut self.apu {
-                    mem[usize::from(addr-0x4000)] = data
-                }
+            // apu registers, 4017 is the frame counter
+            0x4000..=0x4013 | 0x4015 | 0x4017 => {
+                self.apu.borrow_mut().write_u8(addr, data);
             },
-            0x4016..=0x4017 => {
+            0x4016 => {
                 self.controller.borrow_mut().write_u8(addr, data);
             },
-            // cartridge rom
-            0x4020..=0x5fff => {
-                if let Some(mem) = &mut self.rom {
-                    mem[usize::from(addr-0x4020)] = data
-                }
-            },
-            // cartridge sram
-            0x6000..=0x7fff => {
-                if let Some(mem) = &mut self.sram {
-                    mem[usize::from(addr-0x6000)] = data
-                }
-            },
-            // cartridge program
-            0x8000..=0xffff => {
+            // cartridge space
+            0x4020..=0xffff => {
                 self.mapper.borrow_mut().write_u8(addr, data);
             },
             _ => (),
@@ -218,6 +186,70 @@ impl CPUBus {
 
 
 
+// how an instruction uses its operand address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    // read, write back the unmodified value, write the result
+    Modify,
+}
+
+
+// the bus access of a cpu cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BusAccess {
+    Read(u16),
+    Write(u16),
+}
+
+
+// dma unit. it halts the cpu on a read cycle and takes over the bus, reads
+// on get (even) cycles and writes on put (odd) cycles
+#[derive(Default)]
+struct Dma {
+    // the cpu is halted, it repeats the read it was stopped on afterwards
+    halted: bool,
+    // page of a running oam dma
+    oam_page: Option<u8>,
+    // bytes read from the page
+    oam_count: u16,
+    // byte read on a get cycle, written to $2004 on the next put cycle
+    oam_latch: Option<u8>,
+    // pending dmc sample fetch, the dummy cycles left before its get cycle
+    dmc: Option<u8>,
+}
+
+
+impl Dma {
+    fn active(&self) -> bool {
+        self.oam_page.is_some() || self.dmc.is_some()
+    }
+}
+
+
+// what a cpu tick reports back to the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuEvent {
+    // a jam opcode locked up the cpu, only reset recovers
+    Halted { opcode: u8, pc: u16 },
+    // brk executed
+    Break { pc: u16 },
+    // internal state the cpu can't continue from, halts the cpu
+    InvalidState { pc: u16, reason: &'static str },
+}
+
+impl fmt::Display for CpuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuEvent::Halted { opcode, pc } => write!(f, "cpu halted by opcode {:#04x} at {:#06x}", opcode, pc),
+            CpuEvent::Break { pc } => write!(f, "brk at {:#06x}", pc),
+            CpuEvent::InvalidState { pc, reason } => write!(f, "invalid cpu state at {:#06x}: {}", pc, reason),
+        }
+    }
+}
+
+
 // cpu
 pub struct CPU {
     // registers
@@ -225,114 +257,88 @@ pub struct CPU {
 
     // current opcode
     opcode: u8,
+    // cycle of the current instruction, 0 fetches the next opcode
+    step: u8,
+    // interrupt vector when the current instruction is an interrupt
+    interupt: Option<u16>,
     // is page crossing
     page_crossing: bool,
-    // timming table
-    instr_table: Vec<fn(&mut CPU) -> ()>,
-    // timming table
-    timing_table: Vec<u32>,
-    // timming table (cross page)
-    timing_table_cross: Vec<u32>,
     // cycles
     cycles: u64,
-    // cycles_delay
-    cycles_delay: u32,
+    // oam and dmc dma
+    dma: Dma,
+    // run a cycle without the bus to find its access
+    probing: bool,
+    probed: Option<BusAccess>,
     // tmp operand address
     op_addr: u16,
-    // immediate
-    is_immediate: bool,
+    // operand read by the addressing mode
+    op_data: u8,
     // bus
     bus: CPUBus,
     // nmi signal
     nmi: Signal,
     // irq signal
     irq: Signal,
+    // nmi edge seen and not yet serviced
+    need_nmi: bool,
+    // irq asserted while not masked
+    run_irq: bool,
+    // the same, sampled a cycle earlier for the penultimate cycle poll
+    prev_need_nmi: bool,
+    prev_run_irq: bool,
+    // locked up until reset
+    halted: bool,
+    // reported at the end of the tick
+    event: Option<CpuEvent>,
 }
 
 
 impl CPU {
     // new cpu
-    pub fn new(ppu: Rc<RefCell<PPU>>, mapper: Rc<RefCell<Box<dyn Mapper>>>, controller: Rc<RefCell<Controller>>, nmi: Signal, irq: Signal) -> Self {
+    pub fn new(ppu: Rc<RefCell<PPU>>, apu: Rc<RefCell<APU>>, mapper: Rc<RefCell<Box<dyn Mapper>>>, controller: Rc<RefCell<Controller>>, nmi: Signal, irq: Signal) -> Self {
 
-        let mut cpu = Self {
+        Self {
             regs: Registers::default(),
             opcode: 0,
+            step: 0,
+            interupt: None,
             cycles: 0,
-            cycles_delay: 0,
+            dma: Dma::default(),
+            probing: false,
+            probed: None,
             page_crossing: false,
-            instr_table: Vec::new(),
-            timing_table: Vec::new(),
-            timing_table_cross: Vec::new(),
             op_addr: 0,
-            is_immediate: false,
-            bus: CPUBus::new(ppu, mapper, controller),
+            op_data: 0,
+            bus: CPUBus::new(ppu, apu, mapper, controller),
             nmi: nmi,
             irq: irq,
-        };
-        cpu.init_tables();
-        cpu        
-    }
-
-
-    // init tables
-
-    fn init_tables(&mut self) {
-
-        // TODO: use instr table
-        self.instr_table = vec![
-        // 0 1 2 3 4 5 6 7 8 9 A B C D E F
-        // --------------------------------
-        ];
-        self.timing_table = vec![
-            7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
-            6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
-            6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
-            6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
-            2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
-            2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5,
-            2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4,
-            2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4,
-            2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
-            2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6,
-            2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7,
-        ];
-        self.timing_table_cross = vec![
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 1, 1, 1, 1, 1,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
-            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1, 0, 0,
-        ];
+            need_nmi: false,
+            run_irq: false,
+            prev_need_nmi: false,
+            prev_run_irq: false,
+            halted: false,
+            event: None,
+        }
     }
 
+
     // common ops
 
     fn mem_read_u8(&mut self, addr: u16) -> u8 {
+        if self.probing {
+            self.probed.get_or_insert(BusAccess::Read(addr));
+            return 0;
+        }
         self.bus.read_u8(addr)
     }
 
     fn mem_write_u8(&mut self, addr: u16, val: u8) {
-        self.bus.write_u8(addr, val);
-        // oam dma stale
-        if addr == 0x4014 {
-            self.cycles_delay += 513 + (self.cycles % 2) as u32;
+        if self.probing {
+            self.probed.get_or_insert(BusAccess::Write(addr));
+            return;
         }
+        self.bus.write_u8(addr, val);
     }
 
     fn push_u8(&mut self, val: u8) {
@@ -340,37 +346,31 @@ impl CPU {
         self.regs.sp = self.regs.sp.wrapping_sub(1);
     }
 
-    fn push_u16(&mut self, val: u16) {
-        let l: u8 = val as u8 & 0xff;
-        let h: u8 = (val >> 8) as u8 & 0xff;
-        self.push_u8(h);
-        self.push_u8(l);
-    }
-
     fn pop_u8(&mut self) -> u8 {
         self.regs.sp = self.regs.sp.wrapping_add(1);
         self.mem_read_u8(self.regs.sp as u16 + STACK_BASE)
     }
 
-    fn pop_u16(&mut self) -> u16 {
-        let l = self.pop_u8();
-        let h = self.pop_u8();
-        (h as u16) << 8 | l as u16
-    }
-
     fn fetch_u8(&mut self) -> u8 {
         let d = self.mem_read_u8(self.regs.pc);
         self.regs.pc = self.regs.pc.wrapping_add(1);
         d
     }
 
-    fn fetch_u16(&mut self) -> u16 {
-        let l = self.fetch_u8();
-        let h = self.fetch_u8();
-        (h as u16) << 8 | l as u16 
+    // instruction cycles
+
+    // the instruction is done, the next cycle fetches an opcode
+    fn finish(&mut self) {
+        self.step = 0;
+    }
+
+    fn invalid_cycle(&mut self) {
+        self.finish();
+        self.fault("instruction ran past its last cycle");
     }
 
-    // addressing mode
+    // addressing mode, each runs one cycle of the instruction and the
+    // operation on the last one
 
     fn handle_cross_page(&mut self, a: u16, b: u16) {
         if (a >> 8) & 0xff != (b >> 8) & 0xff {
@@ -380,89 +380,162 @@ impl CPU {
 
     fn addressing_none(&mut self) {
         self.op_addr = 0;
-        self.is_immediate = false;
         self.page_crossing = false;
     }
 
-    fn implied(&mut self) {}
+    // the cycles after the effective address is known
+    fn operate(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        match (access, step) {
+            (Access::Read, 0) => {
+                self.op_data = self.mem_read_u8(self.op_addr);
+                op(self);
+                self.finish();
+            },
+            (Access::Write, 0) => {
+                op(self);
+                self.finish();
+            },
+            (Access::Modify, 0) => self.op_data = self.mem_read_u8(self.op_addr),
+            (Access::Modify, 1) => self.mem_write_u8(self.op_addr, self.op_data),
+            (Access::Modify, 2) => {
+                op(self);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
+    }
 
-    fn accumulator(&mut self) {}
+    // accumulator mode too
+    fn implied(&mut self, step: u8, op: fn(&mut CPU)) {
+        match step {
+            1 => {
+                // dummy read of the next byte
+                self.mem_read_u8(self.regs.pc);
+                op(self);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
+    }
 
-    fn immediate(&mut self) {
-        self.is_immediate = true;
+    fn immediate(&mut self, step: u8, op: fn(&mut CPU)) {
+        match step {
+            1 => {
+                self.op_data = self.fetch_u8();
+                op(self);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
     }
 
-    fn zero_page(&mut self) {
-        self.op_addr = u16::from(self.fetch_u8() & 0xff);
+    fn zero_page(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        match step {
+            1 => self.op_addr = u16::from(self.fetch_u8()),
+            _ => self.operate(step - 2, access, op),
+        }
     }
 
-    fn zero_page_x(&mut self) {
-        // load operand
-        let d = self.fetch_u8();
-        self.op_addr = (d.wrapping_add(self.regs.x) & 0xFF).into();
+    fn zero_page_x(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        self.zero_page_indexed(step, access, op, self.regs.x);
     }
 
-    fn zero_page_y(&mut self) {
-        // load operand
-        let d = self.fetch_u8();
-        self.op_addr = (d.wrapping_add(self.regs.y) & 0xFF).into();
+    fn zero_page_y(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        self.zero_page_indexed(step, access, op, self.regs.y);
     }
 
-    fn absolute(&mut self) {
-        self.op_addr = self.fetch_u16();
+    fn zero_page_indexed(&mut self, step: u8, access: Access, op: fn(&mut CPU), index: u8) {
+        match step {
+            1 => self.op_addr = u16::from(self.fetch_u8()),
+            2 => {
+                // dummy read while the index is added, wraps in the zero page
+                self.mem_read_u8(self.op_addr);
+                self.op_addr = u16::from((self.op_addr as u8).wrapping_add(index));
+            },
+            _ => self.operate(step - 3, access, op),
+        }
     }
 
-    fn absolute_x(&mut self) {
-        let d = self.fetch_u16();
-        self.op_addr = d.wrapping_add(self.regs.x as u16);
-        self.handle_cross_page(self.op_addr, d);
+    fn absolute(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        match step {
+            1 => self.op_addr = u16::from(self.fetch_u8()),
+            2 => self.op_addr |= u16::from(self.fetch_u8()) << 8,
+            _ => self.operate(step - 3, access, op),
+        }
     }
 
-    fn absolute_y(&mut self) {
-        let d = self.fetch_u16();
-        self.op_addr = d.wrapping_add(self.regs.y as u16);
-        self.handle_cross_page(self.op_addr, d);
+    fn absolute_x(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        self.absolute_indexed(step, access, op, self.regs.x);
     }
 
-    fn indirect_x(&mut self) {
-        let d = self.fetch_u8();
-        let l = self.mem_read_u8(d.wrapping_add(self.regs.x).into()) as u16;
-        let h = self.mem_read_u8(d.wrapping_add(self.regs.x).wrapping_add(1).into()) as u16;
-        self.op_addr = (h << 8) | l;
+    fn absolute_y(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        self.absolute_indexed(step, access, op, self.regs.y);
     }
 
-    fn indirect_y(&mut self) {
-        let d = self.fetch_u8();
-        let l = self.mem_read_u8(d.into()) as u16;
-        let h = self.mem_read_u8(d.wrapping_add(1).into()) as u16;
-        self.op_addr = ((h << 8) | l).wrapping_add(u16::from(self.regs.y));
-        self.handle_cross_page(self.op_addr, (h << 8) | l);
+    fn absolute_indexed(&mut self, step: u8, access: Access, op: fn(&mut CPU), index: u8) {
+        match step {
+            1 => self.op_addr = u16::from(self.fetch_u8()),
+            2 => {
+                let base = self.op_addr | u16::from(self.fetch_u8()) << 8;
+                self.add_index(base, index);
+            },
+            3 => self.fix_page(access, op),
+            _ => self.operate(step - 4, access, op),
+        }
     }
 
-    fn relative(&mut self) {
-        let d = self.fetch_u8();
-        let rel: u16 = (d as i8) as u16;
-        let base = self.regs.pc;
-        self.op_addr = base.wrapping_add(rel);
-        self.handle_cross_page(self.op_addr, base);
+    fn indirect_x(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        match step {
+            1 => self.op_addr = u16::from(self.fetch_u8()),
+            2 => {
+                self.mem_read_u8(self.op_addr);
+                self.op_addr = u16::from((self.op_addr as u8).wrapping_add(self.regs.x));
+            },
+            3 => self.op_data = self.mem_read_u8(self.op_addr),
+            4 => {
+                let h = self.mem_read_u8(u16::from((self.op_addr as u8).wrapping_add(1)));
+                self.op_addr = u16::from(h) << 8 | u16::from(self.op_data);
+            },
+            _ => self.operate(step - 5, access, op),
+        }
+    }
+
+    fn indirect_y(&mut self, step: u8, access: Access, op: fn(&mut CPU)) {
+        match step {
+            1 => self.op_addr = u16::from(self.fetch_u8()),
+            2 => self.op_data = self.mem_read_u8(self.op_addr),
+            3 => {
+                let h = self.mem_read_u8(u16::from((self.op_addr as u8).wrapping_add(1)));
+                self.add_index(u16::from(h) << 8 | u16::from(self.op_data), self.regs.y);
+            },
+            4 => self.fix_page(access, op),
+            _ => self.operate(step - 5, access, op),
+        }
     }
 
-    fn indirect(&mut self) {
-        self.op_addr = self.fetch_u16();
-        // 6502 indirect JMP bug
-        let l = self.mem_read_u8(self.op_addr) as u16;
-        let addr_high =  (self.op_addr.wrapping_add(1) & 0x00ff) | (self.op_addr & 0xff00);
-        let h = self.mem_read_u8(addr_high) as u16;
-        self.op_addr = (h << 8) | l;
+    fn add_index(&mut self, base: u16, index: u8) {
+        self.op_addr = base.wrapping_add(u16::from(index));
+        self.handle_cross_page(self.op_addr, base);
     }
 
-    fn op_val(&mut self) -> u8 {
-        match self.is_immediate {
-            true => self.fetch_u8(),
-            false => self.mem_read_u8(self.op_addr),
+    // the first read after indexing misses the carry into the high byte.
+    // reads are done when no page was crossed, writes always take the cycle
+    fn fix_page(&mut self, access: Access, op: fn(&mut CPU)) {
+        match (access, self.page_crossing) {
+            (Access::Read, false) => self.operate(0, access, op),
+            (_, false) => {
+                self.mem_read_u8(self.op_addr);
+            },
+            (_, true) => {
+                self.mem_read_u8(self.op_addr.wrapping_sub(0x100));
+            },
         }
     }
 
+    fn op_val(&self) -> u8 {
+        self.op_data
+    }
+
     // flages
     fn flag_nz(&mut self, val: u8) {
         match val {
@@ -536,6 +609,35 @@ impl CPU {
 
     // stack instructions
 
+    fn push(&mut self, step: u8, op: fn(&mut CPU)) {
+        match step {
+            1 => {
+                self.mem_read_u8(self.regs.pc);
+            },
+            2 => {
+                op(self);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
+    }
+
+    fn pull(&mut self, step: u8, op: fn(&mut CPU)) {
+        match step {
+            1 => {
+                self.mem_read_u8(self.regs.pc);
+            },
+            2 => {
+                self.mem_read_u8(self.regs.sp as u16 + STACK_BASE);
+            },
+            3 => {
+                op(self);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
+    }
+
     fn pha(&mut self) {
         self.push_u8(self.regs.acc);
     }
@@ -594,24 +696,18 @@ impl CPU {
 
     fn adc(&mut self) {
         let oprand = self.op_val();
-        let result: u16 = self.regs.acc as u16 + oprand as u16 + (self.regs.status & STATUS_CARRAY) as u16;
-        match result & 0xFF00 {
-            0 => self.regs.status &= !STATUS_CARRAY,
-            _ => self.regs.status |= STATUS_CARRAY,
-        }
-        let result = (result & 0xff) as u8;
-        match (self.regs.acc ^ result) & (oprand ^ result) & 0x80 {
-            0 => self.regs.status &= !STATUS_OVERFLOW,
-            _ => self.regs.status |= STATUS_OVERFLOW,
-        }
-        self.regs.acc = result;
-        self.flag_nz(self.regs.acc);
+        self.add_with_carry(oprand);
     }
 
     fn sbc(&mut self) {
         let oprand = !self.op_val();
+        self.add_with_carry(oprand);
+    }
+
+    // sbc adds the complement of the operand
+    fn add_with_carry(&mut self, oprand: u8) {
         let result: u16 = self.regs.acc as u16 + oprand as u16 + (self.regs.status & STATUS_CARRAY) as u16;
-        match result & 0xff00 {
+        match result & 0xFF00 {
             0 => self.regs.status &= !STATUS_CARRAY,
             _ => self.regs.status |= STATUS_CARRAY,
         }
@@ -759,28 +855,22 @@ impl CPU {
 
     // comparisions
     fn cmp(&mut self) {
-        let oprand = !self.op_val();
-        let result = self.regs.acc as u16 + oprand as u16 + 1;
-        match result & 0xff00 {
-            0 => self.regs.status &= !STATUS_CARRAY,
-            _ => self.regs.status |= STATUS_CARRAY,
-        }
-        self.flag_nz(result as u8);
+        let oprand = self.op_val();
+        self.compare(self.regs.acc, oprand);
     }
 
     fn cpx(&mut self) {
-        let oprand = !self.op_val();
-        let result = self.regs.x as u16 + oprand as u16 + 1;
-        match result & 0xff00 {
-            0 => self.regs.status &= !STATUS_CARRAY,
-            _ => self.regs.status |= STATUS_CARRAY,
-        }
-        self.flag_nz(result as u8);
+        let oprand = self.op_val();
+        self.compare(self.regs.x, oprand);
     }
 
     fn cpy(&mut self) {
-        let oprand = !self.op_val();
-        let result = self.regs.y as u16 + oprand as u16 + 1;
+        let oprand = self.op_val();
+        self.compare(self.regs.y, oprand);
+    }
+
+    fn compare(&mut self, reg: u8, oprand: u8) {
+        let result = reg as u16 + !oprand as u16 + 1;
         match result & 0xff00 {
             0 => self.regs.status &= !STATUS_CARRAY,
             _ => self.regs.status |= STATUS_CARRAY,
@@ -790,137 +880,201 @@ impl CPU {
 
     // branch
 
-    fn bcc(&mut self) {
-        match self.regs.status & STATUS_CARRAY {
-            0 => {
+    fn branch(&mut self, step: u8, cond: fn(&CPU) -> bool) {
+        match step {
+            1 => {
+                let rel = (self.fetch_u8() as i8) as u16;
+                self.op_addr = self.regs.pc.wrapping_add(rel);
+                if !cond(self) {
+                    self.finish();
+                }
+            },
+            2 => {
+                // a taken branch without a page crossing doesn't poll on this
+                // cycle, an irq raised by now waits for the next instruction
+                if self.run_irq && !self.prev_run_irq {
+                    self.run_irq = false;
+                }
+                // the low byte is added first
+                self.mem_read_u8(self.regs.pc);
+                self.handle_cross_page(self.op_addr, self.regs.pc);
+                self.regs.pc = (self.regs.pc & 0xff00) | (self.op_addr & 0x00ff);
+                if !self.page_crossing {
+                    self.finish();
+                }
+            },
+            3 => {
+                self.mem_read_u8(self.regs.pc);
                 self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
+                self.finish();
             },
-            _ => (),
+            _ => self.invalid_cycle(),
         }
     }
 
-    fn bcs(&mut self) {
-        match self.regs.status & STATUS_CARRAY {
-            0 => (),
-            _ => {
-                self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
-            }
-        }
+    fn bcc(&self) -> bool {
+        self.regs.status & STATUS_CARRAY == 0
     }
 
-    fn beq(&mut self) {
-        match self.regs.status & STATUS_ZERO {
-            0 => (),
-            _ => {
-                self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
-            }
-        }
+    fn bcs(&self) -> bool {
+        self.regs.status & STATUS_CARRAY != 0
     }
 
-    fn bmi(&mut self) {
-        match self.regs.status & STATUS_NEG {
-            0 => (),
-            _ => {
-                self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
-            }
-        }
+    fn beq(&self) -> bool {
+        self.regs.status & STATUS_ZERO != 0
     }
 
-    fn bne(&mut self) {
-        match self.regs.status & STATUS_ZERO {
-            0 => {
-                self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
-            },
-            _ => (),
-        }
+    fn bmi(&self) -> bool {
+        self.regs.status & STATUS_NEG != 0
     }
 
-    fn bpl(&mut self) {
-        match self.regs.status & STATUS_NEG {
-            0 => {
-                self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
-            },
-            _ => (),
-        }
+    fn bne(&self) -> bool {
+        self.regs.status & STATUS_ZERO == 0
     }
 
-    fn bvc(&mut self) {
-        match self.regs.status & STATUS_OVERFLOW {
-            0 => {
-                self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
-            },
-            _ => (),
-        }
+    fn bpl(&self) -> bool {
+        self.regs.status & STATUS_NEG == 0
     }
 
-    fn bvs(&mut self) {
-        match self.regs.status & STATUS_OVERFLOW {
-            0 => (),
-            _ => {
-                self.regs.pc = self.op_addr;
-                self.cycles_delay += 1;
-            },
-        }
+    fn bvc(&self) -> bool {
+        self.regs.status & STATUS_OVERFLOW == 0
+    }
+
+    fn bvs(&self) -> bool {
+        self.regs.status & STATUS_OVERFLOW != 0
     }
 
     // jumps & subroutines
 
-    fn jmp(&mut self) {
-        self.regs.pc = self.op_addr;
+    fn jmp(&mut self, step: u8) {
+        match step {
+            1 => self.op_data = self.fetch_u8(),
+            2 => {
+                let h = self.fetch_u8();
+                self.regs.pc = u16::from(h) << 8 | u16::from(self.op_data);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
     }
 
-    fn jsr(&mut self) {
-        self.push_u16(self.regs.pc.wrapping_sub(1));
-        self.regs.pc = self.op_addr;
+    fn jmp_indirect(&mut self, step: u8) {
+        match step {
+            1 => self.op_addr = u16::from(self.fetch_u8()),
+            2 => self.op_addr |= u16::from(self.fetch_u8()) << 8,
+            3 => self.op_data = self.mem_read_u8(self.op_addr),
+            4 => {
+                // 6502 indirect JMP bug, the high byte comes from the same page
+                let addr_high = (self.op_addr.wrapping_add(1) & 0x00ff) | (self.op_addr & 0xff00);
+                let h = self.mem_read_u8(addr_high);
+                self.regs.pc = u16::from(h) << 8 | u16::from(self.op_data);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
     }
 
-    fn rts(&mut self) {
-        self.regs.pc = self.pop_u16().wrapping_add(1);
+    fn jsr(&mut self, step: u8) {
+        match step {
+            1 => self.op_data = self.fetch_u8(),
+            2 => {
+                self.mem_read_u8(self.regs.sp as u16 + STACK_BASE);
+            },
+            // the pushed address points at the high byte of the operand
+            3 => self.push_u8((self.regs.pc >> 8) as u8),
+            4 => self.push_u8(self.regs.pc as u8),
+            5 => {
+                let h = self.mem_read_u8(self.regs.pc);
+                self.regs.pc = u16::from(h) << 8 | u16::from(self.op_data);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
     }
 
-    // interupts
-
-    fn nmi(&mut self) {
-        // set B flag
-        let status = self.regs.status | STATUS_B1;
-        self.push_u16(self.regs.pc);
-        self.push_u8(status);
-        self.regs.pc = self.bus.read_u16(0xfffa);
-        self.regs.status |= STATUS_INTERUPT;
-        self.cycles_delay += 7;
+    fn rts(&mut self, step: u8) {
+        match step {
+            1 => {
+                self.mem_read_u8(self.regs.pc);
+            },
+            2 => {
+                self.mem_read_u8(self.regs.sp as u16 + STACK_BASE);
+            },
+            3 => self.op_data = self.pop_u8(),
+            4 => {
+                let h = self.pop_u8();
+                self.regs.pc = u16::from(h) << 8 | u16::from(self.op_data);
+            },
+            5 => {
+                self.fetch_u8();
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
     }
 
-    fn irq(&mut self) {
-        // set B flag
-        let status = self.regs.status | STATUS_B1;
-        self.push_u16(self.regs.pc);
-        self.push_u8(status);
-        self.regs.pc = self.bus.read_u16(0xfffe);
-        self.regs.status |= STATUS_INTERUPT;
-        self.cycles_delay += 7;
-    }
+    // interupts
 
-    fn brk(&mut self) {
-        // read next instruction byte (and throw it away)
-        self.fetch_u8();
-        // set B flag
-        let status = self.regs.status | STATUS_B1 | STATUS_B2;
-        self.push_u16(self.regs.pc);
-        self.push_u8(status);
-        self.regs.status |= STATUS_INTERUPT;
-        self.regs.pc = self.bus.read_u16(0xfffe);
+    // brk, and nmi and irq which run brk in place of the fetched opcode
+    fn brk(&mut self, step: u8) {
+        match step {
+            1 => {
+                // brk skips the next byte, interrupts return to the dropped opcode
+                self.mem_read_u8(self.regs.pc);
+                if self.interupt.is_none() {
+                    self.event = Some(CpuEvent::Break { pc: self.regs.pc.wrapping_sub(1) });
+                    self.regs.pc = self.regs.pc.wrapping_add(1);
+                }
+            },
+            2 => self.push_u8((self.regs.pc >> 8) as u8),
+            3 => self.push_u8(self.regs.pc as u8),
+            4 => {
+                // set B flag
+                let status = match self.interupt {
+                    Some(_) => self.regs.status | STATUS_B1,
+                    None => self.regs.status | STATUS_B1 | STATUS_B2,
+                };
+                self.push_u8(status);
+                // an nmi seen by now hijacks the vector, brk keeps its b flag
+                self.op_addr = match self.need_nmi {
+                    true => {
+                        self.need_nmi = false;
+                        NMI_VECTOR
+                    },
+                    false => self.interupt.unwrap_or(IRQ_VECTOR),
+                };
+            },
+            5 => {
+                self.op_data = self.mem_read_u8(self.op_addr);
+                self.regs.status |= STATUS_INTERUPT;
+            },
+            6 => {
+                let h = self.mem_read_u8(self.op_addr.wrapping_add(1));
+                self.regs.pc = u16::from(h) << 8 | u16::from(self.op_data);
+                self.interupt = None;
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
     }
 
-    fn rti(&mut self) {
-        self.regs.status = self.pop_u8() & !STATUS_B1 & !STATUS_B2;
-        self.regs.pc = self.pop_u16();
+    fn rti(&mut self, step: u8) {
+        match step {
+            1 => {
+                self.mem_read_u8(self.regs.pc);
+            },
+            2 => {
+                self.mem_read_u8(self.regs.sp as u16 + STACK_BASE);
+            },
+            3 => self.regs.status = self.pop_u8() & !STATUS_B1 & !STATUS_B2,
+            4 => self.op_data = self.pop_u8(),
+            5 => {
+                let h = self.pop_u8();
+                self.regs.pc = u16::from(h) << 8 | u16::from(self.op_data);
+                self.finish();
+            },
+            _ => self.invalid_cycle(),
+        }
     }
 
     // others
@@ -943,37 +1097,211 @@ impl CPU {
 
     }
 
-    fn poll_interupt(&self, sig: &Signal) -> bool {
-    
-        let mut sig = sig.borrow_mut();
-        match *sig {
-            0 => false,
-            _ => { 
-                *sig = 0;
-                true
-            },
+    // unofficial instructions
+
+    fn set_carry(&mut self, carry: bool) {
+        match carry {
+            true => self.regs.status |= STATUS_CARRAY,
+            false => self.regs.status &= !STATUS_CARRAY,
         }
     }
 
-    fn handle_interupt(&mut self) -> bool {
-        // check nmi irq
-        let nmi = self.poll_interupt(&self.nmi);
-        let irq = self.poll_interupt(&self.irq);
-        let interupt_disabled = self.regs.status & STATUS_INTERUPT != 0;
+    // asl + ora
+    fn slo(&mut self) {
+        let oprand = self.op_val();
+        self.set_carry(oprand & 0x80 != 0);
+        let result = oprand << 1;
+        self.mem_write_u8(self.op_addr, result);
+        self.regs.acc |= result;
+        self.flag_nz(self.regs.acc);
+    }
 
-        match (nmi, irq) {
-            (true, _) => {
-                self.nmi();
-                true
-            },
-            (false, true) if !interupt_disabled => {
-                self.irq();
-                true
-            },
-            _ => false
+    // rol + and
+    fn rla(&mut self) {
+        let oprand = self.op_val();
+        let result = oprand << 1 | (self.regs.status & STATUS_CARRAY);
+        self.set_carry(oprand & 0x80 != 0);
+        self.mem_write_u8(self.op_addr, result);
+        self.regs.acc &= result;
+        self.flag_nz(self.regs.acc);
+    }
+
+    // lsr + eor
+    fn sre(&mut self) {
+        let oprand = self.op_val();
+        self.set_carry(oprand & 0x01 != 0);
+        let result = oprand >> 1;
+        self.mem_write_u8(self.op_addr, result);
+        self.regs.acc ^= result;
+        self.flag_nz(self.regs.acc);
+    }
+
+    // ror + adc
+    fn rra(&mut self) {
+        let oprand = self.op_val();
+        let result = oprand >> 1 | (self.regs.status & STATUS_CARRAY) << 7;
+        self.set_carry(oprand & 0x01 != 0);
+        self.mem_write_u8(self.op_addr, result);
+        self.add_with_carry(result);
+    }
+
+    // store a & x
+    fn sax(&mut self) {
+        self.mem_write_u8(self.op_addr, self.regs.acc & self.regs.x);
+    }
+
+    // lda + ldx
+    fn lax(&mut self) {
+        self.regs.acc = self.op_val();
+        self.regs.x = self.regs.acc;
+        self.flag_nz(self.regs.acc);
+    }
+
+    // dec + cmp
+    fn dcp(&mut self) {
+        let result = self.op_val().wrapping_sub(1);
+        self.mem_write_u8(self.op_addr, result);
+        self.compare(self.regs.acc, result);
+    }
+
+    // inc + sbc
+    fn isc(&mut self) {
+        let result = self.op_val().wrapping_add(1);
+        self.mem_write_u8(self.op_addr, result);
+        self.add_with_carry(!result);
+    }
+
+    // and, carry from bit 7
+    fn anc(&mut self) {
+        self.regs.acc &= self.op_val();
+        self.flag_nz(self.regs.acc);
+        self.set_carry(self.regs.acc & 0x80 != 0);
+    }
+
+    // and + lsr a
+    fn alr(&mut self) {
+        let val = self.regs.acc & self.op_val();
+        self.set_carry(val & 0x01 != 0);
+        self.regs.acc = val >> 1;
+        self.flag_nz(self.regs.acc);
+    }
+
+    // and + ror a, carry from bit 6 and overflow from bit 6 ^ bit 5
+    fn arr(&mut self) {
+        let val = self.regs.acc & self.op_val();
+        self.regs.acc = val >> 1 | (self.regs.status & STATUS_CARRAY) << 7;
+        self.flag_nz(self.regs.acc);
+        self.set_carry(self.regs.acc & 0x40 != 0);
+        match (self.regs.acc ^ (self.regs.acc << 1)) & 0x40 {
+            0 => self.regs.status &= !STATUS_OVERFLOW,
+            _ => self.regs.status |= STATUS_OVERFLOW,
         }
     }
 
+    // x = (a & x) - operand, without borrow
+    fn axs(&mut self) {
+        let oprand = self.op_val();
+        let val = self.regs.acc & self.regs.x;
+        self.compare(val, oprand);
+        self.regs.x = val.wrapping_sub(oprand);
+    }
+
+    // unstable, the magic constant varies between chips
+    fn xaa(&mut self) {
+        self.regs.acc = (self.regs.acc | UNSTABLE_MAGIC) & self.regs.x & self.op_val();
+        self.flag_nz(self.regs.acc);
+    }
+
+    // unstable lax immediate
+    fn lxa(&mut self) {
+        self.regs.acc = (self.regs.acc | UNSTABLE_MAGIC) & self.op_val();
+        self.regs.x = self.regs.acc;
+        self.flag_nz(self.regs.acc);
+    }
+
+    // a, x and sp = operand & sp
+    fn las(&mut self) {
+        let val = self.op_val() & self.regs.sp;
+        self.regs.acc = val;
+        self.regs.x = val;
+        self.regs.sp = val;
+        self.flag_nz(val);
+    }
+
+    // stores of a register and the high address byte + 1, a page crossing
+    // indexed store also replaces the high address byte with the value
+    fn unstable_store(&mut self, val: u8, index: u8) {
+        let base = self.op_addr.wrapping_sub(index as u16);
+        let result = val & ((base >> 8) as u8).wrapping_add(1);
+        let addr = match self.page_crossing {
+            true => (result as u16) << 8 | (self.op_addr & 0x00ff),
+            false => self.op_addr,
+        };
+        self.mem_write_u8(addr, result);
+    }
+
+    fn sha(&mut self) {
+        self.unstable_store(self.regs.acc & self.regs.x, self.regs.y);
+    }
+
+    fn shx(&mut self) {
+        self.unstable_store(self.regs.x, self.regs.y);
+    }
+
+    fn shy(&mut self) {
+        self.unstable_store(self.regs.y, self.regs.x);
+    }
+
+    fn tas(&mut self) {
+        self.regs.sp = self.regs.acc & self.regs.x;
+        self.unstable_store(self.regs.sp, self.regs.y);
+    }
+
+    // the cpu locks up on the opcode until reset
+    fn jam(&mut self) {
+        self.regs.pc = self.regs.pc.wrapping_sub(1);
+        self.halted = true;
+        self.event = Some(CpuEvent::Halted { opcode: self.opcode, pc: self.regs.pc });
+    }
+
+    fn fault(&mut self, reason: &'static str) {
+        self.halted = true;
+        self.event = Some(CpuEvent::InvalidState { pc: self.regs.pc, reason });
+    }
+
+    // sample the interrupt lines at the end of every cycle
+    fn sample_interupts(&mut self) {
+        self.prev_need_nmi = self.need_nmi;
+        self.prev_run_irq = self.run_irq;
+        // the ppu raises nmi on an edge, it stays pending until serviced
+        let mut nmi = self.nmi.borrow_mut();
+        if *nmi != 0 {
+            *nmi = 0;
+            self.need_nmi = true;
+        }
+        // irq is a level, masked by the i flag
+        self.run_irq = *self.irq.borrow() != 0 && self.regs.status & STATUS_INTERUPT == 0;
+    }
+
+    // the vector of an interrupt polled on the penultimate cycle of the last
+    // instruction. cli, sei and plp change the i flag on their last cycle so
+    // the change takes effect one instruction later
+    fn handle_interupt(&self) -> Option<u16> {
+        match (self.prev_need_nmi, self.prev_run_irq) {
+            (true, _) => Some(NMI_VECTOR),
+            (false, true) => Some(IRQ_VECTOR),
+            _ => None,
+        }
+    }
+
+    fn clear_interupts(&mut self) {
+        self.interupt = None;
+        self.need_nmi = false;
+        self.run_irq = false;
+        self.prev_need_nmi = false;
+        self.prev_run_irq = false;
+    }
+
     // status
     pub fn power_up(&mut self) {
         self.regs.acc = 0;
@@ -982,6 +1310,9 @@ impl CPU {
         self.regs.status |= STATUS_INTERUPT;
         self.regs.sp = 0xfd;
         self.regs.pc = self.bus.read_u16(0xfffc);
+        self.step = 0;
+        self.clear_interupts();
+        self.halted = false;
     }
 
     pub fn reset(&mut self) {
@@ -989,215 +1320,405 @@ impl CPU {
         self.regs.sp = self.regs.sp.wrapping_sub(3);
         self.regs.status |= STATUS_INTERUPT | STATUS_B1 | STATUS_B2;
         self.regs.pc = self.bus.read_u16(0xfffc);
+        self.step = 0;
+        self.clear_interupts();
+        self.halted = false;
+    }
+
+    pub fn halted(&self) -> bool {
+        self.halted
     }
 
     pub fn load_data(&mut self, addr: u16, data: &[u8]) {    
         self.bus.load_data(addr, data)
     }
 
-    // step simulation
-    pub fn tick(&mut self) -> u32 {
-
-        self.cycles = self.cycles.wrapping_add(1);
-
-        if self.cycles_delay == 0 {
-
-            if !self.handle_interupt() {
-                // load next opcode
-                self.opcode = self.fetch_u8();
+    // run one cycle of the current instruction
+    fn run_cycle(&mut self) {
+        let step = self.step;
+        self.step += 1;
+        match step {
+            0 => {
                 // clear addressing mode
                 self.addressing_none();
-                // debug
-                match self.opcode { 
-                    0x00 => { self.implied();       self.brk();      },
-                    0x01 => { self.indirect_x();    self.ora();      },
-                    0x04 => { self.zero_page();     self.nop();      },
-                    0x05 => { self.zero_page();     self.ora();      },
-                    0x06 => { self.zero_page();     self.asl();      },
-                    0x08 => { self.implied();       self.php();      },
-                    0x09 => { self.immediate();     self.ora();      },
-                    0x0A => { self.accumulator();   self.asla();     },
-                    0x0C => { self.absolute();      self.nop();      },
-                    0x0D => { self.absolute();      self.ora();      },
-                    0x0E => { self.absolute();      self.asl();      },
-                    0x10 => { self.relative();      self.bpl();      },
-                    0x11 => { self.indirect_y();    self.ora();      },
-                    0x14 => { self.zero_page_x();   self.nop();      },
-                    0x15 => { self.zero_page_x();   self.ora();      },
-                    0x16 => { self.zero_page_x();   self.asl();      },
-                    0x18 => { self.implied();       self.clc();      },
-                    0x19 => { self.absolute_y();    self.ora();      },
-                    0x1A => { self.implied();       self.nop();      },
-                    0x1C => { self.absolute_x();    self.nop();      },
-                    0x1D => { self.absolute_x();    self.ora();      },
-                    0x1E => { self.absolute_x();    self.asl();      },
-                    0x20 => { self.absolute();      self.jsr();      },
-                    0x21 => { self.indirect_x();    self.and();      },
-                    0x24 => { self.zero_page();     self.bit();      },
-                    0x25 => { self.zero_page();     self.and();      },
-                    0x26 => { self.zero_page();     self.rol();      },
-                    0x28 => { self.implied();       self.plp();      },
-                    0x29 => { self.immediate();     self.and();      },
-                    0x2A => { self.accumulator();   self.rola();     },
-                    0x2C => { self.absolute();      self.bit();      },
-                    0x2D => { self.absolute();      self.and();      },
-                    0x2E => { self.absolute();      self.rol();      },
-                    0x30 => { self.relative();      self.bmi();      },
-                    0x31 => { self.indirect_y();    self.and();      },
-                    0x34 => { self.zero_page_x();   self.nop();      },
-                    0x35 => { self.zero_page_x();   self.and();      },
-                    0x36 => { self.zero_page_x();   self.rol();      },
-                    0x38 => { self.implied();       self.sec();      },
-                    0x39 => { self.absolute_y();    self.and();      },
-                    0x3A => { self.implied();       self.nop();      },
-                    0x3C => { self.absolute_x();    self.nop();      },
-                    0x3D => { self.absolute_x();    self.and();      },
-                    0x3E => { self.absolute_x();    self.rol();      },
-                    0x40 => { self.implied();       self.rti();      },
-                    0x41 => { self.indirect_x();    self.eor();      },
-                    0x44 => { self.zero_page();     self.nop();      },
-                    0x45 => { self.zero_page();     self.eor();      },
-                    0x46 => { self.zero_page();     self.lsr();      },
-                    0x48 => { self.implied();       self.pha();      },
-                    0x49 => { self.immediate();     self.eor();      },
-                    0x4A => { self.accumulator();   self.lsra();     },
-                    0x4C => { self.absolute();      self.jmp();      },
-                    0x4D => { self.absolute();      self.eor();      },
-                    0x4E => { self.absolute();      self.lsr();      },
-                    0x50 => { self.relative();      self.bvc();      },
-                    0x51 => { self.indirect_y();    self.eor();      },
-                    0x54 => { self.zero_page_x();   self.nop();      },
-                    0x55 => { self.zero_page_x();   self.eor();      },
-                    0x56 => { self.zero_page_x();   self.lsr();      },
-                    0x58 => { self.implied();       self.cli();      },
-                    0x59 => { self.absolute_y();    self.eor();      },
-                    0x5A => { self.implied();       self.nop();      },
-                    0x5C => { self.absolute_x();    self.nop();      },
-                    0x5D => { self.absolute_x();    self.eor();      },
-                    0x5E => { self.absolute_x();    self.lsr();      },
-                    0x60 => { self.implied();       self.rts();      },
-                    0x61 => { self.indirect_x();    self.adc();      },
-                    0x64 => { self.zero_page();     self.nop();      },
-                    0x65 => { self.zero_page();     self.adc();      },
-                    0x66 => { self.zero_page();     self.ror();      },
-                    0x68 => { self.implied();       self.pla();      },
-                    0x69 => { self.immediate();     self.adc();      },
-                    0x6A => { self.accumulator();   self.rora();     },
-                    0x6C => { self.indirect();      self.jmp();      },
-                    0x6D => { self.absolute();      self.adc();      },
-                    0x6E => { self.absolute();      self.ror();      },
-                    0x70 => { self.relative();      self.bvs();      },
-                    0x71 => { self.indirect_y();    self.adc();      },
-                    0x74 => { self.zero_page();     self.nop();      },
-                    0x75 => { self.zero_page_x();   self.adc();      },
-                    0x76 => { self.zero_page_x();   self.ror();      },
-                    0x78 => { self.implied();       self.sei();      },
-                    0x79 => { self.absolute_y();    self.adc();      },
-                    0x7A => { self.implied();       self.nop();      },
-                    0x7C => { self.absolute_x();    self.nop();      },
-                    0x7D => { self.absolute_x();    self.adc();      },
-                    0x7E => { self.absolute_x();    self.ror();      },
-                    0x80 => { self.immediate();     self.nop();      },
-                    0x81 => { self.indirect_x();    self.sta();      },
-                    0x84 => { self.zero_page();     self.sty();      },
-                    0x85 => { self.zero_page();     self.sta();      },
-                    0x86 => { self.zero_page();     self.stx();      },
-                    0x88 => { self.implied();       self.dey();      },
-                    0x8A => { self.implied();       self.txa();      },
-                    0x8C => { self.absolute();      self.sty();      },
-                    0x8D => { self.absolute();      self.sta();      },
-                    0x8E => { self.absolute();      self.stx();      },
-                    0x90 => { self.relative();      self.bcc();      },
-                    0x91 => { self.indirect_y();    self.sta();      },
-                    0x94 => { self.zero_page_x();   self.sty();      },
-                    0x95 => { self.zero_page_x();   self.sta();      },
-                    0x96 => { self.zero_page_y();   self.stx();      },
-                    0x98 => { self.implied();       self.tya();      },
-                    0x99 => { self.absolute_y();    self.sta();      },
-                    0x9A => { self.implied();       self.txs();      },
-                    0x9D => { self.absolute_x();    self.sta();      },
-                    0xA0 => { self.immediate();     self.ldy();      },
-                    0xA1 => { self.indirect_x();    self.lda();      },
-                    0xA2 => { self.immediate();     self.ldx();      },
-                    0xA4 => { self.zero_page();     self.ldy();      },
-                    0xA5 => { self.zero_page();     self.lda();      },
-                    0xA6 => { self.zero_page();     self.ldx();      },
-                    0xA8 => { self.implied();       self.tay();      },
-                    0xA9 => { self.immediate();     self.lda();      },
-                    0xAA => { self.implied();       self.tax();      },
-                    0xAC => { self.absolute();      self.ldy();      },
-                    0xAD => { self.absolute();      self.lda();      },
-                    0xAE => { self.absolute();      self.ldx();      },
-                    0xB0 => { self.relative();      self.bcs();      },
-                    0xB1 => { self.indirect_y();    self.lda();      },
-                    0xB4 => { self.zero_page_x();   self.ldy();      },
-                    0xB5 => { self.zero_page_x();   self.lda();      },
-                    0xB6 => { self.zero_page_y();   self.ldx();      },
-                    0xB8 => { self.implied();       self.clv();      },
-                    0xB9 => { self.absolute_y();    self.lda();      },
-                    0xBA => { self.implied();       self.tsx();      },
-                    0xBC => { self.absolute_x();    self.ldy();      },
-                    0xBD => { self.absolute_x();    self.lda();      },
-                    0xBE => { self.absolute_y();    self.ldx();      },
-                    0xC0 => { self.immediate();     self.cpy();      },
-                    0xC1 => { self.indirect_x();    self.cmp();      },
-                    0xC4 => { self.zero_page();     self.cpy();      },
-                    0xC5 => { self.zero_page();     self.cmp();      },
-                    0xC6 => { self.zero_page();     self.dec();      },
-                    0xC8 => { self.implied();       self.iny();      },
-                    0xC9 => { self.immediate();     self.cmp();      },
-                    0xCA => { self.implied();       self.dex();      },
-                    0xCC => { self.absolute();      self.cpy();      },
-                    0xCD => { self.absolute();      self.cmp();      },
-                    0xCE => { self.absolute();      self.dec();      },
-                    0xD0 => { self.relative();      self.bne();      },
-                    0xD1 => { self.indirect_y();    self.cmp();      },
-                    0xD4 => { self.zero_page_x();   self.nop();      },
-                    0xD5 => { self.zero_page_x();   self.cmp();      },
-                    0xD6 => { self.zero_page_x();   self.dec();      },
-                    0xD8 => { self.implied();       self.cld();      },
-                    0xD9 => { self.absolute_y();    self.cmp();      },
-                    0xDA => { self.implied();       self.nop();      },
-                    0xDC => { self.absolute_x();    self.nop();      },
-                    0xDD => { self.absolute_x();    self.cmp();      },
-                    0xDE => { self.absolute_x();    self.dec();      },
-                    0xE0 => { self.immediate();     self.cpx();      },
-                    0xE1 => { self.indirect_x();    self.sbc();      },
-                    0xE4 => { self.zero_page();     self.cpx();      },
-                    0xE5 => { self.zero_page();     self.sbc();      },
-                    0xE6 => { self.zero_page();     self.inc();      },
-                    0xE8 => { self.implied();       self.inx();      },
-                    0xE9 => { self.immediate();     self.sbc();      },
-                    0xEA => { self.implied();       self.nop();      },
-                    0xEB => { self.immediate();     self.sbc();      },
-                    0xEC => { self.absolute();      self.cpx();      },
-                    0xED => { self.absolute();      self.sbc();      },
-                    0xEE => { self.absolute();      self.inc();      },
-                    0xF0 => { self.relative();      self.beq();      },
-                    0xF1 => { self.indirect_y();    self.sbc();      },
-                    0xF4 => { self.zero_page_x();   self.nop();      },
-                    0xF5 => { self.zero_page_x();   self.sbc();      },
-                    0xF6 => { self.zero_page_x();   self.inc();      },
-                    0xF8 => { self.implied();       self.sed();      },
-                    0xF9 => { self.absolute_y();    self.sbc();      },
-                    0xFA => { self.implied();       self.nop();      },
-                    0xFC => { self.absolute_x();    self.nop();      },
-                    0xFD => { self.absolute_x();    self.sbc();      },
-                    0xFE => { self.absolute_x();    self.inc();      },
-                    _=> panic!("unknow opcode {:#02x} {}", self.opcode, self.regs),
+                self.interupt = self.handle_interupt();
+                match self.interupt {
+                    // the opcode fetch is dropped and brk runs instead
+                    Some(_) => {
+                        self.mem_read_u8(self.regs.pc);
+                        self.opcode = 0x00;
+                    },
+                    // load next opcode
+                    None => self.opcode = self.fetch_u8(),
                 }
-                // add cycles
-                self.cycles_delay += self.timing_table[self.opcode as usize];
-                if self.page_crossing {
-                    self.cycles_delay += self.timing_table_cross[self.opcode as usize];
+            },
+            _ => match self.opcode {
+                0x00 => self.brk(step),
+                0x01 => self.indirect_x(step,  Access::Read,   Self::ora),
+                0x02 => self.implied(step, Self::jam),
+                0x03 => self.indirect_x(step,  Access::Modify, Self::slo),
+                0x04 => self.zero_page(step,   Access::Read,   Self::nop),
+                0x05 => self.zero_page(step,   Access::Read,   Self::ora),
+                0x06 => self.zero_page(step,   Access::Modify, Self::asl),
+                0x07 => self.zero_page(step,   Access::Modify, Self::slo),
+                0x08 => self.push(step, Self::php),
+                0x09 => self.immediate(step, Self::ora),
+                0x0A => self.implied(step, Self::asla),
+                0x0B => self.immediate(step, Self::anc),
+                0x0C => self.absolute(step,    Access::Read,   Self::nop),
+                0x0D => self.absolute(step,    Access::Read,   Self::ora),
+                0x0E => self.absolute(step,    Access::Modify, Self::asl),
+                0x0F => self.absolute(step,    Access::Modify, Self::slo),
+                0x10 => self.branch(step, Self::bpl),
+                0x11 => self.indirect_y(step,  Access::Read,   Self::ora),
+                0x12 => self.implied(step, Self::jam),
+                0x13 => self.indirect_y(step,  Access::Modify, Self::slo),
+                0x14 => self.zero_page_x(step, Access::Read,   Self::nop),
+                0x15 => self.zero_page_x(step, Access::Read,   Self::ora),
+                0x16 => self.zero_page_x(step, Access::Modify, Self::asl),
+                0x17 => self.zero_page_x(step, Access::Modify, Self::slo),
+                0x18 => self.implied(step, Self::clc),
+                0x19 => self.absolute_y(step,  Access::Read,   Self::ora),
+                0x1A => self.implied(step, Self::nop),
+                0x1B => self.absolute_y(step,  Access::Modify, Self::slo),
+                0x1C => self.absolute_x(step,  Access::Read,   Self::nop),
+                0x1D => self.absolute_x(step,  Access::Read,   Self::ora),
+                0x1E => self.absolute_x(step,  Access::Modify, Self::asl),
+                0x1F => self.absolute_x(step,  Access::Modify, Self::slo),
+                0x20 => self.jsr(step),
+                0x21 => self.indirect_x(step,  Access::Read,   Self::and),
+                0x22 => self.implied(step, Self::jam),
+                0x23 => self.indirect_x(step,  Access::Modify, Self::rla),
+                0x24 => self.zero_page(step,   Access::Read,   Self::bit),
+                0x25 => self.zero_page(step,   Access::Read,   Self::and),
+                0x26 => self.zero_page(step,   Access::Modify, Self::rol),
+                0x27 => self.zero_page(step,   Access::Modify, Self::rla),
+                0x28 => self.pull(step, Self::plp),
+                0x29 => self.immediate(step, Self::and),
+                0x2A => self.implied(step, Self::rola),
+                0x2B => self.immediate(step, Self::anc),
+                0x2C => self.absolute(step,    Access::Read,   Self::bit),
+                0x2D => self.absolute(step,    Access::Read,   Self::and),
+                0x2E => self.absolute(step,    Access::Modify, Self::rol),
+                0x2F => self.absolute(step,    Access::Modify, Self::rla),
+                0x30 => self.branch(step, Self::bmi),
+                0x31 => self.indirect_y(step,  Access::Read,   Self::and),
+                0x32 => self.implied(step, Self::jam),
+                0x33 => self.indirect_y(step,  Access::Modify, Self::rla),
+                0x34 => self.zero_page_x(step, Access::Read,   Self::nop),
+                0x35 => self.zero_page_x(step, Access::Read,   Self::and),
+                0x36 => self.zero_page_x(step, Access::Modify, Self::rol),
+                0x37 => self.zero_page_x(step, Access::Modify, Self::rla),
+                0x38 => self.implied(step, Self::sec),
+                0x39 => self.absolute_y(step,  Access::Read,   Self::and),
+                0x3A => self.implied(step, Self::nop),
+                0x3B => self.absolute_y(step,  Access::Modify, Self::rla),
+                0x3C => self.absolute_x(step,  Access::Read,   Self::nop),
+                0x3D => self.absolute_x(step,  Access::Read,   Self::and),
+                0x3E => self.absolute_x(step,  Access::Modify, Self::rol),
+                0x3F => self.absolute_x(step,  Access::Modify, Self::rla),
+                0x40 => self.rti(step),
+                0x41 => self.indirect_x(step,  Access::Read,   Self::eor),
+                0x42 => self.implied(step, Self::jam),
+                0x43 => self.indirect_x(step,  Access::Modify, Self::sre),
+                0x44 => self.zero_page(step,   Access::Read,   Self::nop),
+                0x45 => self.zero_page(step,   Access::Read,   Self::eor),
+                0x46 => self.zero_page(step,   Access::Modify, Self::lsr),
+                0x47 => self.zero_page(step,   Access::Modify, Self::sre),
+                0x48 => self.push(step, Self::pha),
+                0x49 => self.immediate(step, Self::eor),
+                0x4A => self.implied(step, Self::lsra),
+                0x4B => self.immediate(step, Self::alr),
+                0x4C => self.jmp(step),
+                0x4D => self.absolute(step,    Access::Read,   Self::eor),
+                0x4E => self.absolute(step,    Access::Modify, Self::lsr),
+                0x4F => self.absolute(step,    Access::Modify, Self::sre),
+                0x50 => self.branch(step, Self::bvc),
+                0x51 => self.indirect_y(step,  Access::Read,   Self::eor),
+                0x52 => self.implied(step, Self::jam),
+                0x53 => self.indirect_y(step,  Access::Modify, Self::sre),
+                0x54 => self.zero_page_x(step, Access::Read,   Self::nop),
+                0x55 => self.zero_page_x(step, Access::Read,   Self::eor),
+                0x56 => self.zero_page_x(step, Access::Modify, Self::lsr),
+                0x57 => self.zero_page_x(step, Access::Modify, Self::sre),
+                0x58 => self.implied(step, Self::cli),
+                0x59 => self.absolute_y(step,  Access::Read,   Self::eor),
+                0x5A => self.implied(step, Self::nop),
+                0x5B => self.absolute_y(step,  Access::Modify, Self::sre),
+                0x5C => self.absolute_x(step,  Access::Read,   Self::nop),
+                0x5D => self.absolute_x(step,  Access::Read,   Self::eor),
+                0x5E => self.absolute_x(step,  Access::Modify, Self::lsr),
+                0x5F => self.absolute_x(step,  Access::Modify, Self::sre),
+                0x60 => self.rts(step),
+                0x61 => self.indirect_x(step,  Access::Read,   Self::adc),
+                0x62 => self.implied(step, Self::jam),
+                0x63 => self.indirect_x(step,  Access::Modify, Self::rra),
+                0x64 => self.zero_page(step,   Access::Read,   Self::nop),
+                0x65 => self.zero_page(step,   Access::Read,   Self::adc),
+                0x66 => self.zero_page(step,   Access::Modify, Self::ror),
+                0x67 => self.zero_page(step,   Access::Modify, Self::rra),
+                0x68 => self.pull(step, Self::pla),
+                0x69 => self.immediate(step, Self::adc),
+                0x6A => self.implied(step, Self::rora),
+                0x6B => self.immediate(step, Self::arr),
+                0x6C => self.jmp_indirect(step),
+                0x6D => self.absolute(step,    Access::Read,   Self::adc),
+                0x6E => self.absolute(step,    Access::Modify, Self::ror),
+                0x6F => self.absolute(step,    Access::Modify, Self::rra),
+                0x70 => self.branch(step, Self::bvs),
+                0x71 => self.indirect_y(step,  Access::Read,   Self::adc),
+                0x72 => self.implied(step, Self::jam),
+                0x73 => self.indirect_y(step,  Access::Modify, Self::rra),
+                0x74 => self.zero_page_x(step, Access::Read,   Self::nop),
+                0x75 => self.zero_page_x(step, Access::Read,   Self::adc),
+                0x76 => self.zero_page_x(step, Access::Modify, Self::ror),
+                0x77 => self.zero_page_x(step, Access::Modify, Self::rra),
+                0x78 => self.implied(step, Self::sei),
+                0x79 => self.absolute_y(step,  Access::Read,   Self::adc),
+                0x7A => self.implied(step, Self::nop),
+                0x7B => self.absolute_y(step,  Access::Modify, Self::rra),
+                0x7C => self.absolute_x(step,  Access::Read,   Self::nop),
+                0x7D => self.absolute_x(step,  Access::Read,   Self::adc),
+                0x7E => self.absolute_x(step,  Access::Modify, Self::ror),
+                0x7F => self.absolute_x(step,  Access::Modify, Self::rra),
+                0x80 => self.immediate(step, Self::nop),
+                0x81 => self.indirect_x(step,  Access::Write,  Self::sta),
+                0x82 => self.immediate(step, Self::nop),
+                0x83 => self.indirect_x(step,  Access::Write,  Self::sax),
+                0x84 => self.zero_page(step,   Access::Write,  Self::sty),
+                0x85 => self.zero_page(step,   Access::Write,  Self::sta),
+                0x86 => self.zero_page(step,   Access::Write,  Self::stx),
+                0x87 => self.zero_page(step,   Access::Write,  Self::sax),
+                0x88 => self.implied(step, Self::dey),
+                0x89 => self.immediate(step, Self::nop),
+                0x8A => self.implied(step, Self::txa),
+                0x8B => self.immediate(step, Self::xaa),
+                0x8C => self.absolute(step,    Access::Write,  Self::sty),
+                0x8D => self.absolute(step,    Access::Write,  Self::sta),
+                0x8E => self.absolute(step,    Access::Write,  Self::stx),
+                0x8F => self.absolute(step,    Access::Write,  Self::sax),
+                0x90 => self.branch(step, Self::bcc),
+                0x91 => self.indirect_y(step,  Access::Write,  Self::sta),
+                0x92 => self.implied(step, Self::jam),
+                0x93 => self.indirect_y(step,  Access::Write,  Self::sha),
+                0x94 => self.zero_page_x(step, Access::Write,  Self::sty),
+                0x95 => self.zero_page_x(step, Access::Write,  Self::sta),
+                0x96 => self.zero_page_y(step, Access::Write,  Self::stx),
+                0x97 => self.zero_page_y(step, Access::Write,  Self::sax),
+                0x98 => self.implied(step, Self::tya),
+                0x99 => self.absolute_y(step,  Access::Write,  Self::sta),
+                0x9A => self.implied(step, Self::txs),
+                0x9B => self.absolute_y(step,  Access::Write,  Self::tas),
+                0x9C => self.absolute_x(step,  Access::Write,  Self::shy),
+                0x9D => self.absolute_x(step,  Access::Write,  Self::sta),
+                0x9E => self.absolute_y(step,  Access::Write,  Self::shx),
+                0x9F => self.absolute_y(step,  Access::Write,  Self::sha),
+                0xA0 => self.immediate(step, Self::ldy),
+                0xA1 => self.indirect_x(step,  Access::Read,   Self::lda),
+                0xA2 => self.immediate(step, Self::ldx),
+                0xA3 => self.indirect_x(step,  Access::Read,   Self::lax),
+                0xA4 => self.zero_page(step,   Access::Read,   Self::ldy),
+                0xA5 => self.zero_page(step,   Access::Read,   Self::lda),
+                0xA6 => self.zero_page(step,   Access::Read,   Self::ldx),
+                0xA7 => self.zero_page(step,   Access::Read,   Self::lax),
+                0xA8 => self.implied(step, Self::tay),
+                0xA9 => self.immediate(step, Self::lda),
+                0xAA => self.implied(step, Self::tax),
+                0xAB => self.immediate(step, Self::lxa),
+                0xAC => self.absolute(step,    Access::Read,   Self::ldy),
+                0xAD => self.absolute(step,    Access::Read,   Self::lda),
+                0xAE => self.absolute(step,    Access::Read,   Self::ldx),
+                0xAF => self.absolute(step,    Access::Read,   Self::lax),
+                0xB0 => self.branch(step, Self::bcs),
+                0xB1 => self.indirect_y(step,  Access::Read,   Self::lda),
+                0xB2 => self.implied(step, Self::jam),
+                0xB3 => self.indirect_y(step,  Access::Read,   Self::lax),
+                0xB4 => self.zero_page_x(step, Access::Read,   Self::ldy),
+                0xB5 => self.zero_page_x(step, Access::Read,   Self::lda),
+                0xB6 => self.zero_page_y(step, Access::Read,   Self::ldx),
+                0xB7 => self.zero_page_y(step, Access::Read,   Self::lax),
+                0xB8 => self.implied(step, Self::clv),
+                0xB9 => self.absolute_y(step,  Access::Read,   Self::lda),
+                0xBA => self.implied(step, Self::tsx),
+                0xBB => self.absolute_y(step,  Access::Read,   Self::las),
+                0xBC => self.absolute_x(step,  Access::Read,   Self::ldy),
+                0xBD => self.absolute_x(step,  Access::Read,   Self::lda),
+                0xBE => self.absolute_y(step,  Access::Read,   Self::ldx),
+                0xBF => self.absolute_y(step,  Access::Read,   Self::lax),
+                0xC0 => self.immediate(step, Self::cpy),
+                0xC1 => self.indirect_x(step,  Access::Read,   Self::cmp),
+                0xC2 => self.immediate(step, Self::nop),
+                0xC3 => self.indirect_x(step,  Access::Modify, Self::dcp),
+                0xC4 => self.zero_page(step,   Access::Read,   Self::cpy),
+                0xC5 => self.zero_page(step,   Access::Read,   Self::cmp),
+                0xC6 => self.zero_page(step,   Access::Modify, Self::dec),
+                0xC7 => self.zero_page(step,   Access::Modify, Self::dcp),
+                0xC8 => self.implied(step, Self::iny),
+                0xC9 => self.immediate(step, Self::cmp),
+                0xCA => self.implied(step, Self::dex),
+                0xCB => self.immediate(step, Self::axs),
+                0xCC => self.absolute(step,    Access::Read,   Self::cpy),
+                0xCD => self.absolute(step,    Access::Read,   Self::cmp),
+                0xCE => self.absolute(step,    Access::Modify, Self::dec),
+                0xCF => self.absolute(step,    Access::Modify, Self::dcp),
+                0xD0 => self.branch(step, Self::bne),
+                0xD1 => self.indirect_y(step,  Access::Read,   Self::cmp),
+                0xD2 => self.implied(step, Self::jam),
+                0xD3 => self.indirect_y(step,  Access::Modify, Self::dcp),
+                0xD4 => self.zero_page_x(step, Access::Read,   Self::nop),
+                0xD5 => self.zero_page_x(step, Access::Read,   Self::cmp),
+                0xD6 => self.zero_page_x(step, Access::Modify, Self::dec),
+                0xD7 => self.zero_page_x(step, Access::Modify, Self::dcp),
+                0xD8 => self.implied(step, Self::cld),
+                0xD9 => self.absolute_y(step,  Access::Read,   Self::cmp),
+                0xDA => self.implied(step, Self::nop),
+                0xDB => self.absolute_y(step,  Access::Modify, Self::dcp),
+                0xDC => self.absolute_x(step,  Access::Read,   Self::nop),
+                0xDD => self.absolute_x(step,  Access::Read,   Self::cmp),
+                0xDE => self.absolute_x(step,  Access::Modify, Self::dec),
+                0xDF => self.absolute_x(step,  Access::Modify, Self::dcp),
+                0xE0 => self.immediate(step, Self::cpx),
+                0xE1 => self.indirect_x(step,  Access::Read,   Self::sbc),
+                0xE2 => self.immediate(step, Self::nop),
+                0xE3 => self.indirect_x(step,  Access::Modify, Self::isc),
+                0xE4 => self.zero_page(step,   Access::Read,   Self::cpx),
+                0xE5 => self.zero_page(step,   Access::Read,   Self::sbc),
+                0xE6 => self.zero_page(step,   Access::Modify, Self::inc),
+                0xE7 => self.zero_page(step,   Access::Modify, Self::isc),
+                0xE8 => self.implied(step, Self::inx),
+                0xE9 => self.immediate(step, Self::sbc),
+                0xEA => self.implied(step, Self::nop),
+                0xEB => self.immediate(step, Self::sbc),
+                0xEC => self.absolute(step,    Access::Read,   Self::cpx),
+                0xED => self.absolute(step,    Access::Read,   Self::sbc),
+                0xEE => self.absolute(step,    Access::Modify, Self::inc),
+                0xEF => self.absolute(step,    Access::Modify, Self::isc),
+                0xF0 => self.branch(step, Self::beq),
+                0xF1 => self.indirect_y(step,  Access::Read,   Self::sbc),
+                0xF2 => self.implied(step, Self::jam),
+                0xF3 => self.indirect_y(step,  Access::Modify, Self::isc),
+                0xF4 => self.zero_page_x(step, Access::Read,   Self::nop),
+                0xF5 => self.zero_page_x(step, Access::Read,   Self::sbc),
+                0xF6 => self.zero_page_x(step, Access::Modify, Self::inc),
+                0xF7 => self.zero_page_x(step, Access::Modify, Self::isc),
+                0xF8 => self.implied(step, Self::sed),
+                0xF9 => self.absolute_y(step,  Access::Read,   Self::sbc),
+                0xFA => self.implied(step, Self::nop),
+                0xFB => self.absolute_y(step,  Access::Modify, Self::isc),
+                0xFC => self.absolute_x(step,  Access::Read,   Self::nop),
+                0xFD => self.absolute_x(step,  Access::Read,   Self::sbc),
+                0xFE => self.absolute_x(step,  Access::Modify, Self::inc),
+                0xFF => self.absolute_x(step,  Access::Modify, Self::isc),
+            },
+        }
+    }
+
+    // the next cycle's bus access, found by running it without the bus and
+    // rolling back. the opcode fetch isn't run as it polls the interrupts
+    fn probe_cycle(&mut self) -> BusAccess {
+        if self.halted || self.step == 0 {
+            return BusAccess::Read(self.regs.pc);
+        }
+        // a jam or fault in the probed cycle halts, that must wait for the real one
+        let saved = (self.regs, self.opcode, self.step, self.interupt, self.page_crossing, self.op_addr, self.op_data, self.event, self.halted);
+        let interupts = (self.need_nmi, self.run_irq);
+        self.probing = true;
+        self.run_cycle();
+        self.probing = false;
+        (self.regs, self.opcode, self.step, self.interupt, self.page_crossing, self.op_addr, self.op_data, self.event, self.halted) = saved;
+        (self.need_nmi, self.run_irq) = interupts;
+        self.probed.take().unwrap_or(BusAccess::Read(self.regs.pc))
+    }
+
+    // dma halts the cpu on a read cycle, the read still reaches the bus
+    fn halt_for_dma(&mut self) -> bool {
+        match self.probe_cycle() {
+            BusAccess::Read(addr) => {
+                self.mem_read_u8(addr);
+                self.dma.halted = true;
+                true
+            },
+            BusAccess::Write(_) => false,
+        }
+    }
+
+    fn dma_cycle(&mut self) {
+        let get = self.cycles & 1 == 0;
+        match self.dma.dmc {
+            // the dmc fetch takes the get cycle from oam dma
+            Some(0) if get => {
+                self.dma.dmc = None;
+                let addr = self.bus.apu.borrow().dmc_fetch_addr();
+                if let Some(addr) = addr {
+                    let val = self.bus.read_u8(addr);
+                    self.bus.apu.borrow_mut().dmc_load_sample(val);
                 }
-            } else {
-                // interupt handled
-            }
+            },
+            dmc => {
+                self.dma.dmc = dmc.map(|cycles| cycles.saturating_sub(1));
+                self.oam_dma_cycle(get);
+            },
+        }
+        if !self.dma.active() {
+            self.dma.halted = false;
+        }
+    }
+
+    fn oam_dma_cycle(&mut self, get: bool) {
+        let Some(page) = self.dma.oam_page else {
+            return;
+        };
+        match (get, self.dma.oam_latch) {
+            (true, None) => {
+                let addr = u16::from(page) << 8 | self.dma.oam_count;
+                self.dma.oam_latch = Some(self.bus.read_u8(addr));
+                self.dma.oam_count += 1;
+            },
+            (false, Some(val)) => {
+                self.bus.write_u8(0x2004, val);
+                self.dma.oam_latch = None;
+                if self.dma.oam_count == 0x100 {
+                    self.dma.oam_page = None;
+                }
+            },
+            // alignment cycle
+            _ => (),
+        }
+    }
+
+    // step simulation
+    pub fn tick(&mut self) -> Option<CpuEvent> {
+
+        self.cycles = self.cycles.wrapping_add(1);
+        // apu and mapper run at cpu clock
+        let expansion = {
+            let mut mapper = self.bus.mapper.borrow_mut();
+            mapper.tick();
+            mapper.audio_output()
+        };
+        self.bus.apu.borrow_mut().set_expansion_output(expansion);
+        self.bus.apu.borrow_mut().tick();
+        // dma requests
+        if let Some(page) = self.bus.oam_dma.take() {
+            self.dma.oam_page = Some(page);
+            self.dma.oam_count = 0;
+            self.dma.oam_latch = None;
+        }
+        if self.dma.dmc.is_none() && self.bus.apu.borrow().dmc_fetch_addr().is_some() {
+            // one dummy cycle after the halt
+            self.dma.dmc = Some(1);
+        }
+
+        if self.dma.halted {
+            self.dma_cycle();
+        } else if self.dma.active() && self.halt_for_dma() {
+            // the halt cycle
+        } else if self.halted {
+            // the clocks keep running but the cpu does nothing
+        } else {
+            self.run_cycle();
         }
-        // count cycle
-        self.cycles_delay -= 1;
-        // return cycles_delay
-        self.cycles_delay
+        self.sample_interupts();
+        self.event.take()
     }
 }
@@ -3,5 +3,9 @@ pub mod board;
 pub mod cartridge;
 pub mod mapper;
 pub mod ppu;
+pub mod apu;
+pub mod resampler;
+pub mod opll;
 pub mod controller;
-pub mod clock;
+pub mod pacing;
+pub mod save;
@@ -3,19 +3,230 @@ extern crate sdl2;
 use std::error::Error;
 use std::cell::RefCell;
 use std::rc::Rc;
-// use std::time::{Duration, Instant};
+use std::process;
+use std::time::Instant;
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 
-use nes::cpu::{ CPU };
-use nes::board::{ Signal };
+use nes::cpu::{ CPU, CpuEvent };
+use nes::board::{ Region, Signal };
 use nes::cartridge::Cartridge;
 use nes::ppu::PPU;
+use nes::apu::APU;
 use nes::controller::Controller;
-use nes::clock::Clock;
+use nes::mapper::Mapper;
+use nes::pacing::{Pacer, PacingMode};
+use nes::save::SaveFile;
 
 
-fn main() -> Result<(), Box<dyn Error>> {
+const USAGE: &str = "usage: nes [options] <rom>
+
+options:
+    -s, --scale <n>            window scale factor (default 4)
+    -f, --fullscreen           start in fullscreen
+    -r, --region <ntsc|pal>    console region (default from the rom header)
+    -m, --mute                 disable audio output
+    -p, --paused               start paused, press P to resume
+        --pacing <mode>        frame pacing: audio, vsync or dynamic (default audio)
+        --headless <frames>    run the given number of frames without a window and exit
+    -h, --help                 print this help";
+
+
+// command line options
+struct Options {
+    rom: String,
+    scale: u32,
+    fullscreen: bool,
+    region: Option<Region>,
+    mute: bool,
+    paused: bool,
+    pacing: PacingMode,
+    headless: Option<u32>,
+}
+
+
+impl Options {
+
+    // parse arguments, returns None if help was requested
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Self>, String> {
+        let mut rom = None;
+        let mut options = Options {
+            rom: String::new(),
+            scale: 4,
+            fullscreen: false,
+            region: None,
+            mute: false,
+            paused: false,
+            pacing: PacingMode::Audio,
+            headless: None,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| args.next().ok_or(format!("missing value for {}", name));
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-s" | "--scale" => {
+                    options.scale = value(&arg)?.parse().map_err(|_| "scale must be a positive number".to_string())?;
+                    if options.scale == 0 {
+                        return Err("scale must be a positive number".to_string());
+                    }
+                },
+                "-f" | "--fullscreen" => options.fullscreen = true,
+                "-r" | "--region" => options.region = Some(value(&arg)?.parse()?),
+                "-m" | "--mute" => options.mute = true,
+                "-p" | "--paused" => options.paused = true,
+                "--pacing" => options.pacing = value(&arg)?.parse()?,
+                "--headless" => {
+                    let frames = value(&arg)?.parse().map_err(|_| "headless frame count must be a number".to_string())?;
+                    options.headless = Some(frames);
+                },
+                _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+                _ if rom.is_none() => rom = Some(arg),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        options.rom = rom.ok_or("no rom file given")?;
+        Ok(Some(options))
+    }
+}
+
+
+// the emulated console
+struct Machine {
+    cpu: CPU,
+    ppu: Rc<RefCell<PPU>>,
+    apu: Rc<RefCell<APU>>,
+    controller: Rc<RefCell<Controller>>,
+    mapper: Rc<RefCell<Box<dyn Mapper>>>,
+    region: Region,
+    cycles: u64,
+    // battery backed prg ram
+    save: Option<SaveFile>,
+    // what stopped the cpu, until the next reset
+    halt: Option<CpuEvent>,
+}
+
+
+impl Machine {
+
+    fn new(cartridge: Cartridge, region: Region, nmi: Signal, irq: Signal) -> Self {
+        let mapper = cartridge.to_mapper();
+        // controller
+        let controller = Rc::new(RefCell::new(Controller::new()));
+        // create ppu
+        let mut ppu = PPU::new(Rc::clone(&mapper), Rc::clone(&nmi));
+        ppu.set_region(region);
+        let ppu = Rc::new(RefCell::new(ppu));
+        // create apu
+        let mut apu = APU::new(Rc::clone(&irq));
+        apu.set_region(region);
+        let apu = Rc::new(RefCell::new(apu));
+        // create cpu
+        let mut cpu = CPU::new(Rc::clone(&ppu), Rc::clone(&apu), Rc::clone(&mapper), Rc::clone(&controller), nmi, irq);
+        cpu.power_up();
+        // reset ppu
+        ppu.borrow_mut().reset();
+        Self {
+            cpu,
+            ppu,
+            apu,
+            controller,
+            mapper,
+            region,
+            cycles: 0,
+            save: None,
+            halt: None,
+        }
+    }
+
+    // reset button, the cpu restarts from the reset vector
+    fn reset(&mut self) {
+        self.mapper.borrow_mut().reset();
+        self.cpu.reset();
+        self.ppu.borrow_mut().reset();
+        // the apu channels are silenced
+        self.apu.borrow_mut().write_u8(0x4015, 0x00);
+        self.halt = None;
+    }
+
+    // load battery ram from the save file and keep it in sync from now on
+    fn attach_save(&mut self, mut save: SaveFile) -> Result<(), Box<dyn Error>> {
+        if let Some(ram) = self.mapper.borrow_mut().prg_ram_mut() {
+            if save.load(ram)? {
+                println!("loaded {}", save.path().display());
+            }
+            self.save = Some(save);
+        }
+        Ok(())
+    }
+
+    // write battery ram periodically, call once per frame
+    fn autosave(&mut self) {
+        let mapper = self.mapper.borrow();
+        if let (Some(save), Some(ram)) = (&mut self.save, mapper.prg_ram()) {
+            if let Err(e) = save.autosave(ram) {
+                eprintln!("warning: failed to write {}: {}", save.path().display(), e);
+            }
+        }
+    }
+
+    // write battery ram if it changed
+    fn flush_save(&mut self) -> Result<(), Box<dyn Error>> {
+        let mapper = self.mapper.borrow();
+        if let (Some(save), Some(ram)) = (&mut self.save, mapper.prg_ram()) {
+            save.flush(ram).map_err(|e| format!("failed to write {}: {}", save.path().display(), e))?;
+        }
+        Ok(())
+    }
+
+    // run the cpu and ppu until the ppu finishes a frame
+    fn run_frame(&mut self) {
+        let mut end_frame: u8 = 0;
+        while end_frame == 0 {
+            // a halted cpu keeps the machine running, reset recovers
+            if let Some(event @ (CpuEvent::Halted { .. } | CpuEvent::InvalidState { .. })) = self.cpu.tick() {
+                if self.halt.is_none() {
+                    eprintln!("warning: {}, press F5 to reset", event);
+                }
+                self.halt = Some(event);
+            }
+            self.cycles = self.cycles.wrapping_add(1);
+            let mut ppu = self.ppu.borrow_mut();
+            for _ in 0..self.region.ppu_dots(self.cycles) {
+                end_frame |= ppu.tick();
+            }
+        }
+    }
+
+    // throw away generated audio
+    fn drain_audio(&mut self, samples: &mut [i16]) {
+        let mut apu = self.apu.borrow_mut();
+        while apu.read_samples(samples) > 0 {}
+    }
+}
+
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(e) = run(options) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
 
     // *** emulation setup ***
 
@@ -25,37 +236,82 @@ fn main() -> Result<(), Box<dyn Error>> {
     let irq = Signal::default();
 
     // load cartridge data
-    let mapper = Cartridge::load("roms/[182]  角色类 - 忍者龙剑传3.nes", Rc::clone(&irq)).expect("load cartridge error").to_mapper();
-    // let mapper = Cartridge::load("roms/nestest.nes", Rc::clone(&irq)).expect("load cartridge error").to_mapper();
-    // let mapper = Cartridge::load("../nes-test-roms/scrolltest/scroll.nes", Rc::clone(&irq)).expect("load cartridge error").to_mapper();
-    // controller
-    let controller = Rc::new(RefCell::new(Controller::new()));
-    // create ppu
-    let ppu = PPU::new(Rc::clone(&mapper), Rc::clone(&nmi));
-    let ppu = Rc::new(RefCell::new(ppu));
-    // create a cpu for test
-    let mut cpu = CPU::new(Rc::clone(&ppu), Rc::clone(&mapper), Rc::clone(&controller), Rc::clone(&nmi), Rc::clone(&irq));
-    cpu.power_up();
-
-    // reset ppu
-    {
-        let mut p = ppu.borrow_mut();
-        p.reset();
+    let cartridge = Cartridge::load(&options.rom, Rc::clone(&irq))
+        .map_err(|e| format!("failed to load {}: {}", options.rom, e))?;
+    println!("{}: {}", options.rom, cartridge.header());
+    let region = options.region.unwrap_or(cartridge.header().region());
+
+    let battery = cartridge.header().has_battery();
+
+    let mut machine = Machine::new(cartridge, region, nmi, irq);
+    if battery {
+        machine.attach_save(SaveFile::for_rom(&options.rom))?;
+    }
+
+    match options.headless {
+        Some(frames) => run_headless(&mut machine, frames),
+        None => run_window(&mut machine, &options),
     }
-    // clock
-    let mut clock = Clock::new();
+}
+
+
+// run frames as fast as possible without video or audio output
+fn run_headless(machine: &mut Machine, frames: u32) -> Result<(), Box<dyn Error>> {
+    let mut samples = vec![0i16; 4096];
+    let start = Instant::now();
+    for _ in 0..frames {
+        machine.run_frame();
+        machine.drain_audio(&mut samples);
+        machine.autosave();
+    }
+    machine.flush_save()?;
+    let elapsed = start.elapsed().as_secs_f64();
+    println!("ran {} frames in {:.2}s ({:.1} fps)", frames, elapsed, frames as f64 / elapsed.max(f64::EPSILON));
+    Ok(())
+}
+
+
+fn run_window(machine: &mut Machine, options: &Options) -> Result<(), Box<dyn Error>> {
 
     // **** gui setup  ****
 
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+
+    // audio output
+    let mut samples = vec![0i16; 4096];
+    let audio_queue: Option<AudioQueue<i16>> = match options.mute {
+        true => None,
+        false => {
+            let audio_subsystem = sdl_context.audio()?;
+            let desired_spec = AudioSpecDesired {
+                freq: Some(44100),
+                channels: Some(1),
+                samples: Some(1024),
+            };
+            let queue: AudioQueue<i16> = audio_subsystem.open_queue(None, &desired_spec)?;
+            machine.apu.borrow_mut().set_sample_rate(queue.spec().freq as f64);
+            queue.resume();
+            Some(queue)
+        },
+    };
+    let sample_rate = machine.apu.borrow().sample_rate();
+    let queued = || audio_queue.as_ref().map(|queue| queue.size() as usize / 2);
+
+    // frame pacing
+    let mut pacer = Pacer::new(options.pacing, machine.region.frame_rate(), sample_rate);
 
-    let window = video_subsystem.window("nes", 256 * 4, 240 * 4)
-        .position_centered()
-        .build()
-        .unwrap();
+    let mut window = video_subsystem.window("nes", 256 * options.scale, 240 * options.scale);
+    window.position_centered();
+    if options.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build()?;
 
-    let mut canvas = window.into_canvas().accelerated().present_vsync().build().unwrap();
+    let mut canvas = match pacer.uses_vsync() {
+        true => window.into_canvas().accelerated().present_vsync().build()?,
+        false => window.into_canvas().accelerated().build()?,
+    };
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
     canvas.present();
@@ -66,43 +322,71 @@ fn main() -> Result<(), Box<dyn Error>> {
         .create_texture_streaming(PixelFormatEnum::RGB24, 256, 240)
         .map_err(|e| e.to_string())?;
 
+    let mut event_pump = sdl_context.event_pump()?;
+    let mut paused = options.paused;
+    let mut title = String::from("nes");
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
-
-    // let mut prev_time = Instant::now();
     // game loop
     'running: loop {
         // handle key event
         for event in event_pump.poll_iter() {
-
-            let mut controller = controller.borrow_mut();
-
             match event {
                 Event::Quit {..} => {
                     break 'running
                 },
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                    paused = !paused;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => {
+                    machine.reset();
+                },
                 Event::KeyDown { keycode, .. } => {
-                    controller.key_down(keycode);
+                    machine.controller.borrow_mut().key_down(keycode);
                 },
                 Event::KeyUp { keycode, .. } => {
-                    controller.key_up(keycode);
+                    machine.controller.borrow_mut().key_up(keycode);
                 },
                 _ => {}
             }
         }
-        // emulation for one loop
-        let mut end_frame: u8 = 0;
-        let cycles = clock.get_cycles_past();
-        for _ in 0..cycles {
-            cpu.tick();
-            let mut ppu = ppu.borrow_mut();
-            end_frame |= ppu.tick();
-            end_frame |= ppu.tick();
-            end_frame |= ppu.tick();
+        if paused {
+            canvas.present();
+            pacer.wait(|| None);
+            continue;
         }
-        if end_frame != 0 {
-            // time to refresh 
-            let ppu = ppu.borrow();
+        // emulate one frame
+        machine.run_frame();
+        // show a halted cpu in the title
+        let halted = match &machine.halt {
+            Some(event) => format!("nes - {} (F5 to reset)", event),
+            None => String::from("nes"),
+        };
+        if halted != title {
+            canvas.window_mut().set_title(&halted)?;
+            title = halted;
+        }
+        // queue audio samples
+        match &audio_queue {
+            Some(audio_queue) => {
+                let queued = audio_queue.size() as usize / 2;
+                if pacer.audio_overrun(queued) {
+                    audio_queue.clear();
+                }
+                let mut apu = machine.apu.borrow_mut();
+                apu.set_sample_rate(pacer.sample_rate(queued));
+                loop {
+                    let count = apu.read_samples(&mut samples);
+                    if count == 0 {
+                        break;
+                    }
+                    audio_queue.queue_audio(&samples[..count])?;
+                }
+            },
+            None => machine.drain_audio(&mut samples),
+        }
+        // time to refresh
+        {
+            let ppu = machine.ppu.borrow();
             let output = ppu.get_output();
             texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
                 for y in 0..240 {
@@ -114,12 +398,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                     }
                 }
             })?;
-            canvas.clear();
-            canvas.copy(&texture, None, None)?;
-            canvas.present();
         }
-        //::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 120));
+        canvas.clear();
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+        machine.autosave();
+        // wait for real time to catch up
+        pacer.wait(queued);
     }
+    machine.flush_save()?;
     println!("bye!");
     Ok(())
 }
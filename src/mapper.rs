@@ -1,6 +1,7 @@
 use std::fmt::Debug;
 use crate::board::Memory;
-use crate::board::{ Signal };
+use crate::board::{ self, Signal, IRQ_MAPPER };
+use crate::opll::Opll;
 
 
 pub const PRG_BANK_SIZE: usize = 16 * 1024;
@@ -8,62 +9,224 @@ pub const CHR_BANK_SIZE: usize = 8 * 1024;
 
 
 
+// what the ppu is fetching
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpuFetch {
+	// $2007 access
+	Data,
+	NameTable,
+	Attribute,
+	// background pattern
+	Background,
+	// sprite pattern
+	Sprite,
+}
+
+
+// ppu $0000-$3EFF and cpu $4020-$FFFF go through the mapper
 pub trait Mapper {
 	fn read_u8(&mut self, addr: u16) -> u8;
 	fn write_u8(&mut self, addr: u16, val: u8);
+	// ppu rendering fetch, for boards that bank by fetch type
+	fn ppu_fetch(&mut self, addr: u16, _fetch: PpuFetch) -> u8 {
+		self.read_u8(addr)
+	}
+	// cpu writes to $2000-$2007, some boards snoop the ppu control registers
+	fn ppu_register_write(&mut self, _addr: u16, _val: u8) {}
+	// clocked once per cpu cycle
+	fn tick(&mut self) {}
+	// console reset button, most boards never see it and keep their banks
+	fn reset(&mut self) {}
+	// expansion audio, mixed with the apu output on the same scale
+	fn audio_output(&self) -> f32 {
+		0.0
+	}
+	// prg ram, for trainers and battery saves
+	fn prg_ram(&self) -> Option<&Memory>;
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory>;
 }
 
 
+// cartridge prg ram, usually at $6000-$7FFF
 #[derive(Debug)]
+pub struct PrgRam {
+	ram: Option<Memory>,
+	// chip enable, disabled ram reads as open bus
+	enabled: bool,
+	// write protect
+	writable: bool,
+}
+
+
+impl PrgRam {
+
+	pub fn new(size: usize) -> Self {
+		Self {
+			ram: match size {
+				0 => None,
+				size => Some(Memory::new(size)),
+			},
+			enabled: true,
+			writable: true,
+		}
+	}
+
+	// read at an offset into the ram, mirrored when smaller than the window
+	fn read_u8(&self, offset: usize) -> u8 {
+		match &self.ram {
+			Some(ram) if self.enabled => ram[offset % ram.size()],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, offset: usize, val: u8) {
+		match &mut self.ram {
+			Some(ram) if self.enabled && self.writable => {
+				let size = ram.size();
+				ram[offset % size] = val;
+			},
+			_ => (),
+		}
+	}
+
+	fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+	}
+
+	fn set_writable(&mut self, writable: bool) {
+		self.writable = writable;
+	}
+
+	fn memory(&self) -> Option<&Memory> {
+		self.ram.as_ref()
+	}
+
+	fn memory_mut(&mut self) -> Option<&mut Memory> {
+		self.ram.as_mut()
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MirroMode {
-	Single,
+	SingleLower,
+	SingleUpper,
 	Vertical,
 	Horizontal,
 	FourScreen,
 }
 
 
+// source of one 1K nametable quadrant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameTablePage {
+	// console vram (ciram), page 0 or 1
+	Ciram(usize),
+	// extra vram on the cartridge, in 1K pages
+	CartRam(usize),
+	// 1K chr bank used as a nametable
+	Chr(usize),
+}
+
+
+// ppu nametables, each 1K quadrant can be mapped by the mapper
 #[derive(Debug)]
-struct NameTable {
-	name_table: Memory,
-	mode: MirroMode,
+pub struct NameTable {
+	// 2K console vram
+	ciram: Memory,
+	// cartridge vram, four screen boards carry 2K
+	cart_ram: Memory,
+	// $2000, $2400, $2800 and $2C00
+	pages: [NameTablePage; 4],
+	// four screen boards ignore mapper mirroring control
+	four_screen: bool,
 }
 
 
 impl NameTable {
 
-	fn new(mode: MirroMode) -> Self {
-		Self {
-			name_table: Memory::new(4096),
-			mode: mode,
-		}
+	pub fn new(mode: MirroMode) -> Self {
+		let four_screen = mode == MirroMode::FourScreen;
+		let mut name_table = Self {
+			ciram: Memory::new(2048),
+			cart_ram: Memory::new(if four_screen { 2048 } else { 0 }),
+			pages: [NameTablePage::Ciram(0); 4],
+			four_screen: false,
+		};
+		name_table.set_mirror_mode(mode);
+		name_table.four_screen = four_screen;
+		name_table
 	}
 
-	fn tanslate_addr(&self, addr: u16) -> u16 {
-		match self.mode {
-			MirroMode::Single => addr & 0x03ff,
-			MirroMode::Horizontal => (addr & 0x03ff) | ((addr & 0x0800) >> 1),
-			MirroMode::Vertical => addr & 0x07ff,
-			MirroMode::FourScreen => addr & 0x0fff,
+	// add cartridge vram for mappers that bank it into the nametables
+	pub fn with_cart_ram(mut self, size: usize) -> Self {
+		if self.cart_ram.size() < size {
+			self.cart_ram = Memory::new(size);
 		}
+		self
 	}
 }
 
 
 impl NameTable {
 
-	fn read_u8(&mut self, addr: u16) -> u8 {
-		let addr = self.tanslate_addr(addr);
-		self.name_table.read_u8(addr)
+	// chr is needed for quadrants mapped to chr rom
+	pub fn read_u8(&self, addr: u16, chr: &[u8]) -> u8 {
+		self.read_page(self.pages[(addr as usize >> 10) & 0x03], addr, chr)
 	}
 
-	fn write_u8(&mut self, addr: u16, val: u8) {
-		let addr = self.tanslate_addr(addr);
-		self.name_table.write_u8(addr, val);
+	// chr rom quadrants are read only
+	pub fn write_u8(&mut self, addr: u16, val: u8) {
+		self.write_page(self.pages[(addr as usize >> 10) & 0x03], addr, val)
+	}
+
+	// access a page directly, for boards that also map nametable ram into the pattern tables
+	pub fn read_page(&self, page: NameTablePage, addr: u16, chr: &[u8]) -> u8 {
+		let offset = (addr & 0x03ff) as usize;
+		match page {
+			NameTablePage::Ciram(page) => self.ciram[(page & 0x01) << 10 | offset],
+			NameTablePage::CartRam(page) => match self.cart_ram.size() {
+				0 => 0,
+				size => self.cart_ram[(page << 10 | offset) % size],
+			},
+			NameTablePage::Chr(bank) => match chr.len() {
+				0 => 0,
+				size => chr[(bank << 10 | offset) % size],
+			},
+		}
+	}
+
+	pub fn write_page(&mut self, page: NameTablePage, addr: u16, val: u8) {
+		let offset = (addr & 0x03ff) as usize;
+		match page {
+			NameTablePage::Ciram(page) => self.ciram[(page & 0x01) << 10 | offset] = val,
+			NameTablePage::CartRam(page) => {
+				let size = self.cart_ram.size();
+				if size > 0 {
+					self.cart_ram[(page << 10 | offset) % size] = val;
+				}
+			},
+			NameTablePage::Chr(_) => (),
+		}
+	}
+
+	pub fn set_mirror_mode(&mut self, mode: MirroMode) {
+		if self.four_screen {
+			return;
+		}
+		use NameTablePage::*;
+		self.pages = match mode {
+			MirroMode::SingleLower => [Ciram(0); 4],
+			MirroMode::SingleUpper => [Ciram(1); 4],
+			MirroMode::Vertical => [Ciram(0), Ciram(1), Ciram(0), Ciram(1)],
+			MirroMode::Horizontal => [Ciram(0), Ciram(0), Ciram(1), Ciram(1)],
+			MirroMode::FourScreen => [Ciram(0), Ciram(1), CartRam(0), CartRam(1)],
+		};
 	}
 
-	fn set_mirror_mode(&mut self, mode: MirroMode) {
-		self.mode = mode
+	// map a single quadrant, 0-3
+	pub fn set_page(&mut self, quadrant: usize, page: NameTablePage) {
+		self.pages[quadrant & 0x03] = page;
 	}
 }
 
@@ -80,15 +243,19 @@ pub struct NRom {
 
 	// nametable
 	name_table: NameTable,
+
+	// prg ram
+	prg_ram: PrgRam,
 }
 
 
 impl NRom {
-	pub fn new(prg: Memory, chr: Memory, mode: MirroMode) -> Self {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize) -> Self {
 		Self {
 			prg: prg,
 			chr: chr,
 			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
 		}
 	}
 }
@@ -99,7 +266,8 @@ impl Mapper for NRom {
 	fn read_u8(&mut self, addr: u16) -> u8 {
 		match addr {
 			0x0000..=0x1fff => self.chr.read_u8(addr),
-			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000),
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
 			0x8000..=0xffff => {
 				let addr = addr % (self.prg.size() as u16);
 				self.prg.read_u8(addr)
@@ -113,6 +281,7 @@ impl Mapper for NRom {
 			// some nrom rom has CHR ram. so make CHR writable
 			0x0000..=0x1fff => self.chr.write_u8(addr, val),
 			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
 			0x8000..=0xffff => {
 				let addr = addr % (self.prg.size() as u16);
 				self.prg.write_u8(addr, val);
@@ -120,6 +289,14 @@ impl Mapper for NRom {
 			_ => (),
 		}
 	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
 }
 
 
@@ -143,18 +320,21 @@ pub struct UxRom {
 	// nametable
 	name_table: NameTable,
 
+	// prg ram
+	prg_ram: PrgRam,
 }
 
 
 impl UxRom {
-	pub fn new(prg: Memory, _chr: Memory, mode: MirroMode) -> Self {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize) -> Self {
 		let banks = prg.size() / PRG_BANK_SIZE;
 		Self {
 			prg: prg,
-			chr: Memory::new(8192),
+			chr,
 			name_table: NameTable::new(mode),
 			select: 0,
 			banks: banks,
+			prg_ram: PrgRam::new(prg_ram_size),
 		}
 	}
 }
@@ -165,7 +345,8 @@ impl Mapper for UxRom {
 	fn read_u8(&mut self, addr: u16) -> u8 {
 		match addr {
 			0x0000..=0x1fff => self.chr.read_u8(addr),
-			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000),
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
 			0x8000..=0xbfff => {
 				let addr = (addr - 0x8000) as usize | ((self.select as usize) << 14);
 				self.prg[addr]
@@ -182,23 +363,363 @@ impl Mapper for UxRom {
 		match addr {
 			0x0000..=0x1fff => self.chr.write_u8(addr, val),
 			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
 			0x8000..=0xffff => {
 				self.select = ((val as usize) % self.banks) as u8;
 			}
 			_ => (),
 		}
 	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// 32K prg bank read shared by the latch boards
+fn prg_32k(prg: &Memory, bank: usize, addr: u16) -> usize {
+	(bank * 0x8000 + (addr - 0x8000) as usize) % prg.size()
+}
+
+
+// 8K chr bank read shared by the latch boards
+fn chr_8k(chr: &Memory, bank: usize, addr: u16) -> usize {
+	(bank * CHR_BANK_SIZE + addr as usize) % chr.size()
+}
+
+
+// mapper 3
+#[derive(Debug)]
+pub struct CNRom {
+
+	// cpu prg rom, 16K or 32K fixed
+	prg: Memory,
+
+	// ppu pattern table, 8K switchable
+	chr: Memory,
+
+	// chr bank select
+	select: u8,
+
+	// the written value is ANDed with the rom byte at the same address
+	bus_conflicts: bool,
+
+	// nametable
+	name_table: NameTable,
+
+	// prg ram
+	prg_ram: PrgRam,
+}
+
+
+impl CNRom {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, bus_conflicts: bool) -> Self {
+		Self {
+			prg,
+			chr,
+			select: 0,
+			bus_conflicts,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+		}
+	}
+}
+
+
+impl Mapper for CNRom {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[chr_8k(&self.chr, self.select as usize, addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[prg_32k(&self.prg, 0, addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0x8000..=0xffff => {
+				let val = match self.bus_conflicts {
+					true => val & self.prg[prg_32k(&self.prg, 0, addr)],
+					false => val,
+				};
+				self.select = val;
+			},
+			_ => (),
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// mapper 7
+#[derive(Debug)]
+pub struct AxRom {
+
+	// cpu prg rom, 32K switchable
+	prg: Memory,
+
+	// ppu pattern table, usually 8K chr ram
+	chr: Memory,
+
+	// bits 0-2 prg bank, bit 4 single screen page
+	select: u8,
+
+	// ANROM/AMROM have bus conflicts, AOROM doesn't
+	bus_conflicts: bool,
+
+	// nametable
+	name_table: NameTable,
+
+	// prg ram
+	prg_ram: PrgRam,
+}
+
+
+impl AxRom {
+	pub fn new(prg: Memory, chr: Memory, prg_ram_size: usize, bus_conflicts: bool) -> Self {
+		Self {
+			prg,
+			chr,
+			select: 0,
+			bus_conflicts,
+			name_table: NameTable::new(MirroMode::SingleLower),
+			prg_ram: PrgRam::new(prg_ram_size),
+		}
+	}
+}
+
+
+impl Mapper for AxRom {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[chr_8k(&self.chr, 0, addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[prg_32k(&self.prg, (self.select & 0x0f) as usize, addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => {
+				let addr = chr_8k(&self.chr, 0, addr);
+				self.chr[addr] = val;
+			},
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0x8000..=0xffff => {
+				let val = match self.bus_conflicts {
+					true => val & self.read_u8(addr),
+					false => val,
+				};
+				self.select = val;
+				let mirror_mode = match val & 0x10 {
+					0 => MirroMode::SingleLower,
+					_ => MirroMode::SingleUpper,
+				};
+				self.name_table.set_mirror_mode(mirror_mode);
+			},
+			_ => (),
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// mapper 66
+#[derive(Debug)]
+pub struct GxRom {
+
+	// cpu prg rom, 32K switchable
+	prg: Memory,
+
+	// ppu pattern table, 8K switchable
+	chr: Memory,
+
+	// bits 4-5 prg bank, bits 0-1 chr bank
+	select: u8,
+
+	// nametable
+	name_table: NameTable,
+
+	// prg ram
+	prg_ram: PrgRam,
+}
+
+
+impl GxRom {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize) -> Self {
+		Self {
+			prg,
+			chr,
+			select: 0,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+		}
+	}
+}
+
+
+impl Mapper for GxRom {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[chr_8k(&self.chr, (self.select & 0x03) as usize, addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[prg_32k(&self.prg, ((self.select >> 4) & 0x03) as usize, addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			// the latch always sees bus conflicts
+			0x8000..=0xffff => self.select = val & self.read_u8(addr),
+			_ => (),
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// mapper 11
+#[derive(Debug)]
+pub struct ColorDreams {
+
+	// cpu prg rom, 32K switchable
+	prg: Memory,
+
+	// ppu pattern table, 8K switchable
+	chr: Memory,
+
+	// bits 0-1 prg bank, bits 4-7 chr bank
+	select: u8,
+
+	// nametable
+	name_table: NameTable,
+
+	// prg ram
+	prg_ram: PrgRam,
+}
+
+
+impl ColorDreams {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize) -> Self {
+		Self {
+			prg,
+			chr,
+			select: 0,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+		}
+	}
+}
+
+
+impl Mapper for ColorDreams {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[chr_8k(&self.chr, (self.select >> 4) as usize, addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[prg_32k(&self.prg, (self.select & 0x03) as usize, addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			// the latch always sees bus conflicts
+			0x8000..=0xffff => self.select = val & self.read_u8(addr),
+			_ => (),
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+// boards built on the MMC3 core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MMC3Board {
+	// mapper 4
+	TxROM,
+	// mapper 206, Namco 108 without irq, mirroring control or mode bits
+	DxROM,
+	// mapper 118, bit 7 of the chr banks picks the nametable page
+	TxSROM,
+	// mapper 119, bit 6 of the chr banks picks 8K chr ram
+	TQROM,
+}
+
+
+// the irq counter behaves differently between chip revisions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MMC3Revision {
+	// irq only when the counter is decremented to 0 or reloaded through $C001
+	A,
+	// irq whenever the counter is 0 after a clock
+	B,
 }
 
 
 // mapper 4
 #[derive(Debug)]
 pub struct MMC3 {
+	// board variant
+	board: MMC3Board,
+	revision: MMC3Revision,
 	// prg rom
 	prg: Memory,
 	prg_banks: usize,
 	// ppu pattern table
 	chr: Memory,
+	// TQROM chr ram
+	chr_ram: Memory,
 	// nametable
 	name_table: NameTable,
 	// register select
@@ -214,31 +735,198 @@ pub struct MMC3 {
 	// irq functions
 	irq_reload_value: u8,
 	irq_counter: u8,
+	irq_reload: bool,
 	irq_enabled: bool,
 	prev_a12: u16,
+	// prg ram
+	prg_ram: PrgRam,
 }
 
 
 impl MMC3 {
-	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, irq: Signal) -> Self {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, irq: Signal) -> Self {
 		let banks = prg.size() / 8192;
 		Self {
-			prg: prg,
+			board: MMC3Board::TxROM,
+			revision: MMC3Revision::B,
+			prg,
 			prg_banks: banks,
-			chr: chr,
+			chr,
+			chr_ram: Memory::new(0),
 			name_table: NameTable::new(mode),
 			reg_select: 0,
 			regs: [0; 8],
 			prg_bank_mode: 0,
 			chr_inversion: false,
-			irq: irq,
+			irq,
 			irq_reload_value: 0,
 			irq_counter: 0,
+			irq_reload: false,
 			irq_enabled: false,
 			prev_a12: 0,
+			prg_ram: PrgRam::new(prg_ram_size),
 		}
 	}
-}
+
+	pub fn with_board(mut self, board: MMC3Board) -> Self {
+		self.board = board;
+		if board == MMC3Board::TQROM {
+			self.chr_ram = Memory::new(CHR_BANK_SIZE);
+		}
+		self.update_name_table();
+		self
+	}
+
+	pub fn with_revision(mut self, revision: MMC3Revision) -> Self {
+		self.revision = revision;
+		self
+	}
+
+	// 1K chr bank register value behind a pattern table address
+	fn chr_bank(&self, addr: u16) -> u8 {
+		let mut slot = (addr >> 10) as usize & 0x07;
+		if self.chr_inversion {
+			slot ^= 0x04;
+		}
+		match slot {
+			// two 2K banks, R0 and R1 ignore the bottom bit
+			0..=3 => self.regs[slot >> 1] | (slot & 0x01) as u8,
+			// four 1K banks, R2-R5
+			_ => self.regs[slot - 2],
+		}
+	}
+
+	// returns true and the chr ram offset for TQROM ram banks
+	fn chr_addr(&self, addr: u16) -> (bool, usize) {
+		let bank = self.chr_bank(addr);
+		let offset = (addr & 0x03ff) as usize;
+		match self.board == MMC3Board::TQROM && bank & 0x40 != 0 {
+			true => (true, (((bank & 0x07) as usize) << 10 | offset) % self.chr_ram.size()),
+			false => (false, ((bank as usize) << 10 | offset) % self.chr.size()),
+		}
+	}
+
+	fn prg_addr(&self, addr: u16) -> usize {
+		let bank = match (self.prg_bank_mode, addr) {
+			// $8000 swappable, $C000 fixed to the second last bank
+			(0, 0x8000..=0x9fff) => self.regs[6] as usize,
+			(0, 0xc000..=0xdfff) => self.prg_banks - 2,
+			// $C000 swappable, $8000 fixed to the second last bank
+			(_, 0x8000..=0x9fff) => self.prg_banks - 2,
+			(_, 0xc000..=0xdfff) => self.regs[6] as usize,
+			(_, 0xa000..=0xbfff) => self.regs[7] as usize,
+			_ => self.prg_banks - 1,
+		};
+		(bank % self.prg_banks) << 13 | (addr & 0x1fff) as usize
+	}
+
+	// TxSROM wires chr A17 to the nametable A10, one page per 1K of the $0000 pattern table
+	fn update_name_table(&mut self) {
+		if self.board != MMC3Board::TxSROM {
+			return;
+		}
+		for quadrant in 0..4 {
+			let page = (self.chr_bank((quadrant as u16) << 10) >> 7) as usize;
+			self.name_table.set_page(quadrant, NameTablePage::Ciram(page));
+		}
+	}
+
+	// clocked on a rising A12
+	fn clock_irq(&mut self) {
+		let reload = self.irq_counter == 0 || self.irq_reload;
+		match reload {
+			true => self.irq_counter = self.irq_reload_value,
+			false => self.irq_counter -= 1,
+		}
+		let fire = match self.revision {
+			MMC3Revision::A => self.irq_counter == 0 && (!reload || self.irq_reload),
+			MMC3Revision::B => self.irq_counter == 0,
+		};
+		self.irq_reload = false;
+		if fire && self.irq_enabled {
+			board::set_irq(&self.irq, IRQ_MAPPER, true);
+		}
+	}
+
+	fn write_register(&mut self, addr: u16, val: u8) {
+		let even = addr & 1 == 0;
+		// the Namco 108 only decodes $8000-$9FFF
+		if self.board == MMC3Board::DxROM && addr >= 0xa000 {
+			return;
+		}
+		if even {
+			match addr {
+				// Bank select ($8000-$9FFE, even)
+				0x8000..=0x9ffe => {
+					self.reg_select = val & 0x07;
+					if self.board != MMC3Board::DxROM {
+						self.prg_bank_mode = (val & 0x40) >> 6;
+						self.chr_inversion = (val & 0x80) != 0;
+					}
+					self.update_name_table();
+				},
+				// Mirroring ($A000-$BFFE, even)
+				0xa000..=0xbffe => {
+					// TxSROM mirroring follows the chr banks
+					if self.board == MMC3Board::TxSROM {
+						return;
+					}
+					let mirror_mode = match val & 0x01 {
+						0 => MirroMode::Vertical,
+						_ => MirroMode::Horizontal,
+					};
+					self.name_table.set_mirror_mode(mirror_mode);
+				},
+				// IRQ latch ($C000-$DFFE, even)
+				0xc000..=0xdffe => {
+					self.irq_reload_value = val;
+				}
+				// IRQ disable ($E000-$FFFE, even), also acknowledges
+				0xe000..=0xfffe => {
+					self.irq_enabled = false;
+					board::set_irq(&self.irq, IRQ_MAPPER, false);
+				},
+				_ => (),
+			}
+		} else {
+			match addr {
+				// Bank data ($8001-$9FFF, odd)
+				0x8001..=0x9fff => {
+					// R6 and R7 will ignore the top two bits
+					// R0 and R1 ignore the bottom bit
+					let val = match self.reg_select {
+						6 | 7 => val & 0x3f,
+						0 | 1 => val & 0xfe,
+						_ => val,
+					};
+					// the Namco 108 has 6 chr and 4 prg bank lines
+					let val = match (self.board, self.reg_select) {
+						(MMC3Board::DxROM, 6 | 7) => val & 0x0f,
+						(MMC3Board::DxROM, _) => val & 0x3f,
+						_ => val,
+					};
+					self.regs[self.reg_select as usize] = val;
+					self.update_name_table();
+				},
+				// PRG RAM protect ($A001-$BFFF, odd)
+				0xa001..=0xbfff => {
+					self.prg_ram.set_enabled(val & 0x80 != 0);
+					self.prg_ram.set_writable(val & 0x40 == 0);
+				},
+				// IRQ reload ($C001-$DFFF, odd)
+				0xc001..=0xdfff => {
+					self.irq_counter = 0;
+					self.irq_reload = true;
+				},
+				// IRQ enable ($E001-$FFFF, odd)
+				0xe001..=0xffff => {
+					self.irq_enabled = true;
+				},
+				_ => (),
+			}
+		}
+	}
+}
 
 
 impl Mapper for MMC3 {
@@ -249,138 +937,2105 @@ impl Mapper for MMC3 {
 			0x0000..=0x1fff => {
 				// irq A12 handle
 				let a12 = addr & 0x1000;
-				if self.prev_a12 == 0 && a12 != 0 {
+				if self.prev_a12 == 0 && a12 != 0 && self.board != MMC3Board::DxROM {
 					// a12 low -> high
-					match self.irq_counter {
-						0 => self.irq_counter = self.irq_reload_value,
-						_ => {
-							self.irq_counter -= 1;
-							if self.irq_counter == 0 && self.irq_enabled {
-								*self.irq.borrow_mut() = 1;
-							}
-						},
-					}
+					self.clock_irq();
 				}
 				self.prev_a12 = a12;
 
-				let real_addr: usize = if !self.chr_inversion {
-					match addr {
-						// 0
-						0x0000..=0x07ff => (addr - 0x0000) as usize + ((self.regs[0] as usize) << 10),
-						0x0800..=0x0fff => (addr - 0x0800) as usize + ((self.regs[1] as usize) << 10),
-						// 1
-						0x1000..=0x13ff => (addr - 0x1000) as usize + ((self.regs[2] as usize) << 10),
-						0x1400..=0x17ff => (addr - 0x1400) as usize + ((self.regs[3] as usize) << 10),
-						0x1800..=0x1bff => (addr - 0x1800) as usize + ((self.regs[4] as usize) << 10),
-						0x1c00..=0x1fff => (addr - 0x1c00) as usize + ((self.regs[5] as usize) << 10),
-						_ => panic!("bad mmc3 addr {:#06x}", addr),
-					}
-				} else {
-					match addr {
-						// 0
-						0x0000..=0x03ff => (addr - 0x0000) as usize + ((self.regs[2] as usize) << 10),
-						0x0400..=0x07ff => (addr - 0x0400) as usize + ((self.regs[3] as usize) << 10),
-						0x0800..=0x0bff => (addr - 0x0800) as usize + ((self.regs[4] as usize) << 10),
-						0x0c00..=0x0fff => (addr - 0x0c00) as usize + ((self.regs[5] as usize) << 10),
-						// 1
-						0x1000..=0x17ff => (addr - 0x1000) as usize + ((self.regs[0] as usize) << 10),
-						0x1800..=0x1fff => (addr - 0x1800) as usize + ((self.regs[1] as usize) << 10),
-						_ => panic!("bad mmc3 addr {:#06x}", addr),
-					}
-				};
-				self.chr[real_addr]
-			},
-			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000),
-			0x8000..=0xffff => {
-				let real_addr: usize = if self.prg_bank_mode == 0 {
-					match addr {
-						// 0
-						0x8000..=0x9fff => (addr - 0x8000) as usize + ((self.regs[6] as usize) << 13),
-						0xa000..=0xbfff => (addr - 0xa000) as usize + ((self.regs[7] as usize) << 13),
-						// 1
-						0xc000..=0xdfff => (addr - 0xc000) as usize + ((self.prg_banks - 2) << 13),
-						0xe000..=0xffff => (addr - 0xe000) as usize + ((self.prg_banks - 1) << 13),
-						_ => panic!("bad mmc3 addr {:#06x}", addr),
-					}
-				} else {
-					match addr {
-						// 0
-						0x8000..=0x9fff => (addr - 0x8000) as usize + ((self.prg_banks - 2) << 13),
-						0xa000..=0xbfff => (addr - 0xa000) as usize + ((self.regs[7] as usize) << 13),
-						// 1
-						0xc000..=0xdfff => (addr - 0xc000) as usize + ((self.regs[6] as usize) << 13),
-						0xe000..=0xffff => (addr - 0xe000) as usize + ((self.prg_banks - 1) << 13),
-						_ => panic!("bad mmc3 addr {:#06x}", addr),
-					}
-				};
-				self.prg[real_addr]
+				match self.chr_addr(addr) {
+					(true, addr) => self.chr_ram[addr],
+					(false, addr) => self.chr[addr],
+				}
 			},
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[self.prg_addr(addr)],
 			_ => 0,
 		}
 	}
 
 	fn write_u8(&mut self, addr: u16, val: u8) {
 		match addr {
-			0x0000..=0x1fff => (),
+			// only TQROM has chr ram
+			0x0000..=0x1fff => {
+				if let (true, addr) = self.chr_addr(addr) {
+					self.chr_ram[addr] = val;
+				}
+			},
 			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0x8000..=0xffff => self.write_register(addr, val),
+			_ => (),
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+// mapper 1
+//
+// registers are loaded one bit at a time through a 5 bit shift register,
+// the fifth write to $8000-$FFFF commits the value to the register picked by the address
+#[derive(Debug)]
+pub struct MMC1 {
+	// prg rom, 16K banks
+	prg: Memory,
+	prg_banks: usize,
+	// ppu pattern table, 4K banks
+	chr: Memory,
+	// nametable
+	name_table: NameTable,
+	// prg ram, banked in 8K units on SOROM/SXROM
+	prg_ram: PrgRam,
+	// serial load
+	shift: u8,
+	// $8000 control: mirroring, prg mode, chr mode
+	control: u8,
+	// $A000 and $C000 chr banks, the upper bits select prg/prg ram banks on SxROM boards
+	chr_banks: [u8; 2],
+	// $E000 prg bank
+	prg_bank: u8,
+	// last pattern table half the ppu read from, picks the chr register used for SxROM banking
+	chr_a12: bool,
+	// SNROM disables prg ram with chr bit 4
+	snrom: bool,
+	// cpu cycle counter, writes on consecutive cycles are ignored
+	cycle: u64,
+	last_write: Option<u64>,
+}
+
+
+impl MMC1 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize) -> Self {
+		let banks = prg.size() / PRG_BANK_SIZE;
+		Self {
+			prg,
+			prg_banks: banks,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+			shift: 0x10,
+			// power up in prg mode 3, last bank fixed at $C000
+			control: 0x0c,
+			chr_banks: [0; 2],
+			prg_bank: 0,
+			chr_a12: false,
+			snrom: false,
+			cycle: 0,
+			last_write: None,
+		}
+	}
+
+	pub fn with_snrom(mut self) -> Self {
+		self.snrom = true;
+		self
+	}
+
+	// chr register driving the SxROM extra lines
+	fn chr_select(&self) -> u8 {
+		match self.control & 0x10 != 0 && self.chr_a12 {
+			true => self.chr_banks[1],
+			false => self.chr_banks[0],
+		}
+	}
+
+	fn prg_addr(&self, addr: u16) -> usize {
+		// SUROM/SXROM, 512K prg selects the 256K half with chr bit 4
+		let outer = match self.prg_banks > 16 {
+			true => (self.chr_select() & 0x10) as usize,
+			false => 0,
+		};
+		let bank = (self.prg_bank & 0x0f) as usize;
+		let upper = addr >= 0xc000;
+		let bank = match (self.control >> 2) & 0x03 {
+			// 32K mode, low bit ignored
+			0 | 1 => (bank & !1) | upper as usize,
+			// first bank fixed at $8000
+			2 => if upper { bank } else { 0 },
+			// last bank fixed at $C000
+			_ => if upper { 0x0f } else { bank },
+		};
+		((outer | bank) % self.prg_banks) * PRG_BANK_SIZE + (addr & 0x3fff) as usize
+	}
+
+	fn chr_addr(&self, addr: u16) -> usize {
+		let bank = match self.control & 0x10 {
+			// 8K mode, low bit ignored
+			0 => (self.chr_banks[0] & !1) as usize | (addr >> 12) as usize,
+			// two 4K banks
+			_ => self.chr_banks[(addr >> 12) as usize] as usize,
+		};
+		(bank << 12 | (addr & 0x0fff) as usize) % self.chr.size()
+	}
+
+	// SNROM wires chr bit 4 to a second prg ram enable
+	fn prg_ram_gated(&self) -> bool {
+		self.snrom && self.chr_select() & 0x10 != 0
+	}
+
+	fn prg_ram_offset(&self, addr: u16) -> usize {
+		// SOROM has 16K selected by bit 3, SXROM 32K selected by bits 2-3
+		let bank = match self.prg_ram.memory().map_or(0, |ram| ram.size()) {
+			0x4000 => (self.chr_select() >> 3) & 0x01,
+			0x8000 => (self.chr_select() >> 2) & 0x03,
+			_ => 0,
+		};
+		(bank as usize) << 13 | (addr - 0x6000) as usize
+	}
+
+	fn set_control(&mut self, val: u8) {
+		self.control = val;
+		let mirror_mode = match val & 0x03 {
+			0 => MirroMode::SingleLower,
+			1 => MirroMode::SingleUpper,
+			2 => MirroMode::Vertical,
+			_ => MirroMode::Horizontal,
+		};
+		self.name_table.set_mirror_mode(mirror_mode);
+	}
+
+	fn write_register(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x8000..=0x9fff => self.set_control(val),
+			0xa000..=0xbfff => self.chr_banks[0] = val,
+			0xc000..=0xdfff => self.chr_banks[1] = val,
 			_ => {
-				let even = addr % 2 == 0;
-				if even {
-					match addr {
-						// Bank select ($8000-$9FFE, even)
-						0x8000..=0x9ffe => {
-							self.reg_select = val & 0x07;
-							self.prg_bank_mode = (val & 0x40) >> 6;
-							self.chr_inversion = (val & 0x80) != 0;
-						},
-						// Mirroring ($A000-$BFFE, even)
-						0xa000..=0xbffe => {
-							let mirror_mode = match val & 0x01 {
-								0 => MirroMode::Vertical,
-								_ => MirroMode::Horizontal,
-							};
-							self.name_table.set_mirror_mode(mirror_mode);
-						},
-						// IRQ latch ($C000-$DFFE, even)
-						0xc000..=0xdffe => {
-							self.irq_reload_value = val;
-							// self.irq_counter = 0;
-						}
-						// IRQ disable ($E000-$FFFE, even)
-						0xe000..=0xfffe => {
-							self.irq_enabled = false;
-						},
-						_ => (),
-					}
-				} else {
-					match addr {
-						// Bank data ($8001-$9FFF, odd)
-						0x8001..=0x9fff => {
-							// R6 and R7 will ignore the top two bits
-							// R0 and R1 ignore the bottom bit
-							let val = match self.reg_select {
-								6 | 7 => val & 0x3f,
-								0 | 1 => val & 0xfe,
-								_ => val,
-							};
-							self.regs[self.reg_select as usize] = val;
-						},
-						// PRG RAM protect ($A001-$BFFF, odd)
-						0xa001..=0xbfff => {
-							// do nothing for now
-						},
-						// IRQ reload ($C001-$DFFF, odd)
-						0xc001..=0xdfff => {
-							self.irq_counter = 0;
-						},
-						// IRQ enable ($E001-$FFFF, odd)
-						0xe001..=0xffff => {
-							self.irq_enabled = true;
-						},
-						_ => (),
-					}
+				self.prg_bank = val;
+				// bit 4 clear enables prg ram
+				self.prg_ram.set_enabled(val & 0x10 == 0);
+			},
+		}
+	}
+}
+
+
+impl Mapper for MMC1 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => {
+				self.chr_a12 = addr & 0x1000 != 0;
+				self.chr[self.chr_addr(addr)]
+			},
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff if self.prg_ram_gated() => 0,
+			0x6000..=0x7fff => self.prg_ram.read_u8(self.prg_ram_offset(addr)),
+			0x8000..=0xffff => self.prg[self.prg_addr(addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => {
+				let addr = self.chr_addr(addr);
+				self.chr[addr] = val;
+			},
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff if self.prg_ram_gated() => (),
+			0x6000..=0x7fff => {
+				let offset = self.prg_ram_offset(addr);
+				self.prg_ram.write_u8(offset, val);
+			},
+			0x8000..=0xffff => {
+				// the second write of a read-modify-write instruction is ignored
+				let consecutive = self.last_write.is_some_and(|cycle| self.cycle - cycle < 2);
+				self.last_write = Some(self.cycle);
+				if consecutive {
+					return;
+				}
+				if val & 0x80 != 0 {
+					// reset the shift register and lock the last bank at $C000
+					self.shift = 0x10;
+					self.control |= 0x0c;
+					return;
 				}
+				// the marker bit reaches bit 0 on the fifth write
+				let full = self.shift & 0x01 != 0;
+				self.shift = (self.shift >> 1) | ((val & 0x01) << 4);
+				if full {
+					self.write_register(addr, self.shift);
+					self.shift = 0x10;
+				}
+			},
+			_ => (),
+		}
+	}
+
+	fn tick(&mut self) {
+		self.cycle += 1;
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// mappers 9 and 10
+//
+// two 4K chr banks per pattern table half, picked by a latch the ppu flips
+// when it fetches the high plane of tile $FD or $FE
+#[derive(Debug)]
+pub struct MMC2 {
+	// prg rom
+	prg: Memory,
+	// ppu pattern table
+	chr: Memory,
+	// nametable
+	name_table: NameTable,
+	// prg ram, MMC4 boards carry 8K
+	prg_ram: PrgRam,
+	// MMC4 switches 16K prg and latches on the whole tile row of both halves
+	mmc4: bool,
+	// $A000 prg bank
+	prg_bank: u8,
+	// $B000-$E000, the FD and FE banks for $0000, then for $1000
+	chr_banks: [u8; 4],
+	// per half, false selects the FD bank and true the FE bank
+	latches: [bool; 2],
+}
+
+
+impl MMC2 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, mmc4: bool) -> Self {
+		Self {
+			prg,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+			mmc4,
+			prg_bank: 0,
+			chr_banks: [0; 4],
+			latches: [true; 2],
+		}
+	}
+
+	fn prg_addr(&self, addr: u16) -> usize {
+		let size = self.prg.size();
+		let offset = match self.mmc4 {
+			// 16K switchable, last 16K fixed
+			true => match addr {
+				0x8000..=0xbfff => (self.prg_bank as usize) << 14 | (addr & 0x3fff) as usize,
+				_ => size.wrapping_sub(0x4000) | (addr & 0x3fff) as usize,
+			},
+			// 8K switchable, last three 8K fixed
+			false => match addr {
+				0x8000..=0x9fff => (self.prg_bank as usize) << 13 | (addr & 0x1fff) as usize,
+				_ => size.wrapping_sub(0x8000) + (addr - 0x8000) as usize,
+			},
+		};
+		offset % size
+	}
+
+	// flip the latch after a fetch from the trigger tiles
+	fn update_latch(&mut self, addr: u16) {
+		let half = (addr >> 12) as usize;
+		// MMC2 only latches on the exact first row for the $0000 half
+		let addr = match !self.mmc4 && half == 0 {
+			true => addr,
+			false => addr & 0x1ff8,
+		};
+		match addr & 0x0fff {
+			0x0fd8 => self.latches[half] = false,
+			0x0fe8 => self.latches[half] = true,
+			_ => (),
+		}
+	}
+}
+
+
+impl Mapper for MMC2 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => {
+				let half = (addr >> 12) as usize;
+				let bank = self.chr_banks[half * 2 + self.latches[half] as usize] as usize;
+				let val = self.chr[(bank << 12 | (addr & 0x0fff) as usize) % self.chr.size()];
+				// the fetch that trips the latch still sees the old bank
+				self.update_latch(addr);
+				val
+			},
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[self.prg_addr(addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0xa000..=0xafff => self.prg_bank = val & 0x0f,
+			0xb000..=0xefff => self.chr_banks[((addr - 0xb000) >> 12) as usize] = val & 0x1f,
+			0xf000..=0xffff => {
+				let mirror_mode = match val & 0x01 {
+					0 => MirroMode::Vertical,
+					_ => MirroMode::Horizontal,
+				};
+				self.name_table.set_mirror_mode(mirror_mode);
+			},
+			_ => (),
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// mapper 5
+//
+// the MMC5 watches the ppu bus to find scanlines: the ppu reads the same nametable
+// byte three times in a row at the end of each line. from that it keeps its own tile
+// counter for the vertical split and extended attributes.
+#[derive(Debug)]
+pub struct MMC5 {
+	// prg rom, 8K banks
+	prg: Memory,
+	// ppu pattern table, 1K banks
+	chr: Memory,
+	// ciram nametables
+	name_table: NameTable,
+	// prg ram, 8K banks at $6000-$7FFF and optionally $8000-$DFFF
+	prg_ram: PrgRam,
+	// 1K internal ram at $5C00
+	exram: Memory,
+	// irq signal line
+	irq: Signal,
+	// $5100 and $5101
+	prg_mode: u8,
+	chr_mode: u8,
+	// $5102/$5103 have to hold 2 and 1 to write prg ram
+	prg_ram_protect: [u8; 2],
+	// $5104
+	exram_mode: u8,
+	// $5105, two bits per quadrant: ciram 0, ciram 1, exram, fill
+	nt_mapping: u8,
+	// $5106/$5107
+	fill_tile: u8,
+	fill_attr: u8,
+	// $5113-$5117
+	prg_banks: [u8; 5],
+	// $5120-$5127 sprite set and $5128-$512B background set, with the $5130 bits applied
+	chr_banks: [u16; 12],
+	// $5130
+	chr_upper: u8,
+	// the background set was written last, used for 8x8 sprites and $2007
+	chr_last_bg: bool,
+	// $5200-$5202
+	split_control: u8,
+	split_scroll: u8,
+	split_bank: u8,
+	// $5203/$5204
+	irq_compare: u8,
+	irq_enabled: bool,
+	irq_pending: bool,
+	// $5205/$5206
+	multiplicand: u8,
+	multiplier: u8,
+	// ppu state seen through $2000/$2001 writes
+	tall_sprites: bool,
+	rendering: bool,
+	// scanline detection
+	in_frame: bool,
+	scanline: u8,
+	last_nt_addr: u16,
+	nt_repeats: u8,
+	idle_cycles: u8,
+	// background tile fetched on the current line, 34 and 35 are the first tiles of the next line
+	tile: u8,
+	// exram byte of the current tile in extended attribute mode
+	ext_attr: u8,
+	// the current tile comes from the split region
+	in_split: bool,
+}
+
+
+impl MMC5 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, irq: Signal) -> Self {
+		let mut prg_ram = PrgRam::new(prg_ram_size);
+		prg_ram.set_writable(false);
+		Self {
+			prg,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram,
+			exram: Memory::new(1024),
+			irq,
+			// power up with 8K banks and the last bank at $E000
+			prg_mode: 3,
+			chr_mode: 0,
+			prg_ram_protect: [0; 2],
+			exram_mode: 0,
+			nt_mapping: 0,
+			fill_tile: 0,
+			fill_attr: 0,
+			prg_banks: [0, 0xff, 0xff, 0xff, 0xff],
+			chr_banks: [0; 12],
+			chr_upper: 0,
+			chr_last_bg: false,
+			split_control: 0,
+			split_scroll: 0,
+			split_bank: 0,
+			irq_compare: 0,
+			irq_enabled: false,
+			irq_pending: false,
+			multiplicand: 0xff,
+			multiplier: 0xff,
+			tall_sprites: false,
+			rendering: false,
+			in_frame: false,
+			scanline: 0,
+			last_nt_addr: 0,
+			nt_repeats: 0,
+			idle_cycles: 0,
+			tile: 0,
+			ext_attr: 0,
+			in_split: false,
+		}
+	}
+
+	// the register and 8K bank behind a $8000-$FFFF address, bit 7 of the register selects rom
+	fn prg_bank(&self, addr: u16) -> (u8, usize) {
+		let slot = ((addr - 0x8000) >> 13) as usize;
+		// register index into prg_banks and the low bits taken from the address
+		let (reg, low) = match self.prg_mode {
+			0 => (4, 0x03),
+			1 => (if slot < 2 { 2 } else { 4 }, 0x01),
+			2 => match slot {
+				0 | 1 => (2, 0x01),
+				2 => (3, 0x00),
+				_ => (4, 0x00),
+			},
+			_ => (slot + 1, 0x00),
+		};
+		let val = self.prg_banks[reg];
+		// $5117 always maps rom
+		let val = if reg == 4 { val | 0x80 } else { val };
+		(val, ((val & 0x7f) as usize & !low) | (slot & low))
+	}
+
+	fn read_prg(&self, addr: u16) -> u8 {
+		let (val, bank) = self.prg_bank(addr);
+		match val & 0x80 {
+			0 => self.prg_ram.read_u8(bank << 13 | (addr & 0x1fff) as usize),
+			_ => self.prg[(bank << 13 | (addr & 0x1fff) as usize) % self.prg.size()],
+		}
+	}
+
+	fn write_prg(&mut self, addr: u16, val: u8) {
+		let (bank_val, bank) = self.prg_bank(addr);
+		if bank_val & 0x80 == 0 {
+			self.prg_ram.write_u8(bank << 13 | (addr & 0x1fff) as usize, val);
+		}
+	}
+
+	// pattern table address for the sprite or background register set
+	fn chr_addr(&self, addr: u16, background: bool) -> usize {
+		// 8K, 4K, 2K or 1K banks
+		let shift = 13 - self.chr_mode as usize;
+		let slot = (addr as usize) >> shift;
+		let reg = match (background, self.chr_mode) {
+			(false, mode) => ((slot + 1) << (3 - mode)) - 1,
+			(true, 0 | 1) => 11,
+			(true, 2) => 9 + (slot & 0x01) * 2,
+			(true, _) => 8 + (slot & 0x03),
+		};
+		((self.chr_banks[reg] as usize) << shift | (addr as usize & ((1 << shift) - 1))) % self.chr.size()
+	}
+
+	// with 8x16 sprites the sets are split by fetch type, otherwise the last written set is used
+	fn chr_background_set(&self, fetch: PpuFetch) -> bool {
+		match (self.tall_sprites && self.rendering, fetch) {
+			(true, PpuFetch::Sprite) => false,
+			(true, PpuFetch::Background) => true,
+			_ => self.chr_last_bg,
+		}
+	}
+
+	fn read_nametable(&self, addr: u16) -> u8 {
+		let offset = (addr & 0x03ff) as usize;
+		match (self.nt_mapping >> (((addr >> 10) & 0x03) * 2)) & 0x03 {
+			0 | 1 => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			2 => match self.exram_mode {
+				0 | 1 => self.exram[offset],
+				_ => 0,
+			},
+			_ => match offset {
+				0x3c0.. => (self.fill_attr & 0x03) * 0x55,
+				_ => self.fill_tile,
+			},
+		}
+	}
+
+	fn write_nametable(&mut self, addr: u16, val: u8) {
+		match (self.nt_mapping >> (((addr >> 10) & 0x03) * 2)) & 0x03 {
+			0 | 1 => self.name_table.write_u8(addr - 0x2000, val),
+			2 if self.exram_mode <= 1 => self.exram[(addr & 0x03ff) as usize] = val,
+			_ => (),
+		}
+	}
+
+	fn set_nt_mapping(&mut self, val: u8) {
+		self.nt_mapping = val;
+		for quadrant in 0..4 {
+			let page = (val >> (quadrant * 2)) as usize & 0x03;
+			// exram and fill quadrants are handled before the nametable is consulted
+			self.name_table.set_page(quadrant, NameTablePage::Ciram(page & 0x01));
+		}
+	}
+
+	// count scanlines on the third identical nametable fetch
+	fn detect_scanline(&mut self, addr: u16) {
+		if addr != self.last_nt_addr {
+			self.last_nt_addr = addr;
+			self.nt_repeats = 0;
+			return;
+		}
+		self.nt_repeats += 1;
+		if self.nt_repeats != 2 {
+			return;
+		}
+		// this is the fetch of the third tile of the line
+		self.tile = 2;
+		if !self.in_frame {
+			self.in_frame = true;
+			self.scanline = 0;
+		} else {
+			self.scanline = self.scanline.wrapping_add(1);
+			if self.scanline == self.irq_compare {
+				self.irq_pending = true;
+				self.update_irq();
 			}
-		}	
+		}
+	}
+
+	// the irq line follows the pending flag while enabled
+	fn update_irq(&self) {
+		board::set_irq(&self.irq, IRQ_MAPPER, self.irq_pending && self.irq_enabled);
+	}
+
+	// leave the frame when the ppu stops rendering
+	fn end_frame(&mut self) {
+		self.in_frame = false;
+		self.last_nt_addr = 0;
+		self.nt_repeats = 0;
+	}
+
+	// the split region only applies to exram nametable and extended attribute modes
+	fn split_active(&self, tile: u8) -> bool {
+		if self.split_control & 0x80 == 0 || self.exram_mode > 1 || tile >= 32 {
+			return false;
+		}
+		let threshold = self.split_control & 0x1f;
+		match self.split_control & 0x40 {
+			0 => tile < threshold,
+			_ => tile >= threshold,
+		}
+	}
+
+	// split row for the tile being fetched, the last two fetches of a line belong to the next one
+	fn split_y(&self) -> usize {
+		let line = self.scanline as usize + (self.tile >= 34) as usize;
+		(self.split_scroll as usize + line + 239) % 240
+	}
+
+	fn fetch_nametable(&mut self, addr: u16) -> u8 {
+		self.detect_scanline(addr);
+		let tile = match self.tile {
+			34.. => self.tile - 34,
+			tile => tile,
+		};
+		self.tile = self.tile.saturating_add(1);
+		self.in_split = self.split_active(tile);
+		if self.in_split {
+			let coarse_y = self.split_y() / 8;
+			let offset = coarse_y * 32 + tile as usize;
+			// the attribute fetch picks up the palette from here
+			let shift = ((coarse_y & 0x02) << 1) | (tile as usize & 0x02);
+			let attr = self.exram[0x3c0 + (coarse_y / 4) * 8 + tile as usize / 4];
+			self.ext_attr = ((attr >> shift) & 0x03) << 6;
+			return self.exram[offset];
+		}
+		if self.exram_mode == 1 {
+			self.ext_attr = self.exram[(addr & 0x03ff) as usize];
+		}
+		self.read_nametable(addr)
+	}
+
+	fn fetch_attribute(&mut self, addr: u16) -> u8 {
+		match self.in_split || self.exram_mode == 1 {
+			// palette for all four quadrants, the ppu picks one by position
+			true => (self.ext_attr >> 6) * 0x55,
+			false => self.read_nametable(addr),
+		}
+	}
+
+	fn fetch_background(&mut self, addr: u16) -> u8 {
+		let offset = if self.in_split {
+			// 4K split bank with the split fine scroll
+			let fine_y = self.split_y() & 0x07;
+			(self.split_bank as usize) << 12 | (addr as usize & 0x0ff8) | fine_y
+		} else if self.exram_mode == 1 {
+			// 4K bank per tile from exram
+			let bank = (self.ext_attr & 0x3f) as usize | ((self.chr_upper & 0x03) as usize) << 6;
+			bank << 12 | (addr & 0x0fff) as usize
+		} else {
+			self.chr_addr(addr, self.chr_background_set(PpuFetch::Background))
+		};
+		self.chr[offset % self.chr.size()]
+	}
+
+	fn read_register(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x5204 => {
+				let status = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+				self.irq_pending = false;
+				self.update_irq();
+				status
+			},
+			0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+			0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+			// exram is only readable in the ram modes
+			0x5c00..=0x5fff if self.exram_mode >= 2 => self.exram[(addr - 0x5c00) as usize],
+			_ => 0,
+		}
+	}
+
+	fn write_register(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x5100 => self.prg_mode = val & 0x03,
+			0x5101 => self.chr_mode = val & 0x03,
+			0x5102 | 0x5103 => {
+				self.prg_ram_protect[(addr - 0x5102) as usize] = val & 0x03;
+				self.prg_ram.set_writable(self.prg_ram_protect == [2, 1]);
+			},
+			0x5104 => self.exram_mode = val & 0x03,
+			0x5105 => self.set_nt_mapping(val),
+			0x5106 => self.fill_tile = val,
+			0x5107 => self.fill_attr = val & 0x03,
+			0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = val,
+			0x5120..=0x512b => {
+				let index = (addr - 0x5120) as usize;
+				self.chr_banks[index] = val as u16 | ((self.chr_upper & 0x03) as u16) << 8;
+				self.chr_last_bg = index >= 8;
+			},
+			0x5130 => self.chr_upper = val & 0x03,
+			0x5200 => self.split_control = val,
+			0x5201 => self.split_scroll = val,
+			0x5202 => self.split_bank = val,
+			0x5203 => self.irq_compare = val,
+			0x5204 => {
+				self.irq_enabled = val & 0x80 != 0;
+				self.update_irq();
+			},
+			0x5205 => self.multiplicand = val,
+			0x5206 => self.multiplier = val,
+			0x5c00..=0x5fff => {
+				let offset = (addr - 0x5c00) as usize;
+				match self.exram_mode {
+					// nametable modes only take writes while rendering
+					0 | 1 => self.exram[offset] = if self.in_frame { val } else { 0 },
+					2 => self.exram[offset] = val,
+					_ => (),
+				}
+			},
+			_ => (),
+		}
+	}
+}
+
+
+impl Mapper for MMC5 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[self.chr_addr(addr, self.chr_last_bg)],
+			0x2000..=0x3eff => self.read_nametable(addr),
+			0x5000..=0x5fff => self.read_register(addr),
+			0x6000..=0x7fff => self.prg_ram.read_u8(((self.prg_banks[0] & 0x0f) as usize) << 13 | (addr & 0x1fff) as usize),
+			0x8000..=0xffff => {
+				// the nmi vector fetch marks the end of the frame
+				if addr == 0xfffa || addr == 0xfffb {
+					self.end_frame();
+					self.irq_pending = false;
+					self.update_irq();
+				}
+				self.read_prg(addr)
+			},
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => {
+				let addr = self.chr_addr(addr, self.chr_last_bg);
+				self.chr[addr] = val;
+			},
+			0x2000..=0x3eff => self.write_nametable(addr, val),
+			0x5000..=0x5fff => self.write_register(addr, val),
+			0x6000..=0x7fff => {
+				let offset = ((self.prg_banks[0] & 0x0f) as usize) << 13 | (addr & 0x1fff) as usize;
+				self.prg_ram.write_u8(offset, val);
+			},
+			0x8000..=0xffff => self.write_prg(addr, val),
+			_ => (),
+		}
+	}
+
+	fn ppu_fetch(&mut self, addr: u16, fetch: PpuFetch) -> u8 {
+		self.idle_cycles = 0;
+		match fetch {
+			PpuFetch::NameTable => self.fetch_nametable(addr),
+			PpuFetch::Attribute => self.fetch_attribute(addr),
+			PpuFetch::Background => self.fetch_background(addr),
+			PpuFetch::Sprite => self.chr[self.chr_addr(addr, self.chr_background_set(fetch))],
+			PpuFetch::Data => self.read_u8(addr),
+		}
+	}
+
+	fn ppu_register_write(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x2000 => self.tall_sprites = val & 0x20 != 0,
+			0x2001 => {
+				self.rendering = val & 0x18 != 0;
+				if !self.rendering {
+					self.end_frame();
+				}
+			},
+			_ => (),
+		}
+	}
+
+	fn tick(&mut self) {
+		// the ppu stopped fetching, we are in vblank
+		if self.in_frame {
+			self.idle_cycles += 1;
+			if self.idle_cycles >= 3 {
+				self.end_frame();
+			}
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// irq counter shared by the Konami VRC chips
+//
+// counts cpu cycles directly or, in scanline mode, through a prescaler that
+// approximates one scanline every 341 / 3 cpu cycles
+#[derive(Debug)]
+struct VrcIrq {
+	irq: Signal,
+	latch: u8,
+	counter: u8,
+	prescaler: i16,
+	// enable after acknowledge
+	enable_after_ack: bool,
+	enabled: bool,
+	cycle_mode: bool,
+}
+
+
+impl VrcIrq {
+	fn new(irq: Signal) -> Self {
+		Self {
+			irq,
+			latch: 0,
+			counter: 0,
+			prescaler: 341,
+			enable_after_ack: false,
+			enabled: false,
+			cycle_mode: false,
+		}
+	}
+
+	fn write_latch(&mut self, val: u8) {
+		self.latch = val;
+	}
+
+	fn write_control(&mut self, val: u8) {
+		self.enable_after_ack = val & 0x01 != 0;
+		self.enabled = val & 0x02 != 0;
+		self.cycle_mode = val & 0x04 != 0;
+		board::set_irq(&self.irq, IRQ_MAPPER, false);
+		if self.enabled {
+			self.counter = self.latch;
+			self.prescaler = 341;
+		}
+	}
+
+	fn acknowledge(&mut self) {
+		self.enabled = self.enable_after_ack;
+		board::set_irq(&self.irq, IRQ_MAPPER, false);
+	}
+
+	fn clock_counter(&mut self) {
+		match self.counter {
+			0xff => {
+				self.counter = self.latch;
+				board::set_irq(&self.irq, IRQ_MAPPER, true);
+			},
+			_ => self.counter += 1,
+		}
+	}
+
+	// called once per cpu cycle
+	fn tick(&mut self) {
+		if !self.enabled {
+			return;
+		}
+		if self.cycle_mode {
+			self.clock_counter();
+			return;
+		}
+		self.prescaler -= 3;
+		if self.prescaler <= 0 {
+			self.prescaler += 341;
+			self.clock_counter();
+		}
+	}
+}
+
+
+// VRC6 pulse channel, 16 step duty with a 4 bit volume
+#[derive(Debug, Default)]
+struct VrcPulse {
+	volume: u8,
+	duty: u8,
+	// ignore the duty and output the volume
+	constant: bool,
+	period: u16,
+	enabled: bool,
+	timer: u16,
+	step: u8,
+}
+
+
+impl VrcPulse {
+	fn write_u8(&mut self, reg: u16, val: u8) {
+		match reg {
+			0 => {
+				self.volume = val & 0x0f;
+				self.duty = (val >> 4) & 0x07;
+				self.constant = val & 0x80 != 0;
+			},
+			1 => self.period = (self.period & 0x0f00) | val as u16,
+			_ => {
+				self.period = (self.period & 0x00ff) | ((val & 0x0f) as u16) << 8;
+				self.enabled = val & 0x80 != 0;
+				// disabling resets the duty cycle
+				if !self.enabled {
+					self.step = 15;
+				}
+			},
+		}
+	}
+
+	fn clock_timer(&mut self, shift: u8) {
+		if !self.enabled {
+			return;
+		}
+		match self.timer {
+			0 => {
+				self.timer = self.period >> shift;
+				self.step = self.step.wrapping_sub(1) & 0x0f;
+			},
+			_ => self.timer -= 1,
+		}
+	}
+
+	fn output(&self) -> u8 {
+		match self.enabled && (self.constant || self.step <= self.duty) {
+			true => self.volume,
+			false => 0,
+		}
+	}
+}
+
+
+// VRC6 sawtooth channel, an accumulator reset every seventh add
+#[derive(Debug, Default)]
+struct VrcSaw {
+	rate: u8,
+	period: u16,
+	enabled: bool,
+	timer: u16,
+	step: u8,
+	accumulator: u8,
+}
+
+
+impl VrcSaw {
+	fn write_u8(&mut self, reg: u16, val: u8) {
+		match reg {
+			0 => self.rate = val & 0x3f,
+			1 => self.period = (self.period & 0x0f00) | val as u16,
+			_ => {
+				self.period = (self.period & 0x00ff) | ((val & 0x0f) as u16) << 8;
+				self.enabled = val & 0x80 != 0;
+				if !self.enabled {
+					self.step = 0;
+					self.accumulator = 0;
+				}
+			},
+		}
+	}
+
+	fn clock_timer(&mut self, shift: u8) {
+		if !self.enabled {
+			return;
+		}
+		if self.timer > 0 {
+			self.timer -= 1;
+			return;
+		}
+		self.timer = self.period >> shift;
+		// the rate is added on every second clock, the 14th clock resets
+		self.step += 1;
+		if self.step == 14 {
+			self.step = 0;
+			self.accumulator = 0;
+		} else if self.step & 0x01 == 0 {
+			self.accumulator = self.accumulator.wrapping_add(self.rate);
+		}
+	}
+
+	fn output(&self) -> u8 {
+		self.accumulator >> 3
+	}
+}
+
+
+// a full volume VRC6 pulse is about as loud as a full volume apu pulse
+const VRC6_VOLUME: f32 = 0.15 / 15.0;
+
+
+// mappers 24 and 26
+#[derive(Debug)]
+pub struct VRC6 {
+	// prg rom, 16K bank at $8000 and 8K bank at $C000
+	prg: Memory,
+	// ppu pattern table, 1K banks
+	chr: Memory,
+	name_table: NameTable,
+	prg_ram: PrgRam,
+	// mapper 26 boards swap the A0 and A1 register lines
+	swap_lines: bool,
+	prg_banks: [u8; 2],
+	chr_banks: [u8; 8],
+	// $B003
+	ppu_mode: u8,
+	irq: VrcIrq,
+	// expansion audio
+	pulse1: VrcPulse,
+	pulse2: VrcPulse,
+	saw: VrcSaw,
+	// $9003
+	halt: bool,
+	freq_shift: u8,
+}
+
+
+impl VRC6 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, swap_lines: bool, irq: Signal) -> Self {
+		let mut prg_ram = PrgRam::new(prg_ram_size);
+		prg_ram.set_enabled(false);
+		Self {
+			prg,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram,
+			swap_lines,
+			prg_banks: [0; 2],
+			chr_banks: [0; 8],
+			ppu_mode: 0,
+			irq: VrcIrq::new(irq),
+			pulse1: VrcPulse::default(),
+			pulse2: VrcPulse::default(),
+			saw: VrcSaw::default(),
+			halt: false,
+			freq_shift: 0,
+		}
+	}
+
+	fn prg_addr(&self, addr: u16) -> usize {
+		let offset = match addr {
+			0x8000..=0xbfff => ((self.prg_banks[0] & 0x0f) as usize) << 14 | (addr & 0x3fff) as usize,
+			0xc000..=0xdfff => ((self.prg_banks[1] & 0x1f) as usize) << 13 | (addr & 0x1fff) as usize,
+			_ => self.prg.size().wrapping_sub(0x2000) | (addr & 0x1fff) as usize,
+		};
+		offset % self.prg.size()
+	}
+
+	// 2K bank from a 1K register, bit 5 of $B003 keeps the register's bottom bit
+	fn chr_2k(&self, reg: u8, addr: u16) -> usize {
+		match self.ppu_mode & 0x20 {
+			0 => (reg & 0xfe) as usize | (addr >> 10) as usize & 0x01,
+			_ => reg as usize,
+		}
+	}
+
+	fn chr_addr(&self, addr: u16) -> usize {
+		let slot = (addr >> 10) as usize & 0x07;
+		let bank = match self.ppu_mode & 0x03 {
+			// eight 1K banks
+			0 => self.chr_banks[slot] as usize,
+			// four 2K banks
+			1 => self.chr_2k(self.chr_banks[slot >> 1], addr),
+			// four 1K banks then two 2K banks
+			_ => match slot {
+				0..=3 => self.chr_banks[slot] as usize,
+				_ => self.chr_2k(self.chr_banks[4 + ((slot - 4) >> 1)], addr),
+			},
+		};
+		(bank << 10 | (addr & 0x03ff) as usize) % self.chr.size()
+	}
+
+	fn update_name_table(&mut self) {
+		let mirror = (self.ppu_mode >> 2) & 0x03;
+		// bit 4 takes the nametables from chr rom through R6 and R7
+		if self.ppu_mode & 0x10 != 0 {
+			let (r6, r7) = (self.chr_banks[6] as usize, self.chr_banks[7] as usize);
+			let pages = match mirror {
+				0 => [r6, r7, r6, r7],
+				1 => [r6, r6, r7, r7],
+				2 => [r6; 4],
+				_ => [r7; 4],
+			};
+			for (quadrant, bank) in pages.into_iter().enumerate() {
+				self.name_table.set_page(quadrant, NameTablePage::Chr(bank));
+			}
+			return;
+		}
+		let mirror_mode = match mirror {
+			0 => MirroMode::Vertical,
+			1 => MirroMode::Horizontal,
+			2 => MirroMode::SingleLower,
+			_ => MirroMode::SingleUpper,
+		};
+		self.name_table.set_mirror_mode(mirror_mode);
+	}
+
+	fn write_register(&mut self, addr: u16, val: u8) {
+		let addr = match self.swap_lines {
+			true => (addr & 0xf000) | (addr & 0x01) << 1 | (addr & 0x02) >> 1,
+			false => addr & 0xf003,
+		};
+		let reg = addr & 0x03;
+		match addr {
+			0x8000..=0x8003 => self.prg_banks[0] = val,
+			0x9003 => {
+				self.halt = val & 0x01 != 0;
+				// 256x takes priority over 16x
+				self.freq_shift = match val & 0x06 {
+					0 => 0,
+					0x02 => 4,
+					_ => 8,
+				};
+			},
+			0x9000..=0x9002 => self.pulse1.write_u8(reg, val),
+			0xa000..=0xa002 => self.pulse2.write_u8(reg, val),
+			0xb000..=0xb002 => self.saw.write_u8(reg, val),
+			0xb003 => {
+				self.ppu_mode = val;
+				self.prg_ram.set_enabled(val & 0x80 != 0);
+				self.update_name_table();
+			},
+			0xc000..=0xc003 => self.prg_banks[1] = val,
+			0xd000..=0xe003 => {
+				let index = ((addr - 0xd000) >> 10 | reg) as usize;
+				self.chr_banks[index] = val;
+				self.update_name_table();
+			},
+			0xf000 => self.irq.write_latch(val),
+			0xf001 => self.irq.write_control(val),
+			0xf002 => self.irq.acknowledge(),
+			_ => (),
+		}
+	}
+}
+
+
+impl Mapper for VRC6 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[self.chr_addr(addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[self.prg_addr(addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => {
+				let addr = self.chr_addr(addr);
+				self.chr[addr] = val;
+			},
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0x8000..=0xffff => self.write_register(addr, val),
+			_ => (),
+		}
+	}
+
+	fn tick(&mut self) {
+		self.irq.tick();
+		if !self.halt {
+			self.pulse1.clock_timer(self.freq_shift);
+			self.pulse2.clock_timer(self.freq_shift);
+			self.saw.clock_timer(self.freq_shift);
+		}
+	}
+
+	fn audio_output(&self) -> f32 {
+		let sum = self.pulse1.output() + self.pulse2.output() + self.saw.output();
+		sum as f32 * VRC6_VOLUME
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// mappers 21, 22, 23 and 25
+//
+// the boards wire the chip's two register select lines to different cpu
+// address lines, `lines` holds the address bits feeding A0 and A1
+#[derive(Debug)]
+pub struct VRC4 {
+	// prg rom, 8K banks
+	prg: Memory,
+	// ppu pattern table, 1K banks
+	chr: Memory,
+	name_table: NameTable,
+	prg_ram: PrgRam,
+	lines: (u16, u16),
+	// VRC2 has no irq, prg swap mode or 4 way mirroring
+	vrc2: bool,
+	// VRC2a leaves out the lowest chr bank bit
+	chr_shift: u8,
+	prg_banks: [u8; 2],
+	// $C000 swappable and $8000 fixed to the second last bank
+	prg_swap: bool,
+	chr_banks: [u16; 8],
+	irq: VrcIrq,
+	// 1 bit latch at $6000-$6FFF on VRC2 boards without prg ram
+	latch: u8,
+}
+
+
+impl VRC4 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, irq: Signal) -> Self {
+		Self {
+			prg,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+			lines: (0x01, 0x02),
+			vrc2: false,
+			chr_shift: 0,
+			prg_banks: [0; 2],
+			prg_swap: false,
+			chr_banks: [0; 8],
+			irq: VrcIrq::new(irq),
+			latch: 0,
+		}
+	}
+
+	// address bits wired to A0 and A1, several bits can be or'ed when the wiring is unknown
+	pub fn with_lines(mut self, a0: u16, a1: u16) -> Self {
+		self.lines = (a0, a1);
+		self
+	}
+
+	pub fn with_vrc2(mut self, chr_shift: bool) -> Self {
+		self.vrc2 = true;
+		self.chr_shift = chr_shift as u8;
+		self
+	}
+
+	fn prg_addr(&self, addr: u16) -> usize {
+		let banks = self.prg.size() >> 13;
+		let bank = match (addr, self.prg_swap) {
+			(0x8000..=0x9fff, false) | (0xc000..=0xdfff, true) => self.prg_banks[0] as usize,
+			(0x8000..=0x9fff, true) | (0xc000..=0xdfff, false) => banks.wrapping_sub(2),
+			(0xa000..=0xbfff, _) => self.prg_banks[1] as usize,
+			_ => banks.wrapping_sub(1),
+		};
+		((bank << 13) | (addr & 0x1fff) as usize) % self.prg.size()
+	}
+
+	fn chr_addr(&self, addr: u16) -> usize {
+		let bank = (self.chr_banks[(addr >> 10) as usize & 0x07] >> self.chr_shift) as usize;
+		(bank << 10 | (addr & 0x03ff) as usize) % self.chr.size()
+	}
+
+	// register 0-3 within a $1000 block
+	fn register(&self, addr: u16) -> u16 {
+		let (a0, a1) = self.lines;
+		(addr & a0 != 0) as u16 | ((addr & a1 != 0) as u16) << 1
+	}
+
+	fn write_register(&mut self, addr: u16, val: u8) {
+		let reg = self.register(addr);
+		match (addr & 0xf000, reg) {
+			(0x8000, _) => self.prg_banks[0] = val & 0x1f,
+			// prg swap mode and the wram enable
+			(0x9000, 2) if !self.vrc2 => {
+				self.prg_swap = val & 0x02 != 0;
+				self.prg_ram.set_enabled(val & 0x01 != 0);
+			},
+			(0x9000, 3) if !self.vrc2 => (),
+			(0x9000, _) => {
+				let mirror = match self.vrc2 {
+					true => val & 0x01,
+					false => val & 0x03,
+				};
+				let mirror_mode = match mirror {
+					0 => MirroMode::Vertical,
+					1 => MirroMode::Horizontal,
+					2 => MirroMode::SingleLower,
+					_ => MirroMode::SingleUpper,
+				};
+				self.name_table.set_mirror_mode(mirror_mode);
+			},
+			(0xa000, _) => self.prg_banks[1] = val & 0x1f,
+			// two registers per bank, low then high bits
+			(0xb000..=0xe000, _) => {
+				let index = (((addr & 0xf000) - 0xb000) >> 11) as usize | (reg >> 1) as usize;
+				let bank = &mut self.chr_banks[index];
+				*bank = match reg & 0x01 {
+					0 => (*bank & 0x1f0) | (val & 0x0f) as u16,
+					_ => (*bank & 0x0f) | ((val & 0x1f) as u16) << 4,
+				};
+			},
+			(0xf000, _) if self.vrc2 => (),
+			(0xf000, 0) => self.irq.write_latch((self.irq.latch & 0xf0) | (val & 0x0f)),
+			(0xf000, 1) => self.irq.write_latch((self.irq.latch & 0x0f) | (val & 0x0f) << 4),
+			(0xf000, 2) => self.irq.write_control(val),
+			(0xf000, _) => self.irq.acknowledge(),
+			_ => (),
+		}
+	}
+}
+
+
+impl Mapper for VRC4 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[self.chr_addr(addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x6fff if self.vrc2 && self.prg_ram.memory().is_none() => self.latch,
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[self.prg_addr(addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => {
+				let addr = self.chr_addr(addr);
+				self.chr[addr] = val;
+			},
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x6fff if self.vrc2 && self.prg_ram.memory().is_none() => self.latch = val & 0x01,
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0x8000..=0xffff => self.write_register(addr, val),
+			_ => (),
+		}
+	}
+
+	fn tick(&mut self) {
+		if !self.vrc2 {
+			self.irq.tick();
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// Sunsoft 5B audio, a YM2149 style chip with three square channels
+// sharing one noise generator and one envelope
+#[derive(Debug)]
+struct Sunsoft5B {
+	// $C000 register select
+	select: u8,
+	tone_periods: [u16; 3],
+	tone_counters: [u16; 3],
+	tone_outputs: [bool; 3],
+	noise_period: u8,
+	noise_counter: u8,
+	// 17 bit lfsr
+	noise: u32,
+	// register 7, set bits disable tone 0-2 and noise 3-5
+	mixer: u8,
+	// 4 bit volumes, bit 4 uses the envelope
+	volumes: [u8; 3],
+	env_period: u16,
+	env_counter: u16,
+	env_shape: u8,
+	env_step: u8,
+	env_attack: bool,
+	env_holding: bool,
+	// the chip runs its counters every 16 cpu cycles
+	divider: u8,
+	// 32 level logarithmic dac, 1.5 dB per step
+	levels: Vec<f32>,
+}
+
+
+impl Sunsoft5B {
+	fn new() -> Self {
+		Self {
+			select: 0,
+			tone_periods: [0; 3],
+			tone_counters: [0; 3],
+			tone_outputs: [false; 3],
+			noise_period: 0,
+			noise_counter: 0,
+			noise: 1,
+			mixer: 0,
+			volumes: [0; 3],
+			env_period: 0,
+			env_counter: 0,
+			env_shape: 0,
+			env_step: 0,
+			env_attack: false,
+			env_holding: true,
+			divider: 0,
+			levels: (0..32).map(|n| match n {
+				0 => 0.0,
+				n => 10f32.powf(-1.5 * (31 - n) as f32 / 20.0),
+			}).collect(),
+		}
+	}
+
+	fn write_u8(&mut self, val: u8) {
+		match self.select {
+			0 | 2 | 4 => {
+				let period = &mut self.tone_periods[self.select as usize >> 1];
+				*period = (*period & 0x0f00) | val as u16;
+			},
+			1 | 3 | 5 => {
+				let period = &mut self.tone_periods[self.select as usize >> 1];
+				*period = (*period & 0x00ff) | ((val & 0x0f) as u16) << 8;
+			},
+			6 => self.noise_period = val & 0x1f,
+			7 => self.mixer = val,
+			8..=10 => self.volumes[self.select as usize - 8] = val & 0x1f,
+			11 => self.env_period = (self.env_period & 0xff00) | val as u16,
+			12 => self.env_period = (self.env_period & 0x00ff) | (val as u16) << 8,
+			13 => {
+				// restarts the envelope
+				self.env_shape = val & 0x0f;
+				self.env_attack = val & 0x04 != 0;
+				self.env_holding = false;
+				self.env_step = 0;
+				self.env_counter = 0;
+			},
+			_ => (),
+		}
+	}
+
+	fn clock_envelope(&mut self) {
+		if self.env_holding {
+			return;
+		}
+		self.env_step += 1;
+		if self.env_step < 32 {
+			return;
+		}
+		// shape bits: continue, attack, alternate, hold
+		match (self.env_shape & 0x08 != 0, self.env_shape & 0x01 != 0) {
+			// one ramp then silence
+			(false, _) => {
+				self.env_holding = true;
+				self.env_attack = false;
+				self.env_step = 31;
+			},
+			(true, true) => {
+				self.env_holding = true;
+				self.env_attack ^= self.env_shape & 0x02 != 0;
+				self.env_step = 31;
+			},
+			(true, false) => {
+				self.env_attack ^= self.env_shape & 0x02 != 0;
+				self.env_step = 0;
+			},
+		}
+	}
+
+	fn env_level(&self) -> u8 {
+		match self.env_attack {
+			true => self.env_step,
+			false => 31 - self.env_step,
+		}
+	}
+
+	// called once per cpu cycle
+	fn tick(&mut self) {
+		self.divider += 1;
+		if self.divider < 16 {
+			return;
+		}
+		self.divider = 0;
+		for i in 0..3 {
+			self.tone_counters[i] += 1;
+			if self.tone_counters[i] >= self.tone_periods[i].max(1) {
+				self.tone_counters[i] = 0;
+				self.tone_outputs[i] = !self.tone_outputs[i];
+			}
+		}
+		// noise runs at half the tone rate
+		self.noise_counter += 1;
+		if self.noise_counter >= (self.noise_period.max(1) << 1) {
+			self.noise_counter = 0;
+			let bit = (self.noise ^ (self.noise >> 3)) & 0x01;
+			self.noise = (self.noise >> 1) | bit << 16;
+		}
+		self.env_counter += 1;
+		if self.env_counter >= self.env_period.max(1) {
+			self.env_counter = 0;
+			self.clock_envelope();
+		}
+	}
+
+	fn output(&self) -> f32 {
+		let noise = self.noise & 0x01 != 0;
+		(0..3).map(|i| {
+			let tone = self.tone_outputs[i] || self.mixer & (0x01 << i) != 0;
+			let noise = noise || self.mixer & (0x08 << i) != 0;
+			let level = match self.volumes[i] {
+				v if v & 0x10 != 0 => self.env_level(),
+				0 => 0,
+				v => v << 1 | 0x01,
+			};
+			match tone && noise {
+				true => self.levels[level as usize],
+				false => 0.0,
+			}
+		}).sum()
+	}
+}
+
+
+// a full volume 5B channel is about as loud as a full volume apu pulse
+const SUNSOFT_5B_VOLUME: f32 = 0.15;
+
+
+// mapper 69
+#[derive(Debug)]
+pub struct FME7 {
+	// prg rom, 8K banks
+	prg: Memory,
+	// ppu pattern table, 1K banks
+	chr: Memory,
+	name_table: NameTable,
+	prg_ram: PrgRam,
+	// $8000 command
+	command: u8,
+	chr_banks: [u8; 8],
+	// command 8: ram enable, ram select and the bank at $6000
+	prg_ram_bank: u8,
+	// commands 9-B, $8000, $A000 and $C000
+	prg_banks: [u8; 3],
+	irq: Signal,
+	irq_enabled: bool,
+	counter_enabled: bool,
+	counter: u16,
+	audio: Sunsoft5B,
+}
+
+
+impl FME7 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, irq: Signal) -> Self {
+		Self {
+			prg,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+			command: 0,
+			chr_banks: [0; 8],
+			prg_ram_bank: 0,
+			prg_banks: [0; 3],
+			irq,
+			irq_enabled: false,
+			counter_enabled: false,
+			counter: 0,
+			audio: Sunsoft5B::new(),
+		}
+	}
+
+	fn prg_addr(&self, bank: usize, addr: u16) -> usize {
+		(bank << 13 | (addr & 0x1fff) as usize) % self.prg.size()
+	}
+
+	fn chr_addr(&self, addr: u16) -> usize {
+		let bank = self.chr_banks[(addr >> 10) as usize & 0x07] as usize;
+		(bank << 10 | (addr & 0x03ff) as usize) % self.chr.size()
+	}
+
+	// $6000-$7FFF maps a prg rom bank or, with bit 6 set, prg ram
+	fn ram_selected(&self) -> bool {
+		self.prg_ram_bank & 0x40 != 0
+	}
+
+	fn ram_offset(&self, addr: u16) -> usize {
+		((self.prg_ram_bank & 0x3f) as usize) << 13 | (addr & 0x1fff) as usize
+	}
+
+	fn write_parameter(&mut self, val: u8) {
+		match self.command {
+			0..=7 => self.chr_banks[self.command as usize] = val,
+			8 => {
+				self.prg_ram_bank = val;
+				self.prg_ram.set_enabled(val & 0x80 != 0);
+			},
+			9..=11 => self.prg_banks[self.command as usize - 9] = val & 0x3f,
+			12 => {
+				let mirror_mode = match val & 0x03 {
+					0 => MirroMode::Vertical,
+					1 => MirroMode::Horizontal,
+					2 => MirroMode::SingleLower,
+					_ => MirroMode::SingleUpper,
+				};
+				self.name_table.set_mirror_mode(mirror_mode);
+			},
+			// writing the irq control acknowledges the irq
+			13 => {
+				self.irq_enabled = val & 0x01 != 0;
+				self.counter_enabled = val & 0x80 != 0;
+				board::set_irq(&self.irq, IRQ_MAPPER, false);
+			},
+			14 => self.counter = (self.counter & 0xff00) | val as u16,
+			_ => self.counter = (self.counter & 0x00ff) | (val as u16) << 8,
+		}
+	}
+}
+
+
+impl Mapper for FME7 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[self.chr_addr(addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff if self.ram_selected() => self.prg_ram.read_u8(self.ram_offset(addr)),
+			0x6000..=0x7fff => self.prg[self.prg_addr((self.prg_ram_bank & 0x3f) as usize, addr)],
+			0x8000..=0xdfff => {
+				let bank = self.prg_banks[((addr - 0x8000) >> 13) as usize] as usize;
+				self.prg[self.prg_addr(bank, addr)]
+			},
+			0xe000..=0xffff => self.prg[self.prg_addr((self.prg.size() >> 13).wrapping_sub(1), addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => {
+				let addr = self.chr_addr(addr);
+				self.chr[addr] = val;
+			},
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff if self.ram_selected() => {
+				let offset = self.ram_offset(addr);
+				self.prg_ram.write_u8(offset, val);
+			},
+			0x8000..=0x9fff => self.command = val & 0x0f,
+			0xa000..=0xbfff => self.write_parameter(val),
+			0xc000..=0xdfff => self.audio.select = val & 0x0f,
+			0xe000..=0xffff => self.audio.write_u8(val),
+			_ => (),
+		}
+	}
+
+	fn tick(&mut self) {
+		if self.counter_enabled {
+			self.counter = self.counter.wrapping_sub(1);
+			if self.counter == 0xffff && self.irq_enabled {
+				board::set_irq(&self.irq, IRQ_MAPPER, true);
+			}
+		}
+		self.audio.tick();
+	}
+
+	fn audio_output(&self) -> f32 {
+		self.audio.output() * SUNSOFT_5B_VOLUME
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// Namco 163 wavetable audio
+//
+// the chip updates one channel every 15 cpu cycles and outputs only that
+// channel until the next update, so more channels mean a lower multiplexing
+// rate and a quieter channel
+#[derive(Debug)]
+struct Namco163Audio {
+	// 4 bit samples and the channel registers at $40-$7F
+	ram: [u8; 128],
+	// $F800 address port, bit 7 increments after each access
+	addr: u8,
+	auto_increment: bool,
+	// $E000 bit 6
+	disabled: bool,
+	divider: u8,
+	// channel updated next, counts down from 7
+	channel: u8,
+	// sample times volume of the last updated channel
+	output: i16,
+}
+
+
+impl Namco163Audio {
+	fn new() -> Self {
+		Self {
+			ram: [0; 128],
+			addr: 0,
+			auto_increment: false,
+			disabled: false,
+			divider: 0,
+			channel: 7,
+			output: 0,
+		}
+	}
+
+	fn write_addr(&mut self, val: u8) {
+		self.addr = val & 0x7f;
+		self.auto_increment = val & 0x80 != 0;
+	}
+
+	fn step_addr(&mut self) {
+		if self.auto_increment {
+			self.addr = (self.addr + 1) & 0x7f;
+		}
+	}
+
+	fn read_data(&mut self) -> u8 {
+		let val = self.ram[self.addr as usize];
+		self.step_addr();
+		val
+	}
+
+	fn write_data(&mut self, val: u8) {
+		self.ram[self.addr as usize] = val;
+		self.step_addr();
+	}
+
+	// enabled channels, set by bits 4-6 of $7F
+	fn channels(&self) -> u8 {
+		((self.ram[0x7f] >> 4) & 0x07) + 1
+	}
+
+	fn update_channel(&mut self, channel: u8) {
+		let base = 0x40 + (channel as usize) * 8;
+		let reg = |i: usize| self.ram[base + i] as u32;
+		let freq = reg(0) | reg(2) << 8 | (reg(4) & 0x03) << 16;
+		let length = 256 - (reg(4) & 0xfc);
+		let phase = ((reg(1) | reg(3) << 8 | reg(5) << 16) + freq) % (length << 16);
+		let addr = (reg(6) + (phase >> 16)) as usize & 0xff;
+		self.ram[base + 1] = phase as u8;
+		self.ram[base + 3] = (phase >> 8) as u8;
+		self.ram[base + 5] = (phase >> 16) as u8;
+		// two samples per byte, low nibble first
+		let sample = (self.ram[addr >> 1] >> ((addr & 0x01) << 2)) & 0x0f;
+		let volume = self.ram[base + 7] & 0x0f;
+		self.output = (sample as i16 - 8) * volume as i16;
+	}
+
+	// called once per cpu cycle
+	fn tick(&mut self) {
+		if self.disabled {
+			return;
+		}
+		self.divider += 1;
+		if self.divider < 15 {
+			return;
+		}
+		self.divider = 0;
+		self.update_channel(self.channel);
+		self.channel = match self.channel <= 8 - self.channels() {
+			true => 7,
+			false => self.channel - 1,
+		};
+	}
+
+	fn output(&self) -> i16 {
+		match self.disabled {
+			true => 0,
+			false => self.output,
+		}
+	}
+}
+
+
+// a single full volume Namco 163 channel swings about as far as a full volume apu pulse
+const NAMCO_163_VOLUME: f32 = 0.15 / 120.0;
+
+
+// mapper 19
+#[derive(Debug)]
+pub struct Namco163 {
+	// prg rom, 8K banks
+	prg: Memory,
+	// ppu pattern table, 1K banks
+	chr: Memory,
+	// ciram also serves as pattern table ram
+	name_table: NameTable,
+	prg_ram: PrgRam,
+	// $E000, $E800 and $F000 bits 0-5
+	prg_banks: [u8; 3],
+	// $8000-$B800, values $E0 and up pick ciram
+	chr_banks: [u8; 8],
+	// $E800 bits 6 and 7 keep ciram out of the $0000 and $1000 pattern tables
+	chr_ram_disabled: [bool; 2],
+	irq: Signal,
+	irq_enabled: bool,
+	// 15 bit up counter
+	irq_counter: u16,
+	audio: Namco163Audio,
+}
+
+
+impl Namco163 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, irq: Signal) -> Self {
+		Self {
+			prg,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+			prg_banks: [0; 3],
+			chr_banks: [0; 8],
+			chr_ram_disabled: [false; 2],
+			irq,
+			irq_enabled: false,
+			irq_counter: 0,
+			audio: Namco163Audio::new(),
+		}
+	}
+
+	fn prg_addr(&self, addr: u16) -> usize {
+		let bank = match addr {
+			0x8000..=0xdfff => self.prg_banks[((addr - 0x8000) >> 13) as usize] as usize,
+			_ => (self.prg.size() >> 13).wrapping_sub(1),
+		};
+		(bank << 13 | (addr & 0x1fff) as usize) % self.prg.size()
+	}
+
+	// chr rom bank or ciram page for a bank register value
+	fn page(val: u8, ram_allowed: bool) -> NameTablePage {
+		match val >= 0xe0 && ram_allowed {
+			true => NameTablePage::Ciram((val & 0x01) as usize),
+			false => NameTablePage::Chr(val as usize),
+		}
+	}
+
+	fn chr_page(&self, addr: u16) -> NameTablePage {
+		let slot = (addr >> 10) as usize & 0x07;
+		Self::page(self.chr_banks[slot], !self.chr_ram_disabled[slot >> 2])
+	}
+
+	fn read_register(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x4800..=0x4fff => self.audio.read_data(),
+			0x5000..=0x57ff => self.irq_counter as u8,
+			0x5800..=0x5fff => (self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7,
+			_ => 0,
+		}
+	}
+
+	fn write_register(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x4800..=0x4fff => self.audio.write_data(val),
+			// writing the counter acknowledges the irq
+			0x5000..=0x57ff => {
+				self.irq_counter = (self.irq_counter & 0x7f00) | val as u16;
+				board::set_irq(&self.irq, IRQ_MAPPER, false);
+			},
+			0x5800..=0x5fff => {
+				self.irq_counter = (self.irq_counter & 0x00ff) | ((val & 0x7f) as u16) << 8;
+				self.irq_enabled = val & 0x80 != 0;
+				board::set_irq(&self.irq, IRQ_MAPPER, false);
+			},
+			0x8000..=0xbfff => self.chr_banks[((addr - 0x8000) >> 11) as usize] = val,
+			0xc000..=0xdfff => {
+				let quadrant = ((addr - 0xc000) >> 11) as usize;
+				self.name_table.set_page(quadrant, Self::page(val, true));
+			},
+			0xe000..=0xe7ff => {
+				self.prg_banks[0] = val & 0x3f;
+				self.audio.disabled = val & 0x40 != 0;
+			},
+			0xe800..=0xefff => {
+				self.prg_banks[1] = val & 0x3f;
+				self.chr_ram_disabled = [val & 0x40 != 0, val & 0x80 != 0];
+			},
+			0xf000..=0xf7ff => self.prg_banks[2] = val & 0x3f,
+			0xf800..=0xffff => self.audio.write_addr(val),
+			_ => (),
+		}
+	}
+}
+
+
+impl Mapper for Namco163 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.name_table.read_page(self.chr_page(addr), addr, &self.chr),
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x4800..=0x5fff => self.read_register(addr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[self.prg_addr(addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => self.name_table.write_page(self.chr_page(addr), addr, val),
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0x4800..=0x5fff | 0x8000..=0xffff => self.write_register(addr, val),
+			_ => (),
+		}
+	}
+
+	fn tick(&mut self) {
+		if self.irq_enabled && self.irq_counter < 0x7fff {
+			self.irq_counter += 1;
+			if self.irq_counter == 0x7fff {
+				board::set_irq(&self.irq, IRQ_MAPPER, true);
+			}
+		}
+		self.audio.tick();
+	}
+
+	fn audio_output(&self) -> f32 {
+		self.audio.output() as f32 * NAMCO_163_VOLUME
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
+	}
+}
+
+
+// the opll makes one sample every 36 cpu cycles
+const OPLL_DIVIDER: u8 = 36;
+
+// a full volume VRC7 channel is about as loud as a full volume apu pulse
+const VRC7_VOLUME: f32 = 0.15 / 4096.0;
+
+
+// mapper 85
+//
+// VRC7a boards select the second register of each pair with A4, VRC7b with A3
+#[derive(Debug)]
+pub struct VRC7 {
+	// prg rom, 8K banks
+	prg: Memory,
+	// ppu pattern table, 1K banks
+	chr: Memory,
+	name_table: NameTable,
+	prg_ram: PrgRam,
+	// address bits selecting the second register
+	line: u16,
+	prg_banks: [u8; 3],
+	chr_banks: [u8; 8],
+	irq: VrcIrq,
+	// fm audio
+	opll: Opll,
+	opll_select: u8,
+	opll_divider: u8,
+	opll_output: i32,
+	// $E000 bit 6 holds the audio in reset
+	audio_reset: bool,
+}
+
+
+impl VRC7 {
+	pub fn new(prg: Memory, chr: Memory, mode: MirroMode, prg_ram_size: usize, line: u16, irq: Signal) -> Self {
+		Self {
+			prg,
+			chr,
+			name_table: NameTable::new(mode),
+			prg_ram: PrgRam::new(prg_ram_size),
+			line,
+			prg_banks: [0; 3],
+			chr_banks: [0; 8],
+			irq: VrcIrq::new(irq),
+			opll: Opll::new(),
+			opll_select: 0,
+			opll_divider: 0,
+			opll_output: 0,
+			audio_reset: false,
+		}
+	}
+
+	fn prg_addr(&self, addr: u16) -> usize {
+		let bank = match addr {
+			0x8000..=0xdfff => self.prg_banks[((addr - 0x8000) >> 13) as usize] as usize,
+			_ => (self.prg.size() >> 13).wrapping_sub(1),
+		};
+		(bank << 13 | (addr & 0x1fff) as usize) % self.prg.size()
+	}
+
+	fn chr_addr(&self, addr: u16) -> usize {
+		let bank = self.chr_banks[(addr >> 10) as usize & 0x07] as usize;
+		(bank << 10 | (addr & 0x03ff) as usize) % self.chr.size()
+	}
+
+	fn write_register(&mut self, addr: u16, val: u8) {
+		// $9010 and $9030 are decoded with A5 on both boards
+		match addr & 0xf030 {
+			0x9010 => {
+				self.opll_select = val;
+				return;
+			},
+			0x9030 => {
+				self.opll.write(self.opll_select, val);
+				return;
+			},
+			_ => (),
+		}
+		let second = addr & self.line != 0;
+		match (addr & 0xf000, second) {
+			(0x8000, false) => self.prg_banks[0] = val & 0x3f,
+			(0x8000, true) => self.prg_banks[1] = val & 0x3f,
+			(0x9000, false) => self.prg_banks[2] = val & 0x3f,
+			(0xa000..=0xd000, _) => {
+				let index = (((addr & 0xf000) - 0xa000) >> 11) as usize | second as usize;
+				self.chr_banks[index] = val;
+			},
+			(0xe000, false) => {
+				let mirror_mode = match val & 0x03 {
+					0 => MirroMode::Vertical,
+					1 => MirroMode::Horizontal,
+					2 => MirroMode::SingleLower,
+					_ => MirroMode::SingleUpper,
+				};
+				self.name_table.set_mirror_mode(mirror_mode);
+				self.audio_reset = val & 0x40 != 0;
+				if self.audio_reset {
+					self.opll.reset();
+					self.opll_output = 0;
+				}
+				self.prg_ram.set_enabled(val & 0x80 != 0);
+			},
+			(0xe000, true) => self.irq.write_latch(val),
+			(0xf000, false) => self.irq.write_control(val),
+			(0xf000, true) => self.irq.acknowledge(),
+			_ => (),
+		}
+	}
+}
+
+
+impl Mapper for VRC7 {
+
+	fn read_u8(&mut self, addr: u16) -> u8 {
+		match addr {
+			0x0000..=0x1fff => self.chr[self.chr_addr(addr)],
+			0x2000..=0x3eff => self.name_table.read_u8(addr - 0x2000, &self.chr),
+			0x6000..=0x7fff => self.prg_ram.read_u8((addr - 0x6000) as usize),
+			0x8000..=0xffff => self.prg[self.prg_addr(addr)],
+			_ => 0,
+		}
+	}
+
+	fn write_u8(&mut self, addr: u16, val: u8) {
+		match addr {
+			0x0000..=0x1fff => {
+				let addr = self.chr_addr(addr);
+				self.chr[addr] = val;
+			},
+			0x2000..=0x3eff => self.name_table.write_u8(addr - 0x2000, val),
+			0x6000..=0x7fff => self.prg_ram.write_u8((addr - 0x6000) as usize, val),
+			0x8000..=0xffff => self.write_register(addr, val),
+			_ => (),
+		}
+	}
+
+	fn tick(&mut self) {
+		self.irq.tick();
+		if self.audio_reset {
+			return;
+		}
+		self.opll_divider += 1;
+		if self.opll_divider >= OPLL_DIVIDER {
+			self.opll_divider = 0;
+			self.opll_output = self.opll.clock();
+		}
+	}
+
+	fn audio_output(&self) -> f32 {
+		self.opll_output as f32 * VRC7_VOLUME
+	}
+
+	fn prg_ram(&self) -> Option<&Memory> {
+		self.prg_ram.memory()
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut Memory> {
+		self.prg_ram.memory_mut()
 	}
 }
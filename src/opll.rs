@@ -0,0 +1,450 @@
+// YM2413 (OPLL) style fm synthesis, as built into the Konami VRC7
+//
+// each channel is a modulator operator feeding the phase of a carrier operator.
+// operators work in the log domain like the real chip: a log-sine table lookup plus
+// the attenuation, turned back into a linear level through an exponent table.
+// the chip produces one sample every 72 clocks of its 3.58 MHz clock, about 49716 Hz.
+use std::f64::consts::PI;
+
+
+pub const CHANNELS: usize = 6;
+
+// the VRC7 instrument rom, patches 1-15, patch 0 is the custom instrument at $00-$07
+pub const VRC7_PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xe8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0d, 0xd8, 0xf6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xfa, 0xb2, 0x20, 0x12],
+    [0x31, 0x61, 0x0c, 0x07, 0xa8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xa3, 0xe2, 0xf4, 0xf4],
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xa2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xb5, 0x01, 0x0f, 0x0f, 0xa8, 0xa5, 0x51, 0x02],
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xd3, 0x05, 0xc9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0c, 0x00, 0x94, 0xc0, 0x33, 0xf6],
+    [0x21, 0x72, 0x0d, 0x00, 0xc1, 0xd5, 0x56, 0x06],
+];
+
+// frequency multiplier times two
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+// key scale level attenuation of the top block by fnum bits 5-8, 0.75 dB steps
+const KSL_TABLE: [i32; 16] = [0, 24, 32, 37, 40, 43, 45, 47, 48, 50, 51, 52, 53, 54, 55, 56];
+
+// envelope increments for the four fractional rates, over 8 counter steps
+const EG_INC: [[u32; 8]; 4] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+];
+
+// vibrato steps, in 1/2 of fnum bits 6-8
+const PM_TABLE: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+
+// 7 bit envelope attenuation, 0.375 dB per step
+const ENV_MAX: u32 = 127;
+
+// 10 bit sine index
+const SINE_BITS: u32 = 10;
+// 19 bit phase accumulator
+const PHASE_BITS: u32 = 19;
+
+
+// attenuation envelope stages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EnvState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+
+// operator settings taken from a patch
+#[derive(Debug, Clone, Copy, Default)]
+struct OperatorPatch {
+    am: bool,
+    vibrato: bool,
+    // sustained envelope, otherwise percussive
+    sustained: bool,
+    ksr: bool,
+    mult: u8,
+    ksl: u8,
+    // half sine wave
+    rectified: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: u8,
+    release: u8,
+}
+
+
+impl OperatorPatch {
+    // the 8 patch bytes hold the modulator and carrier side by side
+    fn from_patch(patch: &[u8; 8], carrier: bool) -> Self {
+        let i = carrier as usize;
+        Self {
+            am: patch[i] & 0x80 != 0,
+            vibrato: patch[i] & 0x40 != 0,
+            sustained: patch[i] & 0x20 != 0,
+            ksr: patch[i] & 0x10 != 0,
+            mult: patch[i] & 0x0f,
+            ksl: patch[2 + i] >> 6,
+            rectified: patch[3] & (0x08 << i) != 0,
+            attack: patch[4 + i] >> 4,
+            decay: patch[4 + i] & 0x0f,
+            sustain_level: patch[6 + i] >> 4,
+            release: patch[6 + i] & 0x0f,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+struct Operator {
+    patch: OperatorPatch,
+    phase: u32,
+    env: u32,
+    state: EnvState,
+    // last two outputs, for the modulator feedback
+    output: [i32; 2],
+}
+
+
+impl Operator {
+    fn new() -> Self {
+        Self {
+            patch: OperatorPatch::default(),
+            phase: 0,
+            env: ENV_MAX,
+            state: EnvState::Off,
+            output: [0; 2],
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.state = EnvState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.state != EnvState::Off {
+            self.state = EnvState::Release;
+        }
+    }
+
+    // 6 bit envelope rate of the current stage, 0 keeps the envelope still
+    fn rate(&self, key_code: u32, sustain_on: bool) -> u32 {
+        let rate = match self.state {
+            EnvState::Attack => self.patch.attack,
+            EnvState::Decay => self.patch.decay,
+            EnvState::Sustain if self.patch.sustained => 0,
+            EnvState::Sustain => self.patch.release,
+            EnvState::Release if sustain_on => 5,
+            EnvState::Release if self.patch.sustained => self.patch.release,
+            EnvState::Release => 7,
+            EnvState::Off => 0,
+        } as u32;
+        let ksr = match self.patch.ksr {
+            true => key_code,
+            false => key_code >> 2,
+        };
+        match rate {
+            0 => 0,
+            rate => (rate * 4 + ksr).min(63),
+        }
+    }
+
+    fn clock_envelope(&mut self, key_code: u32, sustain_on: bool, counter: u32) {
+        let rate = self.rate(key_code, sustain_on);
+        if self.state == EnvState::Attack && rate >= 60 {
+            self.env = 0;
+        }
+        let inc = match rate {
+            0 => 0,
+            // slow rates only step every 2^shift samples
+            1..=47 => {
+                let shift = 12 - (rate >> 2);
+                match counter & ((1 << shift) - 1) {
+                    0 => EG_INC[rate as usize & 0x03][(counter >> shift) as usize & 0x07],
+                    _ => 0,
+                }
+            },
+            _ => EG_INC[rate as usize & 0x03][counter as usize & 0x07] << ((rate >> 2) - 12),
+        };
+        match self.state {
+            EnvState::Attack => {
+                // exponential approach to full volume
+                if inc > 0 && self.env > 0 {
+                    self.env = self.env.saturating_sub(((self.env * inc) >> 3) + 1);
+                }
+                if self.env == 0 {
+                    self.state = EnvState::Decay;
+                }
+            },
+            EnvState::Decay => {
+                self.env = (self.env + inc).min(ENV_MAX);
+                // 3 dB sustain level steps
+                if self.env >= (self.patch.sustain_level as u32) << 3 {
+                    self.state = EnvState::Sustain;
+                }
+            },
+            EnvState::Sustain | EnvState::Release => {
+                self.env = (self.env + inc).min(ENV_MAX);
+                if self.env >= ENV_MAX {
+                    self.state = EnvState::Off;
+                }
+            },
+            EnvState::Off => self.env = ENV_MAX,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    fnum: u32,
+    block: u32,
+    key: bool,
+    sustain_on: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+}
+
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            fnum: 0,
+            block: 0,
+            key: false,
+            sustain_on: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+        }
+    }
+
+    // fnum bit 8 and the block, for key scaling
+    fn key_code(&self) -> u32 {
+        self.block << 1 | self.fnum >> 8
+    }
+
+    // key scale level attenuation in envelope steps
+    fn ksl(&self, ksl: u8) -> u32 {
+        if ksl == 0 {
+            return 0;
+        }
+        let level = KSL_TABLE[(self.fnum >> 5) as usize] - 8 * (7 - self.block as i32);
+        ((level.max(0) as u32) << 1) >> (3 - ksl)
+    }
+}
+
+
+// six fm channels with the VRC7 instrument set
+#[derive(Debug)]
+pub struct Opll {
+    // register file, $00-$07 is the custom patch
+    regs: [u8; 0x40],
+    channels: [Channel; CHANNELS],
+    // envelope, tremolo and vibrato timing
+    counter: u32,
+    // -log2(sin) for a quarter wave in 1/256 steps
+    log_sin: Vec<u32>,
+    // 2^(-x/256) scaled to 12 bits
+    exp: Vec<u32>,
+}
+
+
+impl Default for Opll {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl Opll {
+
+    pub fn new() -> Self {
+        Self {
+            regs: [0; 0x40],
+            channels: [Channel::new(); CHANNELS],
+            counter: 0,
+            log_sin: (0..256).map(|i| {
+                let x = ((i as f64 + 0.5) * PI / 512.0).sin();
+                (-x.log2() * 256.0).round() as u32
+            }).collect(),
+            exp: (0..256).map(|i| {
+                (4096.0 * 2f64.powf(-(i as f64) / 256.0)).round() as u32
+            }).collect(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.regs = [0; 0x40];
+        self.channels = [Channel::new(); CHANNELS];
+        self.counter = 0;
+    }
+
+    fn patch(&self, instrument: u8) -> [u8; 8] {
+        match instrument {
+            0 => {
+                let mut patch = [0; 8];
+                patch.copy_from_slice(&self.regs[0..8]);
+                patch
+            },
+            n => VRC7_PATCHES[n as usize - 1],
+        }
+    }
+
+    fn load_patch(&mut self, channel: usize) {
+        let patch = self.patch(self.channels[channel].instrument);
+        let ch = &mut self.channels[channel];
+        ch.modulator.patch = OperatorPatch::from_patch(&patch, false);
+        ch.carrier.patch = OperatorPatch::from_patch(&patch, true);
+    }
+
+    pub fn write(&mut self, reg: u8, val: u8) {
+        let reg = reg & 0x3f;
+        self.regs[reg as usize] = val;
+        let channel = (reg & 0x0f) as usize;
+        match reg {
+            // custom patch, used by every channel on instrument 0
+            0x00..=0x07 => {
+                for channel in 0..CHANNELS {
+                    if self.channels[channel].instrument == 0 {
+                        self.load_patch(channel);
+                    }
+                }
+            },
+            0x10..=0x15 => {
+                let ch = &mut self.channels[channel];
+                ch.fnum = (ch.fnum & 0x100) | val as u32;
+            },
+            0x20..=0x25 => {
+                let ch = &mut self.channels[channel];
+                ch.fnum = (ch.fnum & 0xff) | ((val & 0x01) as u32) << 8;
+                ch.block = ((val >> 1) & 0x07) as u32;
+                ch.sustain_on = val & 0x20 != 0;
+                let key = val & 0x10 != 0;
+                if key && !ch.key {
+                    ch.modulator.key_on();
+                    ch.carrier.key_on();
+                } else if !key && ch.key {
+                    ch.modulator.key_off();
+                    ch.carrier.key_off();
+                }
+                ch.key = key;
+            },
+            0x30..=0x35 => {
+                let ch = &mut self.channels[channel];
+                ch.instrument = val >> 4;
+                ch.volume = val & 0x0f;
+                self.load_patch(channel);
+            },
+            _ => (),
+        }
+    }
+
+    // one operator sample from a sine index and attenuation in envelope steps
+    fn operator_output(&self, index: i32, attenuation: u32, rectified: bool) -> i32 {
+        // fully attenuated operators are muted
+        if attenuation >= ENV_MAX {
+            return 0;
+        }
+        let index = index as u32 & ((1 << SINE_BITS) - 1);
+        let negative = index & 0x200 != 0;
+        if negative && rectified {
+            return 0;
+        }
+        let quarter = match index & 0x100 {
+            0 => index & 0xff,
+            _ => 0xff - (index & 0xff),
+        };
+        let level = self.log_sin[quarter as usize] + (attenuation << 4);
+        let out = match level >> 8 {
+            shift if shift >= 13 => 0,
+            shift => (self.exp[(level & 0xff) as usize] >> shift) as i32,
+        };
+        match negative {
+            true => -out,
+            false => out,
+        }
+    }
+
+    // phase increment of an operator, with vibrato applied to the fnum
+    fn phase_step(&self, ch: &Channel, op: &OperatorPatch) -> u32 {
+        let mut fnum = ch.fnum as i32;
+        if op.vibrato {
+            let step = (self.counter >> 10) as usize & 0x07;
+            fnum += ((ch.fnum >> 6) as i32 * PM_TABLE[step]) >> 1;
+        }
+        (((fnum.max(0) as u32) << ch.block) * MULTIPLIERS[op.mult as usize]) >> 1
+    }
+
+    // tremolo depth in envelope steps, a triangle from 0 to 13
+    fn tremolo(&self) -> u32 {
+        let step = (self.counter >> 9) % 26;
+        match step {
+            0..=13 => step,
+            _ => 26 - step,
+        }
+    }
+
+    // run one sample period, returns the sum of the carrier outputs
+    pub fn clock(&mut self) -> i32 {
+        let tremolo = self.tremolo();
+        let mut sum = 0;
+        for i in 0..CHANNELS {
+            let mut ch = self.channels[i];
+            let key_code = ch.key_code();
+            ch.modulator.clock_envelope(key_code, ch.sustain_on, self.counter);
+            ch.carrier.clock_envelope(key_code, ch.sustain_on, self.counter);
+
+            let patch = self.patch(ch.instrument);
+            let am = |op: &OperatorPatch| if op.am { tremolo } else { 0 };
+
+            // modulator with feedback of its last two outputs. outputs are 12
+            // bits and sign and go unscaled into the 10 bit sine index, so the
+            // feedback reaches 2 periods (4 pi) at level 7
+            let feedback = match patch[3] & 0x07 {
+                0 => 0,
+                fb => (ch.modulator.output[0] + ch.modulator.output[1]) >> (9 - fb),
+            };
+            let total_level = ((patch[2] & 0x3f) as u32) << 1;
+            let attenuation = ch.modulator.env + total_level + ch.ksl(ch.modulator.patch.ksl) + am(&ch.modulator.patch);
+            let index = (ch.modulator.phase >> (PHASE_BITS - SINE_BITS)) as i32 + feedback;
+            let modulation = match ch.modulator.state {
+                EnvState::Off => 0,
+                _ => self.operator_output(index, attenuation, ch.modulator.patch.rectified),
+            };
+            ch.modulator.output = [ch.modulator.output[1], modulation];
+
+            // carrier, 3 dB volume steps. a full scale modulator moves its
+            // phase by up to 4 periods
+            let attenuation = ch.carrier.env + ((ch.volume as u32) << 3) + ch.ksl(ch.carrier.patch.ksl) + am(&ch.carrier.patch);
+            let index = (ch.carrier.phase >> (PHASE_BITS - SINE_BITS)) as i32 + modulation;
+            let out = match ch.carrier.state {
+                EnvState::Off => 0,
+                _ => self.operator_output(index, attenuation, ch.carrier.patch.rectified),
+            };
+            ch.carrier.output = [ch.carrier.output[1], out];
+            sum += out;
+
+            let mask = (1 << PHASE_BITS) - 1;
+            ch.modulator.phase = (ch.modulator.phase + self.phase_step(&ch, &ch.modulator.patch)) & mask;
+            ch.carrier.phase = (ch.carrier.phase + self.phase_step(&ch, &ch.carrier.patch)) & mask;
+            self.channels[i] = ch;
+        }
+        self.counter = self.counter.wrapping_add(1);
+        sum
+    }
+}
@@ -0,0 +1,115 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// default audio queue target, in frames of audio
+const TARGET_FRAMES: f64 = 3.0;
+// maximum resampling rate deviation for dynamic rate control
+const MAX_RATE_DELTA: f64 = 0.005;
+// reset the wall clock if we fall this many frames behind
+const MAX_LAG_FRAMES: u32 = 4;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacingMode {
+    // block until the audio queue drains to the target latency
+    Audio,
+    // let present() block on the display's vsync
+    Vsync,
+    // vsync paced video, resampling rate nudged to keep the audio queue at the target latency
+    DynamicRate,
+}
+
+
+impl std::str::FromStr for PacingMode {
+
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "audio" => Ok(PacingMode::Audio),
+            "vsync" => Ok(PacingMode::Vsync),
+            "dynamic" => Ok(PacingMode::DynamicRate),
+            _ => Err(format!("unknown pacing mode {}", s)),
+        }
+    }
+}
+
+
+// paces emulation to real time, one frame per step
+pub struct Pacer {
+    mode: PacingMode,
+    sample_rate: f64,
+    // audio queue target in samples
+    target_latency: usize,
+    // wall clock pacing when there is no audio
+    frame_duration: Duration,
+    next_frame: Instant,
+}
+
+
+impl Pacer {
+
+    pub fn new(mode: PacingMode, frame_rate: f64, sample_rate: f64) -> Self {
+        Self {
+            mode,
+            sample_rate,
+            target_latency: (sample_rate / frame_rate * TARGET_FRAMES) as usize,
+            frame_duration: Duration::from_secs_f64(1.0 / frame_rate),
+            next_frame: Instant::now(),
+        }
+    }
+
+    // present() should wait for vsync
+    pub fn uses_vsync(&self) -> bool {
+        matches!(self.mode, PacingMode::Vsync | PacingMode::DynamicRate)
+    }
+
+    // sample rate the resampler should produce for the next frame, given the queued samples
+    pub fn sample_rate(&self, queued: usize) -> f64 {
+        match self.mode {
+            PacingMode::DynamicRate => {
+                // run the queue around half of twice the target: produce more samples when
+                // it runs low and fewer when it fills up
+                let capacity = (self.target_latency * 2) as f64;
+                let fill = (queued as f64 / capacity).min(1.0);
+                self.sample_rate * (1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill))
+            },
+            _ => self.sample_rate,
+        }
+    }
+
+    // the audio queue is too far ahead and should be dropped
+    pub fn audio_overrun(&self, queued: usize) -> bool {
+        queued > self.target_latency * 4
+    }
+
+    // throttle after a frame has been emulated.
+    // `queued` returns the samples waiting in the audio queue, or None without audio output
+    pub fn wait<F>(&mut self, queued: F)
+    where
+        F: Fn() -> Option<usize>,
+    {
+        match (self.mode, queued()) {
+            (PacingMode::Audio, Some(_)) => {
+                while queued().unwrap_or(0) > self.target_latency {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            },
+            // vsync already blocked in present()
+            (PacingMode::Vsync | PacingMode::DynamicRate, Some(_)) => (),
+            // no audio, fall back to the wall clock
+            (_, None) => self.wait_clock(),
+        }
+    }
+
+    fn wait_clock(&mut self) {
+        let now = Instant::now();
+        if now < self.next_frame {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > self.frame_duration * MAX_LAG_FRAMES {
+            // too far behind, don't try to catch up
+            self.next_frame = now;
+        }
+        self.next_frame += self.frame_duration;
+    }
+}
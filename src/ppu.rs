@@ -1,8 +1,8 @@
 use std::ops::{Deref, DerefMut};
 use std::cell::RefCell;
 use std::rc::Rc;
-use crate::board::{ Memory, Signal };
-use crate::mapper::Mapper;
+use crate::board::{ Memory, Region, Signal };
+use crate::mapper::{ Mapper, PpuFetch };
 
 const PPUCTRL: u16    = 0x2000;
 const PPUMASK: u16    = 0x2001;
@@ -200,6 +200,11 @@ impl PPUBus {
         }
     }
 
+    // rendering fetch from the pattern tables or nametables
+    pub fn fetch_u8(&mut self, addr: u16, fetch: PpuFetch) -> u8 {
+        self.mapper.borrow_mut().ppu_fetch(addr & 0x3fff, fetch)
+    }
+
     pub fn write_u8(&mut self, addr: u16, val: u8) {
         let addr = addr & 0x3fff;
         match addr {
@@ -311,6 +316,9 @@ struct RenderStatus {
     sprite_0_hit: bool,
     // frame number
     frame_number: u32,
+    // pal has 50 more vblank lines, repeated as line 260
+    extra_vblank_lines: u16,
+    extra_vblank_line: u16,
     // nt tile byte
     tile_index: u8,
     // at data
@@ -348,7 +356,13 @@ impl RenderStatus {
         self.cycle += 1;
         if self.cycle > 340 {
             self.cycle = 0;
-            self.scanline += 1;
+            if self.scanline == 260 && self.extra_vblank_line < self.extra_vblank_lines {
+                // stay in vblank
+                self.extra_vblank_line += 1;
+            } else {
+                self.extra_vblank_line = 0;
+                self.scanline += 1;
+            }
             if self.scanline > 261 {
                 self.scanline = 0;
                 self.frame_number = self.frame_number.wrapping_add(1);
@@ -639,14 +653,8 @@ impl PPU {
         }
     }
 
-    pub fn oam_dma(&mut self, data: &[u8]) {
-        let mut oam_addr: u8 = self.regs.oam_addr;
-        data.iter().for_each(|x| {
-            let n: usize = usize::from((oam_addr >> 2) & 0x3f);
-            let m: usize = usize::from(oam_addr & 0x03);
-            self.oam[n][m] = *x;
-            oam_addr = oam_addr.wrapping_add(1);
-        });
+    pub fn set_region(&mut self, region: Region) {
+        self.rs.extra_vblank_lines = region.extra_vblank_lines();
     }
 
     pub fn reset(&mut self) {
@@ -662,13 +670,13 @@ impl PPU {
     fn fetch_nt(&mut self) {
         // tile address      = 0x2000 | (v & 0x0FFF)
         let addr = 0x2000 | (self.regs.v & 0x0fff);
-        self.rs.tile_index = self.ppu_bus.read_u8(addr);
+        self.rs.tile_index = self.ppu_bus.fetch_u8(addr, PpuFetch::NameTable);
     }
 
     fn fetch_at(&mut self) {
         // attribute address = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07)
         let addr = 0x23C0 | (self.regs.v & 0x0C00) | ((self.regs.v >> 4) & 0x38) | ((self.regs.v >> 2) & 0x07);
-        let at = self.ppu_bus.read_u8(addr);
+        let at = self.ppu_bus.fetch_u8(addr, PpuFetch::Attribute);
         let shift = ((self.regs.v >> 4) & 0x4) | (self.regs.v & 0x02);
         self.rs.at_data = (at >> shift & 0x03) << 2;
     }
@@ -677,15 +685,14 @@ impl PPU {
         let fine_y = (self.regs.v >> 12) & 0x07;
         let index = self.rs.tile_index as u16;
         let addr = self.background_table.wrapping_add((index << 4) | fine_y);
-        self.rs.tile_low = self.ppu_bus.read_u8(addr);
+        self.rs.tile_low = self.ppu_bus.fetch_u8(addr, PpuFetch::Background);
     }
 
     fn fetch_bg_tile_high(&mut self) {
         let fine_y = (self.regs.v >> 12) & 0x07;
         let index = self.rs.tile_index as u16;
         let addr = self.background_table.wrapping_add((index << 4) | 0x08 | fine_y );
-        self.rs.tile_high = self.ppu_bus.read_u8(addr);
-        
+        self.rs.tile_high = self.ppu_bus.fetch_u8(addr, PpuFetch::Background);
     }
 
     fn store_shift_register(&mut self) {
@@ -722,7 +729,7 @@ impl PPU {
             }
             addr = table.wrapping_add((tile_index << 4) | row);
         }
-        self.rs.tile_low = self.ppu_bus.read_u8(addr);
+        self.rs.tile_low = self.ppu_bus.fetch_u8(addr, PpuFetch::Sprite);
     }
 
     // fetch sprite high byte
@@ -747,7 +754,7 @@ impl PPU {
             }
             addr = table.wrapping_add((tile_index << 4) |  0x08 | row);
         }
-        self.rs.tile_high = self.ppu_bus.read_u8(addr);
+        self.rs.tile_high = self.ppu_bus.fetch_u8(addr, PpuFetch::Sprite);
     }
 
     // store fetch sprite data
@@ -889,6 +896,7 @@ impl PPU {
                             // we read here, becuase some maapers depend on the address bus behavior. eg. MMC3 A12
                             self.fetch_bg_tile_low(); 
                         },
+                        // MMC2/MMC4 latch on the high plane of tiles $FD/$FE, the next tile sees the new bank
                         6 => self.fetch_bg_tile_high(),
                         0 => { 
                             self.store_shift_register(); 
@@ -897,6 +905,8 @@ impl PPU {
                         _ => (),
                     }
                 },
+                // unused nametable fetches at the end of the line, MMC5 counts scanlines with them
+                (0..=239 | 261, 338 | 340) => self.fetch_nt(),
                 _ => (),
             }
             // sprite evalation & fetch logic
@@ -927,11 +937,11 @@ impl PPU {
                 (0..=239 | 261, 257) => self.regs.copy_hori_t(),
                 (0..=239 | 261, 256) => self.regs.inc_vert_v(),
                 (261, 280..=304) => self.regs.copy_vert_t(),
-                (261, 339) => {
-                    if self.rs.is_odd_frame() && self.rs.show_background {
-                        // skip cycle on odd frams
-                        self.rs.inc_cycle();
-                    }
+                // skip cycle on odd frams, the second unused nametable fetch
+                // still happens so MMC5 sees the frame start on time
+                (261, 339) if self.rs.is_odd_frame() && self.rs.show_background && self.rs.extra_vblank_lines == 0 => {
+                    self.fetch_nt();
+                    self.rs.inc_cycle();
                 },
                 _ => (),
             }
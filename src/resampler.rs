@@ -0,0 +1,159 @@
+// band-limited step synthesizer
+//
+// converts a signal clocked at a high rate (eg. the ~1.79 MHz apu output) to an
+// audio sample rate without aliasing. amplitude changes are recorded as deltas at
+// their clock time and spread over a windowed sinc step, then integrated on read.
+use std::f64::consts::PI;
+
+
+// sub-sample positions of the kernel
+const PHASES: usize = 32;
+// kernel width in output samples
+const WIDTH: usize = 16;
+// fixed point fraction bits for clock to sample conversion
+const FRAC_BITS: u32 = 20;
+const FRAC_ONE: u64 = 1 << FRAC_BITS;
+
+
+pub struct Resampler {
+    clock_rate: f64,
+    sample_rate: f64,
+    // output samples per clock, fixed point
+    factor: u64,
+    // position of clock 0 of the current frame, fixed point
+    offset: u64,
+    // accumulated deltas, samples before `avail` are complete
+    buffer: Vec<f32>,
+    avail: usize,
+    // step kernel for every phase
+    kernel: Vec<[f32; WIDTH]>,
+    // running sum of deltas
+    integrator: f32,
+    // dc blocking high pass
+    high_pass: f32,
+    prev_in: f32,
+    prev_out: f32,
+}
+
+
+impl Resampler {
+
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        let mut resampler = Self {
+            clock_rate,
+            sample_rate,
+            factor: 0,
+            offset: 0,
+            buffer: vec![0.0; WIDTH],
+            avail: 0,
+            kernel: Self::build_kernel(),
+            integrator: 0.0,
+            high_pass: 0.0,
+            prev_in: 0.0,
+            prev_out: 0.0,
+        };
+        resampler.set_rates(clock_rate, sample_rate);
+        resampler
+    }
+
+    // windowed sinc impulse for each sub-sample phase, normalized so a step has unit height
+    fn build_kernel() -> Vec<[f32; WIDTH]> {
+        // keep the pass band a little below nyquist
+        let cutoff = 0.9;
+        (0..PHASES).map(|phase| {
+            let frac = phase as f64 / PHASES as f64;
+            let mut taps = [0.0f64; WIDTH];
+            for (i, tap) in taps.iter_mut().enumerate() {
+                let x = i as f64 - (WIDTH / 2) as f64 + 1.0 - frac;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x * cutoff).sin() / (PI * x * cutoff)
+                };
+                // blackman window
+                let n = (i as f64 + 1.0 - frac) / WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+                *tap = sinc * window;
+            }
+            let sum: f64 = taps.iter().sum();
+            let mut kernel = [0.0f32; WIDTH];
+            for (k, tap) in kernel.iter_mut().zip(taps.iter()) {
+                *k = (tap / sum) as f32;
+            }
+            kernel
+        }).collect()
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.clock_rate = clock_rate;
+        self.sample_rate = sample_rate;
+        self.factor = (sample_rate / clock_rate * FRAC_ONE as f64).round() as u64;
+        // one pole high pass around 37 Hz removes the dc offset of the mixer
+        self.high_pass = (-2.0 * PI * 37.0 / sample_rate).exp() as f32;
+    }
+
+    pub fn clock_rate(&self) -> f64 {
+        self.clock_rate
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    // add an amplitude change at clock time relative to the current frame
+    pub fn add_delta(&mut self, clock_time: u32, delta: f32) {
+        let pos = self.offset + clock_time as u64 * self.factor;
+        let index = (pos >> FRAC_BITS) as usize;
+        let phase = (((pos & (FRAC_ONE - 1)) * PHASES as u64) >> FRAC_BITS) as usize;
+        let end = index + WIDTH;
+        if self.buffer.len() < end {
+            self.buffer.resize(end, 0.0);
+        }
+        let kernel = &self.kernel[phase];
+        for (out, k) in self.buffer[index..end].iter_mut().zip(kernel.iter()) {
+            *out += delta * k;
+        }
+    }
+
+    // end the current frame after `clocks` clocks, making its samples available
+    pub fn end_frame(&mut self, clocks: u32) {
+        let pos = self.offset + clocks as u64 * self.factor;
+        self.avail = (pos >> FRAC_BITS) as usize;
+        self.offset = pos;
+        if self.buffer.len() < self.avail + WIDTH {
+            self.buffer.resize(self.avail + WIDTH, 0.0);
+        }
+    }
+
+    // number of samples that can be read
+    pub fn samples_avail(&self) -> usize {
+        self.avail
+    }
+
+    // read available samples, returns the number of samples written
+    pub fn read_samples(&mut self, out: &mut [i16]) -> usize {
+        let count = out.len().min(self.avail);
+        for (sample, delta) in out.iter_mut().zip(self.buffer.iter()).take(count) {
+            self.integrator += delta;
+            let filtered = self.integrator - self.prev_in + self.high_pass * self.prev_out;
+            self.prev_in = self.integrator;
+            self.prev_out = filtered;
+            *sample = (filtered * 32767.0).clamp(-32768.0, 32767.0) as i16;
+        }
+        // drop the consumed samples
+        self.buffer.drain(..count);
+        self.buffer.resize(self.buffer.len().max(WIDTH), 0.0);
+        self.avail -= count;
+        self.offset -= (count as u64) << FRAC_BITS;
+        count
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.iter_mut().for_each(|x| *x = 0.0);
+        self.avail = 0;
+        self.offset &= FRAC_ONE - 1;
+        self.integrator = 0.0;
+        self.prev_in = 0.0;
+        self.prev_out = 0.0;
+    }
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// write changed ram back about every five seconds
+const AUTOSAVE_FRAMES: u32 = 300;
+
+
+// battery backed prg ram kept in a .sav file next to the rom
+pub struct SaveFile {
+    path: PathBuf,
+    // ram contents at the last load or save
+    saved: Vec<u8>,
+    frames: u32,
+}
+
+
+impl SaveFile {
+
+    pub fn for_rom(rom: &str) -> Self {
+        Self {
+            path: Path::new(rom).with_extension("sav"),
+            saved: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // fill ram from the save file, returns false when there is no save yet
+    pub fn load(&mut self, ram: &mut [u8]) -> io::Result<bool> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.saved = ram.to_vec();
+                return Ok(false);
+            },
+            Err(e) => return Err(e),
+        };
+        // a save of a different size still loads as much as fits
+        let len = data.len().min(ram.len());
+        ram[..len].copy_from_slice(&data[..len]);
+        self.saved = ram.to_vec();
+        Ok(true)
+    }
+
+    // call once per frame, saves periodically when ram changed
+    pub fn autosave(&mut self, ram: &[u8]) -> io::Result<()> {
+        self.frames += 1;
+        if self.frames < AUTOSAVE_FRAMES {
+            return Ok(());
+        }
+        self.frames = 0;
+        self.flush(ram)
+    }
+
+    // write ram if it changed since the last save
+    pub fn flush(&mut self, ram: &[u8]) -> io::Result<()> {
+        if self.saved == ram {
+            return Ok(());
+        }
+        // write a temporary file first so a crash never leaves a half written save
+        let tmp = self.path.with_extension("sav.tmp");
+        fs::write(&tmp, ram)?;
+        fs::rename(&tmp, &self.path)?;
+        self.saved = ram.to_vec();
+        Ok(())
+    }
+}
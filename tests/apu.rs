@@ -0,0 +1,417 @@
+use nes::apu::APU;
+use nes::board::{Signal, IRQ_DMC, IRQ_FRAME_COUNTER};
+
+
+// 4-step quarter frames in cpu cycles, the sequence repeats every 29830
+const QUARTER_FRAMES: [u32; 4] = [7457, 14913, 22371, 29829];
+
+fn quarter_frame(n: usize) -> u32 {
+    n as u32 / 4 * 29830 + QUARTER_FRAMES[n % 4]
+}
+
+fn run(apu: &mut APU, cycles: u32) {
+    for _ in 0..cycles {
+        apu.tick();
+    }
+}
+
+// 3 * triangle + 2 * noise + dmc back from the mixer
+fn tnd_level(apu: &APU) -> u8 {
+    match apu.output() {
+        out if out <= 0.0 => 0,
+        out => (24329.0 / (163.67 / out - 100.0)).round() as u8,
+    }
+}
+
+fn tnd_out(level: u8) -> f32 {
+    match level {
+        0 => 0.0,
+        n => 163.67 / (24329.0 / n as f32 + 100.0),
+    }
+}
+
+// pulse1 + pulse2 back from the mixer. a silent triangle holds its first
+// step, 15, and the others are quiet
+fn pulse_level(apu: &APU) -> u8 {
+    match apu.output() - tnd_out(3 * 15) {
+        out if out <= 1e-6 => 0,
+        out => (8128.0 / (95.52 / out - 100.0)).round() as u8,
+    }
+}
+
+// the loudest pulse level over the next cycles
+fn pulse_peak(apu: &mut APU, cycles: u32) -> u8 {
+    (0..cycles).map(|_| {
+        apu.tick();
+        pulse_level(apu)
+    }).max().unwrap()
+}
+
+// a pulse channel at $4000 or $4004 with a 50% duty and its length counter
+// enabled and loaded
+fn pulse(base: u16, control: u8, sweep: u8, period: u16) -> APU {
+    let mut apu = APU::new(Signal::default());
+    apu.write_u8(0x4015, 0x03);
+    apu.write_u8(base, 0x80 | control);
+    apu.write_u8(base + 1, sweep);
+    apu.write_u8(base + 2, period as u8);
+    apu.write_u8(base + 3, 0x08 | (period >> 8) as u8);
+    apu
+}
+
+
+// the first half frame sweeps a period of 9 by 9 >> 3. pulse 2 lands on 8,
+// pulse 1 subtracts one more and drops below 8, which mutes it
+#[test]
+fn sweep_negate_ones_complement_on_pulse_1() {
+    for (base, level) in [(0x4000, 0), (0x4004, 15)] {
+        // halt, constant volume 15. sweep enabled, period 0, negate, shift 3
+        let mut apu = pulse(base, 0x3f, 0x8b, 9);
+        assert_eq!(pulse_peak(&mut apu, 200), 15);
+        run(&mut apu, QUARTER_FRAMES[1] - 200);
+        assert_eq!(pulse_peak(&mut apu, 200), level, "{:04x}", base);
+    }
+}
+
+// a target period past $7ff mutes the channel whether the sweep is enabled
+// or not, negated targets never overflow
+#[test]
+fn sweep_target_overflow_mutes() {
+    for (sweep, period, level) in [
+        // 0x555 + 0x2aa = 0x7ff
+        (0x01, 0x555, 15),
+        // 0x556 + 0x2ab = 0x801
+        (0x01, 0x556, 0),
+        (0x81, 0x556, 0),
+        (0x09, 0x556, 15),
+        // shift 0 still adds the period to itself
+        (0x00, 0x400, 0),
+        (0x00, 0x3ff, 15),
+        // below 8 is muted either way
+        (0x09, 0x007, 0),
+    ] {
+        let mut apu = pulse(0x4000, 0x3f, sweep, period);
+        assert_eq!(pulse_peak(&mut apu, 10), level, "{:02x} {:03x}", sweep, period);
+    }
+}
+
+// lengths only load while enabled, count down on half frames unless halted
+// and clear when the channel is disabled
+#[test]
+fn length_counter_halt_and_load() {
+    for (base, bit) in [(0x4000, 0x01), (0x4004, 0x02)] {
+        let mut apu = APU::new(Signal::default());
+        // length index 3 is 2 half frames
+        apu.write_u8(base + 3, 0x18);
+        assert_eq!(apu.read_u8(0x4015) & bit, 0);
+        apu.write_u8(0x4015, bit);
+        apu.write_u8(base + 3, 0x18);
+        run(&mut apu, QUARTER_FRAMES[3] - 1);
+        assert_eq!(apu.read_u8(0x4015) & bit, bit);
+        run(&mut apu, 1);
+        assert_eq!(apu.read_u8(0x4015) & bit, 0);
+
+        apu.write_u8(base, 0x20);
+        apu.write_u8(base + 3, 0x18);
+        run(&mut apu, 2 * 29830);
+        assert_eq!(apu.read_u8(0x4015) & bit, bit);
+        apu.write_u8(base, 0x00);
+        run(&mut apu, 29830);
+        assert_eq!(apu.read_u8(0x4015) & bit, 0);
+
+        apu.write_u8(base + 3, 0x18);
+        assert_eq!(apu.read_u8(0x4015) & bit, bit);
+        apu.write_u8(0x4015, 0x00);
+        assert_eq!(apu.read_u8(0x4015) & bit, 0);
+    }
+}
+
+// with a divider period of 0 the decay level starts at 15 on the first
+// quarter frame and drops by one on every following one
+#[test]
+fn envelope_decay_and_loop() {
+    for (control, tail) in [(0x00, [0, 0]), (0x20, [15, 14])] {
+        let mut apu = pulse(0x4000, control, 0x08, 0x10);
+        let mut levels = vec![pulse_peak(&mut apu, 300)];
+        let mut now = 300;
+        for n in 0..18 {
+            run(&mut apu, quarter_frame(n) - now);
+            levels.push(pulse_peak(&mut apu, 300));
+            now = quarter_frame(n) + 300;
+        }
+        let decay: Vec<u8> = (0..=15).rev().chain(tail).collect();
+        assert_eq!(levels[0], 0);
+        assert_eq!(levels[1..], decay[..], "{:02x}", control);
+    }
+}
+
+// $4015 reads the length counters of the enabled channels
+#[test]
+fn status_reads_length_counters() {
+    let mut apu = APU::new(Signal::default());
+    apu.write_u8(0x4015, 0x0f);
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+    for (addr, bits) in [(0x4003, 0x01), (0x4007, 0x03), (0x400b, 0x07), (0x400f, 0x0f)] {
+        apu.write_u8(addr, 0x08);
+        assert_eq!(apu.read_u8(0x4015), bits);
+    }
+    // reading leaves them alone
+    assert_eq!(apu.read_u8(0x4015), 0x0f);
+    apu.write_u8(0x4015, 0x0d);
+    assert_eq!(apu.read_u8(0x4015), 0x0d);
+    apu.write_u8(0x4015, 0x00);
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+}
+
+// ticks on which the quarter frames changed the noise envelope and on which
+// the half frames ran out length counters of 2 on pulse 1 and 4 on pulse 2,
+// with $4017 written after `at` cycles
+fn sequencer_clocks(write: Option<(u32, u8)>) -> (Vec<u32>, Vec<u32>) {
+    let mut apu = APU::new(Signal::default());
+    apu.write_u8(0x4015, 0x0b);
+    apu.write_u8(0x4003, 0x18);
+    apu.write_u8(0x4007, 0x28);
+    // envelope with a divider period of 0 on the slowest noise period, the
+    // shift register leaves bit 0 clear for 14 of its clocks
+    apu.write_u8(0x400c, 0x00);
+    apu.write_u8(0x400e, 0x0f);
+    apu.write_u8(0x400f, 0x08);
+    let (mut quarters, mut lengths) = (Vec::new(), Vec::new());
+    let (mut level, mut status) = (tnd_level(&apu), 0x03);
+    for tick in 1..=60000 {
+        if let Some((at, val)) = write {
+            if tick == at + 1 {
+                apu.write_u8(0x4017, val);
+            }
+        }
+        apu.tick();
+        if tick < 50000 && tnd_level(&apu) != level {
+            level = tnd_level(&apu);
+            quarters.push(tick);
+        }
+        if apu.read_u8(0x4015) & 0x03 != status {
+            status = apu.read_u8(0x4015) & 0x03;
+            lengths.push(tick);
+        }
+    }
+    (quarters, lengths)
+}
+
+#[test]
+fn four_and_five_step_sequences() {
+    let (quarters, lengths) = sequencer_clocks(None);
+    assert_eq!(quarters, [7457, 14913, 22371, 29829, 37287, 44743]);
+    assert_eq!(lengths, [29829, 59659]);
+    // the write lands 3 cycles later on an even cycle, the 5-step mode clocks
+    // a quarter and a half frame right away and has its 4th step at 37281
+    let (quarters, lengths) = sequencer_clocks(Some((0, 0x80)));
+    assert_eq!(quarters, [3, 7459, 14915, 22373, 37283, 44741]);
+    assert_eq!(lengths, [14915, 52197]);
+    // 4 cycles on an odd one
+    let (quarters, _) = sequencer_clocks(Some((1, 0x80)));
+    assert_eq!(quarters[..2], [5, 7461]);
+    // a 4-step write restarts the sequence without clocking
+    let (quarters, lengths) = sequencer_clocks(Some((0, 0x00)));
+    assert_eq!(quarters[..2], [7459, 14915]);
+    assert_eq!(lengths[0], 29831);
+}
+
+// the 4-step sequence raises the frame irq on its last 3 cycles, a $4015
+// read acknowledges it
+#[test]
+fn frame_irq() {
+    let irq = Signal::default();
+    let mut apu = APU::new(irq.clone());
+    run(&mut apu, 29827);
+    assert_eq!(*irq.borrow(), 0);
+    run(&mut apu, 1);
+    assert_eq!(*irq.borrow(), IRQ_FRAME_COUNTER);
+    // set again on the next cycle after a read
+    assert_eq!(apu.read_u8(0x4015), 0x40);
+    assert_eq!(*irq.borrow(), 0);
+    run(&mut apu, 1);
+    assert_eq!(*irq.borrow(), IRQ_FRAME_COUNTER);
+    run(&mut apu, 1);
+    assert_eq!(apu.read_u8(0x4015), 0x40);
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+    assert_eq!(*irq.borrow(), 0);
+    run(&mut apu, 29830);
+    assert_eq!(*irq.borrow(), IRQ_FRAME_COUNTER);
+    // the inhibit flag clears the irq and keeps it from being set
+    apu.write_u8(0x4017, 0x40);
+    assert_eq!(*irq.borrow(), 0);
+    run(&mut apu, 2 * 29830);
+    assert_eq!(*irq.borrow(), 0);
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+    // never set in the 5-step mode
+    let mut apu = APU::new(irq.clone());
+    apu.write_u8(0x4017, 0x80);
+    run(&mut apu, 2 * 37282);
+    assert_eq!(*irq.borrow(), 0);
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+}
+
+// whether the triangle steps over the next cycles
+fn triangle_moves(apu: &mut APU, cycles: u32) -> bool {
+    let level = tnd_level(apu);
+    let mut moved = false;
+    for _ in 0..cycles {
+        apu.tick();
+        moved |= tnd_level(apu) != level;
+    }
+    moved
+}
+
+// the linear counter is reloaded on the quarter frame after a $400b write and
+// silences the triangle when it runs out. with the control flag set the
+// reload flag stays on and so does the triangle
+#[test]
+fn triangle_linear_counter_reload() {
+    let mut apu = APU::new(Signal::default());
+    apu.write_u8(0x4015, 0x04);
+    apu.write_u8(0x4008, 0x02);
+    apu.write_u8(0x400a, 0x10);
+    apu.write_u8(0x400b, 0x08);
+    let mut now = 0;
+    let mut moves_at = |apu: &mut APU, at: u32| {
+        run(apu, at - now);
+        now = at + 100;
+        triangle_moves(apu, 100)
+    };
+    assert!(!moves_at(&mut apu, 7000));
+    assert!(moves_at(&mut apu, 7500));
+    assert!(moves_at(&mut apu, 22000));
+    assert!(!moves_at(&mut apu, 22500));
+    apu.write_u8(0x4008, 0x82);
+    apu.write_u8(0x400b, 0x08);
+    assert!(!moves_at(&mut apu, 29700));
+    assert!(moves_at(&mut apu, 30000));
+    assert!(moves_at(&mut apu, 100000));
+    // cleared control lets the next quarter frame clear the reload flag
+    apu.write_u8(0x4008, 0x02);
+    assert!(moves_at(&mut apu, 100000 + 2 * 7457));
+    assert!(!moves_at(&mut apu, 100000 + 4 * 7457));
+}
+
+// shift register bit 0 after each of `count` noise clocks on the shortest
+// period, a clock every 4 cycles from the first
+fn noise_bits(mode: u8, count: usize) -> Vec<bool> {
+    let mut apu = APU::new(Signal::default());
+    apu.write_u8(0x4015, 0x08);
+    apu.write_u8(0x400c, 0x3f);
+    apu.write_u8(0x400e, mode);
+    apu.write_u8(0x400f, 0x08);
+    let silent = tnd_level(&apu);
+    (0..count).map(|_| {
+        run(&mut apu, 4);
+        tnd_level(&apu) == silent
+    }).collect()
+}
+
+// the shortest shift that repeats the first `window` bits
+fn repeats_after(bits: &[bool], window: usize) -> usize {
+    (1..=bits.len() - window).find(|&shift| bits[shift..shift + window] == bits[..window]).unwrap()
+}
+
+// feedback from bit 1 gives the 32767 step sequence, from bit 6 in the
+// short mode a 93 step one
+#[test]
+fn noise_short_and_long_mode() {
+    let long = noise_bits(0x00, 32767 + 200);
+    assert_eq!(repeats_after(&long, 200), 32767);
+    let short = noise_bits(0x80, 400);
+    assert_eq!(repeats_after(&short, 200), 93);
+}
+
+// addresses the dmc fetched over the cycles, fed with 0x55
+fn dmc_fetches(apu: &mut APU, cycles: u32) -> Vec<u16> {
+    let mut fetched = Vec::new();
+    for _ in 0..cycles {
+        if let Some(addr) = apu.dmc_fetch_addr() {
+            fetched.push(addr);
+            apu.dmc_load_sample(0x55);
+        }
+        apu.tick();
+    }
+    fetched
+}
+
+// a byte every 8 timer clocks from $c000 + $4012 * 64 for $4013 * 16 + 1
+// bytes, wrapping from $ffff to $8000. looping starts over
+#[test]
+fn dmc_fetch_and_loop() {
+    let mut apu = APU::new(Signal::default());
+    apu.write_u8(0x4017, 0x40);
+    apu.write_u8(0x4010, 0x0f);
+    apu.write_u8(0x4012, 0x01);
+    apu.write_u8(0x4013, 0x01);
+    apu.write_u8(0x4015, 0x10);
+    assert_eq!(apu.read_u8(0x4015), 0x10);
+    assert_eq!(dmc_fetches(&mut apu, 10000), (0xc040..=0xc050).collect::<Vec<_>>());
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+
+    apu.write_u8(0x4012, 0xff);
+    apu.write_u8(0x4013, 0x04);
+    apu.write_u8(0x4015, 0x10);
+    let fetched = dmc_fetches(&mut apu, 40000);
+    assert_eq!(fetched.len(), 65);
+    assert_eq!(fetched[63..], [0xffff, 0x8000]);
+
+    apu.write_u8(0x4010, 0x4f);
+    apu.write_u8(0x4012, 0x01);
+    apu.write_u8(0x4013, 0x00);
+    apu.write_u8(0x4015, 0x10);
+    assert_eq!(dmc_fetches(&mut apu, 2000)[..4], [0xc040; 4]);
+    assert_eq!(apu.read_u8(0x4015), 0x10);
+}
+
+// the end of a sample raises the dmc irq when enabled, writes to $4015 or
+// clearing the enable bit acknowledge it
+#[test]
+fn dmc_irq_flag() {
+    let irq = Signal::default();
+    let mut apu = APU::new(irq.clone());
+    // no frame irqs
+    apu.write_u8(0x4017, 0x40);
+    apu.write_u8(0x4010, 0x8f);
+    apu.write_u8(0x4015, 0x10);
+    assert_eq!(dmc_fetches(&mut apu, 1), [0xc000]);
+    assert_eq!(*irq.borrow(), IRQ_DMC);
+    // reads leave it set
+    assert_eq!(apu.read_u8(0x4015), 0x80);
+    assert_eq!(apu.read_u8(0x4015), 0x80);
+    apu.write_u8(0x4015, 0x00);
+    assert_eq!(*irq.borrow(), 0);
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+
+    apu.write_u8(0x4015, 0x10);
+    dmc_fetches(&mut apu, 1000);
+    assert_eq!(*irq.borrow(), IRQ_DMC);
+    apu.write_u8(0x4010, 0x0f);
+    assert_eq!(*irq.borrow(), 0);
+    // a looping sample never ends
+    apu.write_u8(0x4010, 0xcf);
+    apu.write_u8(0x4015, 0x10);
+    dmc_fetches(&mut apu, 5000);
+    assert_eq!(*irq.borrow(), 0);
+}
+
+// enabling through $4015 restarts a finished sample, not a playing one, and
+// disabling stops it
+#[test]
+fn dmc_restart_through_4015() {
+    let mut apu = APU::new(Signal::default());
+    apu.write_u8(0x4017, 0x40);
+    apu.write_u8(0x4010, 0x0f);
+    apu.write_u8(0x4013, 0x01);
+    apu.write_u8(0x4015, 0x10);
+    assert_eq!(dmc_fetches(&mut apu, 500), [0xc000, 0xc001]);
+    apu.write_u8(0x4012, 0x01);
+    apu.write_u8(0x4015, 0x10);
+    assert_eq!(dmc_fetches(&mut apu, 500), [0xc002]);
+    apu.write_u8(0x4015, 0x00);
+    assert_eq!(apu.read_u8(0x4015), 0x00);
+    assert_eq!(dmc_fetches(&mut apu, 1000), []);
+    apu.write_u8(0x4015, 0x10);
+    assert_eq!(dmc_fetches(&mut apu, 1000)[..2], [0xc040, 0xc041]);
+}
@@ -0,0 +1,333 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use nes::board::{Region, Signal};
+use nes::cartridge::{Cartridge, CartridgeError, ConsoleType, Timing};
+use nes::cpu::CPUBus;
+use nes::mapper::Mapper;
+use nes::ppu::PPU;
+use nes::apu::APU;
+use nes::controller::Controller;
+
+
+// nes 2.0 header, byte 9 holds the msb nibbles of both rom sizes
+fn nes2_header(mapper: u8, prg_lsb: u8, chr_lsb: u8, size_msb: u8) -> Vec<u8> {
+    vec![b'N', b'E', b'S', 0x1a, prg_lsb, chr_lsb, mapper << 4, 0x08 | (mapper & 0xf0), 0, size_msb, 0, 0, 0, 0, 0, 0]
+}
+
+fn read(data: &[u8]) -> Result<Cartridge, CartridgeError> {
+    Cartridge::read(&mut &data[..], Signal::default())
+}
+
+fn with_roms(mut header: Vec<u8>, prg: usize, chr: usize) -> Vec<u8> {
+    header.resize(header.len() + prg + chr, 0);
+    header
+}
+
+
+// 2^E * (MM * 2 + 1) when the msb nibble is $f
+#[test]
+fn exponent_form_rom_sizes() {
+    // 2^14 PRG and 2^13 * 3 CHR on CNROM
+    let data = with_roms(nes2_header(3, 14 << 2, 13 << 2 | 1, 0xff), 0x4000, 0x6000);
+    let cartridge = read(&data).ok().unwrap();
+    assert_eq!(cartridge.header().prg_rom_size(), 0x4000);
+    assert_eq!(cartridge.header().chr_rom_size(), 0x6000);
+}
+
+// 2^63 * 7 would abort on the allocation
+#[test]
+fn huge_exponent_form_size_is_rejected() {
+    let data = nes2_header(0, 0xff, 0, 0x0f);
+    assert!(matches!(read(&data).err(), Some(CartridgeError::InconsistentSize(_))));
+    let data = nes2_header(0, 1, 0xff, 0xf0);
+    assert!(matches!(read(&data).err(), Some(CartridgeError::InconsistentSize(_))));
+}
+
+// a 1M CHR ROM that isn't in the file is reported without reading past the end
+#[test]
+fn exponent_form_size_past_the_file_is_truncated() {
+    let data = with_roms(nes2_header(3, 14 << 2, 20 << 2, 0xff), 0x4000, 0x100);
+    match read(&data).err() {
+        Some(CartridgeError::Truncated { section, expected, actual }) => {
+            assert_eq!((section, expected, actual), ("CHR ROM", 0x100000, 0x100));
+        },
+        _ => panic!("expected a truncated chr rom"),
+    }
+}
+
+// ines 1.0 header with 16K prg and 8K chr units
+fn ines_header(mapper: u8, num_prg: u8, num_chr: u8) -> Vec<u8> {
+    vec![b'N', b'E', b'S', 0x1a, num_prg, num_chr, mapper << 4, mapper & 0xf0, 0, 0, 0, 0, 0, 0, 0, 0]
+}
+
+fn inconsistent(data: &[u8]) -> bool {
+    matches!(read(data).err(), Some(CartridgeError::InconsistentSize(_)))
+}
+
+#[test]
+fn bad_magic() {
+    let mut data = with_roms(ines_header(0, 1, 1), 0x4000, 0x2000);
+    data[3] = 0x00;
+    match read(&data).err() {
+        Some(CartridgeError::BadMagic(magic)) => assert_eq!(magic, [b'N', b'E', b'S', 0x00]),
+        _ => panic!("expected bad magic"),
+    }
+}
+
+#[test]
+fn truncated_sections() {
+    let data = ines_header(0, 1, 1);
+    match read(&data[..10]).err() {
+        Some(CartridgeError::Truncated { section, expected, actual }) => assert_eq!((section, expected, actual), ("header", 16, 10)),
+        _ => panic!("expected a truncated header"),
+    }
+    let data = with_roms(ines_header(0, 1, 1), 0x4000, 0x1000);
+    match read(&data).err() {
+        Some(CartridgeError::Truncated { section, expected, actual }) => assert_eq!((section, expected, actual), ("CHR ROM", 0x2000, 0x1000)),
+        _ => panic!("expected a truncated chr rom"),
+    }
+}
+
+#[test]
+fn unsupported_mapper() {
+    let data = with_roms(ines_header(99, 2, 1), 0x8000, 0x2000);
+    match read(&data).err() {
+        Some(CartridgeError::UnsupportedMapper { number, name }) => assert_eq!((number, name), (99, "unknown")),
+        _ => panic!("expected an unsupported mapper"),
+    }
+}
+
+#[test]
+fn rom_sizes_must_fit_the_board() {
+    // no prg rom
+    assert!(inconsistent(&ines_header(2, 0, 0)));
+    // NROM maps 32K
+    assert!(inconsistent(&with_roms(ines_header(0, 3, 1), 0xc000, 0x2000)));
+    assert!(inconsistent(&with_roms(ines_header(0, 2, 2), 0x8000, 0x4000)));
+    // MMC3 fixes the last two 8K banks
+    assert!(inconsistent(&with_roms(nes2_header(4, 13 << 2, 0, 0x0f), 0x2000, 0)));
+    // AxROM switches 32K
+    assert!(inconsistent(&with_roms(ines_header(7, 3, 0), 0xc000, 0)));
+    // MMC1 switches 4K of chr, 2K isn't a bank
+    assert!(inconsistent(&with_roms(nes2_header(1, 2, 11 << 2, 0xf0), 0x8000, 0x800)));
+    // the same boards with sizes they can map
+    assert!(read(&with_roms(ines_header(0, 2, 1), 0x8000, 0x2000)).is_ok());
+    assert!(read(&with_roms(ines_header(4, 8, 16), 0x20000, 0x20000)).is_ok());
+    assert!(read(&with_roms(ines_header(7, 8, 0), 0x20000, 0)).is_ok());
+    assert!(read(&with_roms(ines_header(1, 16, 0), 0x40000, 0)).is_ok());
+}
+
+// 32K PRG and 8K CHR NROM with the given header bytes 6 to 15
+fn nes2_with(bytes: &[(usize, u8)]) -> Cartridge {
+    let mut data = with_roms(nes2_header(0, 2, 1, 0), 0x8000, 0x2000);
+    for (index, val) in bytes {
+        data[*index] = *val;
+    }
+    read(&data).ok().unwrap()
+}
+
+#[test]
+fn submapper_nibble() {
+    // mapper 4 submapper 4, and the mapper msb nibble from byte 8
+    let mut data = with_roms(nes2_header(4, 2, 1, 0), 0x8000, 0x2000);
+    data[8] = 0x40;
+    let cartridge = read(&data).ok().unwrap();
+    assert_eq!((cartridge.header().mapper_number(), cartridge.header().submapper()), (4, 4));
+    data[8] = 0x41;
+    assert!(matches!(read(&data).err(), Some(CartridgeError::UnsupportedMapper { number: 0x104, .. })));
+}
+
+// 64 << shift, with shift 0 for none
+#[test]
+fn ram_sizes_from_shift_counts() {
+    let cartridge = nes2_with(&[(10, 0x97)]);
+    let header = cartridge.header();
+    assert_eq!((header.prg_ram_size(), header.prg_nvram_size()), (64 << 7, 64 << 9));
+    assert_eq!((header.chr_ram_size(), header.chr_nvram_size()), (0, 0));
+    let cartridge = nes2_with(&[(11, 0x17)]);
+    let header = cartridge.header();
+    assert_eq!((header.prg_ram_size(), header.prg_nvram_size()), (0, 0));
+    assert_eq!((header.chr_ram_size(), header.chr_nvram_size()), (64 << 7, 64 << 1));
+}
+
+#[test]
+fn timing() {
+    for (val, timing, region) in [
+        (0, Timing::Ntsc, Region::Ntsc),
+        (1, Timing::Pal, Region::Pal),
+        (2, Timing::MultiRegion, Region::Ntsc),
+        (3, Timing::Dendy, Region::Ntsc),
+    ] {
+        let cartridge = nes2_with(&[(12, val)]);
+        assert_eq!((cartridge.header().timing(), cartridge.header().region()), (timing, region));
+    }
+}
+
+#[test]
+fn console_type() {
+    for (flags, extended, console) in [
+        (0x08, 0, ConsoleType::Nes),
+        (0x09, 0, ConsoleType::VsSystem),
+        (0x0a, 0, ConsoleType::Playchoice),
+        (0x0b, 0x03, ConsoleType::Extended(3)),
+    ] {
+        let cartridge = nes2_with(&[(7, flags), (13, extended)]);
+        assert_eq!(cartridge.header().console_type(), console);
+    }
+}
+
+#[test]
+fn expansion_device() {
+    let cartridge = nes2_with(&[(15, 0x01)]);
+    assert_eq!(cartridge.header().expansion_device(), 1);
+    // the upper two bits are reserved
+    let cartridge = nes2_with(&[(15, 0xea)]);
+    assert_eq!(cartridge.header().expansion_device(), 0x2a);
+}
+
+// byte 7 bits 2-3 other than 10 is ines 1.0, the nes 2.0 bytes are ignored
+#[test]
+fn ines_1_0_header_keeps_the_old_sizing() {
+    for flags in [0x00, 0x04, 0x0c] {
+        let mut data = with_roms(ines_header(0, 2, 1), 0x8000, 0x2000);
+        data[7] = flags;
+        // a nes 2.0 size msb, ram shifts, timing and expansion device
+        data[9] = 0x10;
+        data[10] = 0x07;
+        data[12] = 0x03;
+        data[15] = 0x01;
+        let cartridge = read(&data).ok().unwrap();
+        let header = cartridge.header();
+        assert!(!header.is_nes2());
+        assert_eq!((header.prg_rom_size(), header.chr_rom_size()), (0x8000, 0x2000));
+        assert_eq!((header.prg_ram_size(), header.prg_nvram_size(), header.chr_ram_size()), (0x2000, 0, 0));
+        assert_eq!((header.submapper(), header.expansion_device()), (0, 0));
+    }
+    // ines 1.0 without chr rom has 8K chr ram
+    let cartridge = read(&with_roms(ines_header(2, 2, 0), 0x8000, 0)).ok().unwrap();
+    assert_eq!(cartridge.header().chr_ram_size(), 0x2000);
+}
+
+// ines header flag 6 bit 2 puts 512 bytes between the header and prg rom
+fn with_trainer(mut header: Vec<u8>, prg: usize, chr: usize) -> (Vec<u8>, Vec<u8>) {
+    header[6] |= 0x04;
+    let trainer: Vec<u8> = (0..512).map(|i| (i as u8) ^ 0xa5).collect();
+    header.extend(&trainer);
+    (with_roms(header, prg, chr), trainer)
+}
+
+fn cpu_bus(cartridge: Cartridge) -> (CPUBus, Rc<RefCell<Box<dyn Mapper>>>) {
+    let mapper = cartridge.to_mapper();
+    let ppu = Rc::new(RefCell::new(PPU::new(Rc::clone(&mapper), Signal::default())));
+    let apu = Rc::new(RefCell::new(APU::new(Signal::default())));
+    let bus = CPUBus::new(ppu, apu, Rc::clone(&mapper), Rc::new(RefCell::new(Controller::new())));
+    (bus, mapper)
+}
+
+#[test]
+fn trainer_is_loaded_at_7000() {
+    let (data, trainer) = with_trainer(ines_header(0, 2, 1), 0x8000, 0x2000);
+    let cartridge = read(&data).ok().unwrap();
+    let (bus, _mapper) = cpu_bus(cartridge);
+    let loaded: Vec<u8> = (0x7000..0x7200).map(|addr| bus.read_u8(addr)).collect();
+    assert_eq!(loaded, trainer);
+    // nothing else of the ram is touched
+    assert_eq!(bus.read_u8(0x6fff), 0);
+    assert_eq!(bus.read_u8(0x7200), 0);
+}
+
+// SOROM banks 16K of ram at $6000, the trainer goes to the bank the cpu sees
+#[test]
+fn trainer_goes_to_the_selected_ram_bank() {
+    let mut header = nes2_header(1, 16, 0, 0);
+    header[10] = 0x08;
+    let (data, trainer) = with_trainer(header, 0x40000, 0);
+    let cartridge = read(&data).ok().unwrap();
+    let (bus, mapper) = cpu_bus(cartridge);
+    let loaded: Vec<u8> = (0x7000..0x7200).map(|addr| bus.read_u8(addr)).collect();
+    assert_eq!(loaded, trainer);
+    let mapper = mapper.borrow();
+    let ram = mapper.prg_ram().unwrap();
+    assert_eq!(ram.size(), 0x4000);
+    assert_eq!(&ram[0x1000..0x1200], &trainer[..]);
+    assert!(ram[0x3000..0x3200].iter().all(|x| *x == 0));
+}
+
+// writes a different value through each nametable quadrant, then reads what
+// every quadrant shows. quadrants sharing a page show the last write
+fn quadrants(mapper: &Rc<RefCell<Box<dyn Mapper>>>, base: u16) -> [u8; 4] {
+    let mut mapper = mapper.borrow_mut();
+    for quadrant in 0..4u16 {
+        mapper.write_u8(base + quadrant * 0x400 + 0x123, quadrant as u8 + 1);
+    }
+    let mut out = [0; 4];
+    for (quadrant, val) in out.iter_mut().enumerate() {
+        *val = mapper.read_u8(0x2000 + quadrant as u16 * 0x400 + 0x123);
+    }
+    out
+}
+
+fn mapper_for(mut header: Vec<u8>, flags: u8, prg: usize, chr: usize) -> Rc<RefCell<Box<dyn Mapper>>> {
+    header[6] |= flags;
+    read(&with_roms(header, prg, chr)).ok().unwrap().to_mapper()
+}
+
+#[test]
+fn header_mirroring() {
+    let mapper = mapper_for(ines_header(0, 2, 1), 0x00, 0x8000, 0x2000);
+    assert_eq!(quadrants(&mapper, 0x2000), [2, 2, 4, 4]);
+    let mapper = mapper_for(ines_header(0, 2, 1), 0x01, 0x8000, 0x2000);
+    assert_eq!(quadrants(&mapper, 0x2000), [3, 4, 3, 4]);
+    // $3000-$3EFF mirrors the nametables
+    assert_eq!(quadrants(&mapper, 0x3000), [3, 4, 3, 4]);
+}
+
+// flag 6 bit 3 gives every quadrant its own page, the lower two from the
+// console vram and the upper two from the cartridge
+#[test]
+fn four_screen_flag() {
+    for flags in [0x08, 0x09] {
+        let mapper = mapper_for(ines_header(0, 2, 1), flags, 0x8000, 0x2000);
+        assert_eq!(quadrants(&mapper, 0x2000), [1, 2, 3, 4]);
+    }
+    // mapper mirroring control can't undo it
+    let mapper = mapper_for(ines_header(4, 2, 1), 0x08, 0x8000, 0x2000);
+    mapper.borrow_mut().write_u8(0xa000, 1);
+    assert_eq!(quadrants(&mapper, 0x2000), [1, 2, 3, 4]);
+    mapper.borrow_mut().write_u8(0xa000, 0);
+    assert_eq!(quadrants(&mapper, 0x2000), [1, 2, 3, 4]);
+}
+
+// AxROM picks one of the two console vram pages for all quadrants
+#[test]
+fn single_screen_pages() {
+    let mapper = mapper_for(ines_header(7, 2, 0), 0x00, 0x8000, 0);
+    assert_eq!(quadrants(&mapper, 0x2000), [4, 4, 4, 4]);
+    mapper.borrow_mut().write_u8(0x8000, 0x10);
+    assert_eq!(quadrants(&mapper, 0x2000), [4, 4, 4, 4]);
+    mapper.borrow_mut().write_u8(0x2000, 0x77);
+    // the lower page still holds what was written to it
+    mapper.borrow_mut().write_u8(0x8000, 0x00);
+    assert_eq!(mapper.borrow_mut().read_u8(0x2000), 0);
+    assert_eq!(mapper.borrow_mut().read_u8(0x2123), 4);
+    mapper.borrow_mut().write_u8(0x8000, 0x10);
+    assert_eq!(mapper.borrow_mut().read_u8(0x2c00), 0x77);
+}
+
+// Namco 163 maps chr rom banks or console vram into each quadrant
+#[test]
+fn chr_rom_quadrant_pages() {
+    let mut data = with_roms(ines_header(19, 2, 2), 0x8000, 0x4000);
+    let chr = data.len() - 0x4000;
+    for (i, x) in data[chr..].iter_mut().enumerate() {
+        *x = (i / 0x400) as u8 + 0x40;
+    }
+    let mapper = read(&data).ok().unwrap().to_mapper();
+    for (quadrant, val) in [(0, 0x05), (1, 0xe0), (2, 0x0c), (3, 0xe1)] {
+        mapper.borrow_mut().write_u8(0xc000 + quadrant * 0x800, val);
+    }
+    // chr rom quadrants read the bank and ignore writes
+    assert_eq!(quadrants(&mapper, 0x2000), [0x45, 2, 0x4c, 4]);
+    assert_eq!(mapper.borrow_mut().read_u8(0x23ff), 0x45);
+    assert_eq!(mapper.borrow_mut().read_u8(0x2bff), 0x4c);
+}
@@ -0,0 +1,455 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use nes::cpu::{CPU, CpuEvent};
+use nes::board::{Region, Signal, IRQ_MAPPER};
+use nes::cartridge::Cartridge;
+use nes::mapper::Mapper;
+use nes::ppu::PPU;
+use nes::apu::APU;
+use nes::controller::Controller;
+
+
+type SharedMapper = Rc<RefCell<Box<dyn Mapper>>>;
+
+fn load(path: &str) -> (CPU, SharedMapper) {
+    let (cpu, _ppu, mapper) = load_machine(path);
+    (cpu, mapper)
+}
+
+fn load_machine(path: &str) -> (CPU, Rc<RefCell<PPU>>, SharedMapper) {
+    connect(path, Signal::default(), Signal::default())
+}
+
+// a machine with the interrupt lines given by the caller
+fn connect(path: &str, nmi: Signal, irq: Signal) -> (CPU, Rc<RefCell<PPU>>, SharedMapper) {
+    let cart = Cartridge::load(path, Rc::clone(&irq)).unwrap();
+    insert(cart, nmi, irq)
+}
+
+fn insert(cart: Cartridge, nmi: Signal, irq: Signal) -> (CPU, Rc<RefCell<PPU>>, SharedMapper) {
+    let mapper = cart.to_mapper();
+    let controller = Rc::new(RefCell::new(Controller::new()));
+    let ppu = Rc::new(RefCell::new(PPU::new(Rc::clone(&mapper), Rc::clone(&nmi))));
+    let apu = Rc::new(RefCell::new(APU::new(Rc::clone(&irq))));
+    let cpu = CPU::new(Rc::clone(&ppu), apu, Rc::clone(&mapper), controller, nmi, irq);
+    (cpu, ppu, mapper)
+}
+
+fn prg_ram(mapper: &SharedMapper, addr: usize) -> u8 {
+    mapper.borrow().prg_ram().unwrap()[addr]
+}
+
+// runs a program loaded at $0700 until it writes 0x5a to $6100, returns the cycles taken
+fn run_program(cpu: &mut CPU, mapper: &SharedMapper, program: &[u8]) -> u32 {
+    run_with(cpu, mapper, program, |_| ())
+}
+
+// as above, calling `before_tick` with the cycle count ahead of every tick
+fn run_with(cpu: &mut CPU, mapper: &SharedMapper, program: &[u8], mut before_tick: impl FnMut(u32)) -> u32 {
+    cpu.load_data(0x0700, program);
+    cpu.load_data(0xfffc, &[0x00, 0x07]);
+    cpu.power_up();
+    for cycles in 0..1_000_000 {
+        if prg_ram(mapper, 0x100) == 0x5a {
+            return cycles;
+        }
+        before_tick(cycles);
+        cpu.tick();
+    }
+    panic!("the program did not finish");
+}
+
+// the nmi and irq/brk handlers save x and what the interrupt pushed, status
+// first, to $6000-$6003 and finish
+const NMI_HANDLER: u16 = 0x0780;
+const IRQ_HANDLER: u16 = 0x07c0;
+
+fn load_handlers(cpu: &mut CPU) {
+    let handler = [
+        0x8e, 0x00, 0x60,   // stx $6000
+        0x68,               // pla
+        0x8d, 0x01, 0x60,   // sta $6001
+        0x68,               // pla
+        0x8d, 0x02, 0x60,   // sta $6002
+        0x68,               // pla
+        0x8d, 0x03, 0x60,   // sta $6003
+        0xa9, 0x5a,         // lda #$5a
+        0x8d, 0x00, 0x61,   // sta $6100
+        0x02,               // jam
+    ];
+    cpu.load_data(NMI_HANDLER, &handler);
+    cpu.load_data(IRQ_HANDLER, &handler);
+    cpu.load_data(0xfffa, &NMI_HANDLER.to_le_bytes());
+    cpu.load_data(0xfffe, &IRQ_HANDLER.to_le_bytes());
+}
+
+enum Line {
+    Nmi,
+    Irq,
+}
+
+// raises the line before the given cycle of a program on nestest, returns
+// x and the pushed status and return address the handler saw
+fn interrupted(program: &[u8], line: Line, at: u32) -> (u8, u8, u16) {
+    let (nmi, irq) = (Signal::default(), Signal::default());
+    let (mut cpu, _ppu, mapper) = connect("roms/nestest.nes", nmi.clone(), irq.clone());
+    load_handlers(&mut cpu);
+    let line = match line {
+        Line::Nmi => nmi,
+        Line::Irq => irq,
+    };
+    run_with(&mut cpu, &mapper, program, |cycles| if cycles == at {
+        *line.borrow_mut() = 1;
+    });
+    (prg_ram(&mapper, 0), prg_ram(&mapper, 1), u16::from_le_bytes([prg_ram(&mapper, 2), prg_ram(&mapper, 3)]))
+}
+
+
+// nestest in automation mode starts at $c000 and keeps the number of the
+// last failing test of each group at $00, $10 and $11, then copies some of
+// them to $02 and $03. a stub in ram calls it and saves all of them to prg ram
+#[test]
+fn nestest() {
+    let (mut cpu, mapper) = load("roms/nestest.nes");
+    run_program(&mut cpu, &mapper, &[
+        0x20, 0x00, 0xc0,   // jsr $c000
+        0xa2, 0x00,         // ldx #$00
+        0xb5, 0x00,         // lda $00,x
+        0x9d, 0x00, 0x60,   // sta $6000,x
+        0xe8,               // inx
+        0xe0, 0x20,         // cpx #$20
+        0xd0, 0xf6,         // bne -10
+        0xa9, 0x5a,         // lda #$5a
+        0x8d, 0x00, 0x61,   // sta $6100
+        0x02,               // jam
+    ]);
+    for addr in [0x00, 0x02, 0x03, 0x10, 0x11] {
+        assert_eq!(prg_ram(&mapper, addr), 0x00, "failing test {:#04x} at ${:02x}", prg_ram(&mapper, addr), addr);
+    }
+}
+
+#[test]
+fn jam_halts_until_reset() {
+    let (mut cpu, _mapper) = load("roms/nestest.nes");
+    cpu.load_data(0x0700, &[0x02]);
+    cpu.load_data(0x0710, &[0x00]);
+    cpu.load_data(0xfffc, &[0x00, 0x07]);
+    cpu.power_up();
+    // the opcode fetch, then the jam
+    assert_eq!(cpu.tick(), None);
+    assert_eq!(cpu.tick(), Some(CpuEvent::Halted { opcode: 0x02, pc: 0x0700 }));
+    for _ in 0..1000 {
+        assert_eq!(cpu.tick(), None);
+    }
+    assert!(cpu.halted());
+    // only reset gets the cpu going again
+    cpu.load_data(0xfffc, &[0x10, 0x07]);
+    cpu.reset();
+    assert!(!cpu.halted());
+    assert_eq!(cpu.tick(), None);
+    assert_eq!(cpu.tick(), Some(CpuEvent::Break { pc: 0x0710 }));
+}
+
+// blargg's test roms write 0x80 to $6000 while running and the result code
+// when done, with a text report from $6004
+#[test]
+fn instr_timing() {
+    let (mut cpu, ppu, mapper) = load_machine("roms/instr_timing.nes");
+    cpu.power_up();
+    ppu.borrow_mut().reset();
+    let region = Region::Ntsc;
+    let mut cycles = 0u64;
+    let mut started = false;
+    while cycles < 100_000_000 {
+        cpu.tick();
+        cycles += 1;
+        let mut ppu = ppu.borrow_mut();
+        for _ in 0..region.ppu_dots(cycles) {
+            ppu.tick();
+        }
+        match prg_ram(&mapper, 0) {
+            0x80 => started = true,
+            _ if started => break,
+            _ => (),
+        }
+    }
+    let ram = mapper.borrow();
+    let ram = ram.prg_ram().unwrap();
+    let text: String = (4..ram.size()).map(|i| ram[i]).take_while(|c| *c != 0).map(char::from).collect();
+    assert_eq!(ram[0], 0x00, "{}", text);
+}
+
+// nametable text, the test roms use the ascii code as the tile number
+fn screen_text(mapper: &SharedMapper) -> Vec<String> {
+    let mut mapper = mapper.borrow_mut();
+    (0..30u16).map(|row| {
+        let line: String = (0..32).map(|col| match mapper.read_u8(0x2000 + row * 32 + col) {
+            c @ 0x20..=0x7e => char::from(c),
+            _ => ' ',
+        }).collect();
+        line.trim().to_string()
+    }).filter(|line| !line.is_empty()).collect()
+}
+
+// the older timing test only reports on screen, after about 16 seconds
+#[test]
+fn cpu_timing_test() {
+    let (mut cpu, ppu, mapper) = load_machine("roms/cpu_timing_test.nes");
+    cpu.power_up();
+    ppu.borrow_mut().reset();
+    let region = Region::Ntsc;
+    let mut text = Vec::new();
+    for cycles in 1..30_000_000u64 {
+        cpu.tick();
+        let mut ppu = ppu.borrow_mut();
+        for _ in 0..region.ppu_dots(cycles) {
+            ppu.tick();
+        }
+        if cycles % 100_000 == 0 {
+            text = screen_text(&mapper);
+            if text.len() > 2 {
+                break;
+            }
+        }
+    }
+    assert_eq!(text.last().map(String::as_str), Some("PASSED"), "{:?}", text);
+}
+
+// the cycles an oam dma adds, started after a 2 or 3 cycle instruction
+fn oam_dma_cycles(delay: &[u8]) -> u32 {
+    let run = |store: [u8; 2]| {
+        let (mut cpu, mapper) = load("roms/nestest.nes");
+        let mut program = delay.to_vec();
+        program.extend_from_slice(&[
+            0xa9, 0x07,                 // lda #$07
+            0x8d, store[0], store[1],   // sta
+            0xa9, 0x5a,                 // lda #$5a
+            0x8d, 0x00, 0x61,           // sta $6100
+            0x02,                       // jam
+        ]);
+        run_program(&mut cpu, &mapper, &program)
+    };
+    run([0x14, 0x40]) - run([0x00, 0x02])
+}
+
+#[test]
+fn oam_dma_halts_for_513_or_514_cycles() {
+    // nop
+    let even = oam_dma_cycles(&[0xea]);
+    // lda $00
+    let odd = oam_dma_cycles(&[0xa5, 0x00]);
+    let mut cycles = [even, odd];
+    cycles.sort();
+    assert_eq!(cycles, [513, 514]);
+}
+
+#[test]
+fn oam_dma_reads_through_the_cpu_bus() {
+    let (mut cpu, mapper) = load("roms/nestest.nes");
+    let page: Vec<u8> = (0..=255u8).map(|i| i ^ 0xa5).collect();
+    cpu.load_data(0x6000, &page);
+    run_program(&mut cpu, &mapper, &[
+        0xa9, 0x60,                 // lda #$60
+        0x8d, 0x14, 0x40,           // sta $4014
+        0xa9, 0x41,                 // lda #$41
+        0x8d, 0x03, 0x20,           // sta $2003
+        0xad, 0x04, 0x20,           // lda $2004
+        0x8d, 0x01, 0x61,           // sta $6101
+        0xa9, 0xfd,                 // lda #$fd
+        0x8d, 0x03, 0x20,           // sta $2003
+        0xad, 0x04, 0x20,           // lda $2004
+        0x8d, 0x02, 0x61,           // sta $6102
+        0xa9, 0x5a,                 // lda #$5a
+        0x8d, 0x00, 0x61,           // sta $6100
+        0x02,                       // jam
+    ]);
+    assert_eq!(prg_ram(&mapper, 0x101), 0x41 ^ 0xa5);
+    assert_eq!(prg_ram(&mapper, 0x102), 0xfd ^ 0xa5);
+}
+
+// reads the controller 256 times with no buttons held, saving the or of the
+// first 8 bits of each read to $6000. a dmc fetch that halts the cpu on a
+// $4016 read reads it twice, so a 1 from past the 8th bit shows up
+fn controller_reads(dmc: u8) -> Vec<u8> {
+    let (mut cpu, mapper) = load("roms/nestest.nes");
+    run_program(&mut cpu, &mapper, &[
+        0xa9, 0x0f,                 // lda #$0f
+        0x8d, 0x10, 0x40,           // sta $4010
+        0xa9, 0xff,                 // lda #$ff
+        0x8d, 0x13, 0x40,           // sta $4013
+        0xa9, dmc,                  // lda #dmc
+        0x8d, 0x15, 0x40,           // sta $4015
+        0xa2, 0x00,                 // ldx #$00
+        0xa9, 0x01,                 // lda #$01
+        0x8d, 0x16, 0x40,           // sta $4016
+        0x4a,                       // lsr a
+        0x8d, 0x16, 0x40,           // sta $4016
+        0xa0, 0x08,                 // ldy #$08
+        0x0d, 0x16, 0x40,           // ora $4016
+        0x88,                       // dey
+        0xd0, 0xfa,                 // bne -6
+        0x9d, 0x00, 0x60,           // sta $6000,x
+        0xe8,                       // inx
+        0xd0, 0xe9,                 // bne -23
+        0xa9, 0x5a,                 // lda #$5a
+        0x8d, 0x00, 0x61,           // sta $6100
+        0x02,                       // jam
+    ]);
+    (0..0x100).map(|i| prg_ram(&mapper, i)).collect()
+}
+
+#[test]
+fn dmc_dma_double_reads_the_controller() {
+    assert!(controller_reads(0x00).iter().all(|x| *x == 0));
+    assert!(controller_reads(0x10).iter().any(|x| *x != 0));
+}
+
+// a dmc fetch that halts the cpu on the second cycle of a jam finds that
+// cycle by running it, the jam must still be reported when it really runs.
+// a looping one byte sample refetches every 432 cycles, the delay before
+// the jam sweeps over one period from past the first refetch
+#[test]
+fn dmc_dma_on_a_jam_reports_the_halt() {
+    for delay in 500..940 {
+        let (mut cpu, _mapper) = load("roms/nestest.nes");
+        let mut program = vec![
+            0xa9, 0x4f,         // lda #$4f
+            0x8d, 0x10, 0x40,   // sta $4010
+            0xa9, 0x10,         // lda #$10
+            0x8d, 0x15, 0x40,   // sta $4015
+        ];
+        // nops of 2 cycles and a zero page read of 3 for odd delays
+        if delay % 2 == 1 {
+            program.extend([0xa5, 0x00]);
+        }
+        program.extend(vec![0xea; delay / 2]);
+        program.push(0x02);
+        let pc = 0x0200 + program.len() as u16 - 1;
+        cpu.load_data(0x0200, &program);
+        cpu.load_data(0xfffc, &[0x00, 0x02]);
+        cpu.power_up();
+        let events: Vec<CpuEvent> = (0..2000).filter_map(|_| cpu.tick()).collect();
+        assert_eq!(events, [CpuEvent::Halted { opcode: 0x02, pc }], "delay {}", delay);
+    }
+}
+
+// the i flag changes on the last cycle of cli, sei and plp, after the poll
+#[test]
+fn i_flag_changes_take_effect_after_the_next_instruction() {
+    let cases: [(&[u8], u8); 3] = [
+        // cli; inx; inx
+        (&[0x58, 0xe8, 0xe8], 1),
+        // cli; sei; inx, the irq still comes after sei
+        (&[0x58, 0x78, 0xe8], 0),
+        // lda #$00; pha; plp; inx; inx
+        (&[0xa9, 0x00, 0x48, 0x28, 0xe8, 0xe8], 1),
+    ];
+    for (program, x) in cases {
+        let (saved_x, status, _) = interrupted(program, Line::Irq, 0);
+        assert_eq!(saved_x, x, "{:02x?}", program);
+        assert_eq!(status & 0x10, 0);
+    }
+}
+
+// the irq line stays asserted until its source is acknowledged, so the
+// handler is entered again after every rti
+#[test]
+fn irq_is_level_triggered() {
+    let irq = Signal::default();
+    let (mut cpu, _ppu, mapper) = connect("roms/nestest.nes", Signal::default(), irq.clone());
+    cpu.load_data(IRQ_HANDLER, &[
+        0xee, 0x00, 0x60,   // inc $6000
+        0xad, 0x00, 0x60,   // lda $6000
+        0xc9, 0x03,         // cmp #$03
+        0xf0, 0x01,         // beq +1
+        0x40,               // rti
+        0xa9, 0x5a,         // lda #$5a
+        0x8d, 0x00, 0x61,   // sta $6100
+        0x02,               // jam
+    ]);
+    cpu.load_data(0xfffe, &IRQ_HANDLER.to_le_bytes());
+    *irq.borrow_mut() = 1;
+    run_program(&mut cpu, &mapper, &[
+        0x58,               // cli
+        0x4c, 0x01, 0x07,   // jmp $0701
+    ]);
+    assert_eq!(prg_ram(&mapper, 0), 3);
+}
+
+// an nmi raised before brk pushes the status takes over its vector, the b
+// flag and the skipped byte still show it was a brk. a later nmi comes
+// before the first instruction of the brk handler
+#[test]
+fn nmi_hijacks_brk() {
+    // brk; nop
+    let program = [0x00, 0xea];
+    let (_, status, pc) = interrupted(&program, Line::Nmi, 3);
+    assert_eq!((status & 0x10, pc), (0x10, 0x0702));
+    let (_, status, pc) = interrupted(&program, Line::Nmi, 5);
+    assert_eq!((status & 0x10, pc), (0x00, IRQ_HANDLER));
+}
+
+// a taken branch that stays on its page doesn't poll on its last cycle, an
+// irq raised during its operand fetch waits one more instruction
+#[test]
+fn taken_branch_delays_the_irq() {
+    // cli; nop; clc; bcc +0; inx; inx
+    let program = [0x58, 0xea, 0x18, 0x90, 0x00, 0xe8, 0xe8];
+    let (x, _, _) = interrupted(&program, Line::Irq, 6);
+    assert_eq!(x, 0);
+    let (x, _, _) = interrupted(&program, Line::Irq, 7);
+    assert_eq!(x, 1);
+}
+
+
+// an MMC3 with 32K prg whose reset vector points to the program at $0700
+fn mmc3_machine(irq: Signal) -> (CPU, SharedMapper) {
+    let mut data = vec![b'N', b'E', b'S', 0x1a, 2, 1, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    data.resize(16 + 0x8000 + 0x2000, 0);
+    data[16 + 0x7ffc..16 + 0x8000].copy_from_slice(&[0x00, 0x07, IRQ_HANDLER as u8, (IRQ_HANDLER >> 8) as u8]);
+    let cart = Cartridge::read(&mut &data[..], Rc::clone(&irq)).unwrap();
+    let (cpu, _ppu, mapper) = insert(cart, Signal::default(), irq);
+    (cpu, mapper)
+}
+
+// the counter reloads to 2 on the first rising A12 edge and asserts the irq
+// on the third. the program polls on the middle cycle of each 3 cycle jmp
+// from cycle 16, an edge on cycle 80 is taken right after the jmp at 79 and
+// the handler acknowledges through $e000 on its 4th cycle, 89 + 3. an edge on
+// the jmp's last cycle waits for the next one
+#[test]
+fn mmc3_irq_timing() {
+    for (last_edge, acknowledged) in [(80, 92), (81, 95)] {
+        let irq = Signal::default();
+        let (mut cpu, mapper) = mmc3_machine(irq.clone());
+        cpu.load_data(IRQ_HANDLER, &[
+            0x8d, 0x00, 0xe0,   // sta $e000
+            0xa9, 0x5a,         // lda #$5a
+            0x8d, 0x00, 0x61,   // sta $6100
+            0x02,               // jam
+        ]);
+        let mut line = Vec::new();
+        let shared = Rc::clone(&mapper);
+        run_with(&mut cpu, &mapper, &[
+            0xa9, 0x02,         // lda #$02
+            0x8d, 0x00, 0xc0,   // sta $c000
+            0x8d, 0x01, 0xc0,   // sta $c001
+            0x8d, 0x01, 0xe0,   // sta $e001
+            0x58,               // cli
+            0x4c, 0x0c, 0x07,   // jmp $070c
+        ], |cycles| {
+            if [40, 60, last_edge].contains(&cycles) {
+                let mut mapper = shared.borrow_mut();
+                mapper.read_u8(0x0000);
+                mapper.read_u8(0x1000);
+            }
+            line.push(*irq.borrow());
+        });
+        // the first cycles the line was seen changed on
+        let changes: Vec<_> = line.windows(2).enumerate()
+            .filter(|(_, pair)| pair[0] != pair[1])
+            .map(|(cycle, pair)| (cycle as u32 + 1, pair[1]))
+            .collect();
+        assert_eq!(changes, [(last_edge, IRQ_MAPPER), (acknowledged + 1, 0)]);
+    }
+}
@@ -0,0 +1,36 @@
+// plays a register write script on emu2413 in VRC7 mode and prints every
+// sample, one per line. the dumps next to the scripts are made with
+//
+//   cc -O2 -o dump dump.c emu2413.c
+//   for f in *.txt; do ./dump < $f > ${f%.txt}.dump; done
+//
+// script lines are "w RR VV" for a register write and "r N" to run N
+// samples, "#" starts a comment
+
+#include <stdio.h>
+#include "emu2413.h"
+
+// one sample every 72 clocks, no rate conversion
+#define CLOCK 3579545
+#define RATE (CLOCK / 72)
+
+int main(void) {
+    OPLL *opll = OPLL_new(CLOCK, RATE);
+    OPLL_setChipType(opll, 1);
+    OPLL_resetPatch(opll, OPLL_VRC7_TONE);
+    OPLL_reset(opll);
+
+    char line[256];
+    while (fgets(line, sizeof(line), stdin)) {
+        unsigned reg, val, count;
+        if (sscanf(line, "w %x %x", &reg, &val) == 2) {
+            OPLL_writeReg(opll, reg, val);
+        } else if (sscanf(line, "r %u", &count) == 1) {
+            for (unsigned i = 0; i < count; i++) {
+                printf("%d\n", OPLL_calc(opll));
+            }
+        }
+    }
+    OPLL_delete(opll);
+    return 0;
+}
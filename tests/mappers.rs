@@ -0,0 +1,1030 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use nes::board::{Memory, Signal, IRQ_MAPPER};
+use nes::mapper::{Mapper, MirroMode, PpuFetch, MMC1, CNRom, AxRom, GxRom, ColorDreams, MMC2, MMC3, MMC3Board, MMC3Revision, MMC5, VRC4, VRC6, FME7, Namco163, VRC7};
+use nes::ppu::PPU;
+
+
+// rom where every byte holds the number of its bank
+fn banked(banks: usize, bank_size: usize) -> Memory {
+    let mut mem = Memory::new(banks * bank_size);
+    for (i, x) in mem.iter_mut().enumerate() {
+        *x = (i / bank_size) as u8;
+    }
+    mem
+}
+
+// rom filled with 0xff except for the bank number in the first byte of each bank,
+// writes elsewhere don't lose bits to bus conflicts
+fn marked(banks: usize, bank_size: usize) -> Memory {
+    let mut mem = filled(banks * bank_size, 0xff);
+    for bank in 0..banks {
+        mem[bank * bank_size] = bank as u8;
+    }
+    mem
+}
+
+// rom filled with one value, for bus conflict tests
+fn filled(size: usize, val: u8) -> Memory {
+    let mut mem = Memory::new(size);
+    mem.iter_mut().for_each(|x| *x = val);
+    mem
+}
+
+
+// serial load of the low 5 bits, with the cpu cycles of a store between writes
+fn mmc1_load(mapper: &mut MMC1, addr: u16, val: u8) {
+    for bit in 0..5 {
+        mmc1_write(mapper, addr, val >> bit);
+    }
+}
+
+fn mmc1_write(mapper: &mut MMC1, addr: u16, val: u8) {
+    for _ in 0..4 {
+        mapper.tick();
+    }
+    mapper.write_u8(addr, val);
+}
+
+#[test]
+fn mmc1_serial_register_loads() {
+    let mut mapper = MMC1::new(banked(16, 0x4000), banked(16, 0x1000), MirroMode::Horizontal, 0x2000);
+    // powers up with the last bank fixed at $C000
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    assert_eq!(mapper.read_u8(0xc000), 15);
+    mmc1_load(&mut mapper, 0xe000, 5);
+    assert_eq!(mapper.read_u8(0x8000), 5);
+    assert_eq!(mapper.read_u8(0xc000), 15);
+    // first bank fixed at $8000, vertical mirroring
+    mmc1_load(&mut mapper, 0x8000, 0x0a);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    assert_eq!(mapper.read_u8(0xc000), 5);
+    mapper.write_u8(0x2000, 0x55);
+    assert_eq!(mapper.read_u8(0x2800), 0x55);
+    // 32K mode ignores the low bit
+    mmc1_load(&mut mapper, 0x8000, 0x02);
+    assert_eq!(mapper.read_u8(0x8000), 4);
+    assert_eq!(mapper.read_u8(0xc000), 5);
+    // 8K chr ignores the low bit, 4K chr uses both registers
+    mmc1_load(&mut mapper, 0xa000, 3);
+    mmc1_load(&mut mapper, 0xc000, 7);
+    assert_eq!(mapper.read_u8(0x0000), 2);
+    assert_eq!(mapper.read_u8(0x1000), 3);
+    mmc1_load(&mut mapper, 0x8000, 0x12);
+    assert_eq!(mapper.read_u8(0x0000), 3);
+    assert_eq!(mapper.read_u8(0x1000), 7);
+}
+
+#[test]
+fn mmc1_bit_7_resets_the_shift_register() {
+    let mut mapper = MMC1::new(banked(16, 0x4000), banked(2, 0x1000), MirroMode::Horizontal, 0x2000);
+    mmc1_load(&mut mapper, 0x8000, 0x08);
+    assert_eq!(mapper.read_u8(0xc000), 0);
+    // four bits of a value that never lands
+    for _ in 0..4 {
+        mmc1_write(&mut mapper, 0xe000, 1);
+    }
+    // the reset also fixes the last bank at $C000 again
+    mmc1_write(&mut mapper, 0x8000, 0x80);
+    assert_eq!(mapper.read_u8(0xc000), 15);
+    mmc1_load(&mut mapper, 0xe000, 6);
+    assert_eq!(mapper.read_u8(0x8000), 6);
+}
+
+// a read-modify-write instruction writes twice on consecutive cycles, the
+// second write is ignored
+#[test]
+fn mmc1_ignores_writes_on_consecutive_cycles() {
+    let mut mapper = MMC1::new(banked(16, 0x4000), banked(2, 0x1000), MirroMode::Horizontal, 0x2000);
+    for bit in 0..5 {
+        mmc1_write(&mut mapper, 0xe000, 9 >> bit);
+        mapper.tick();
+        mapper.write_u8(0xe000, 0x80);
+    }
+    assert_eq!(mapper.read_u8(0x8000), 9);
+    // writes two cycles apart both count
+    mmc1_write(&mut mapper, 0xe000, 0x80);
+    for bit in 0..5 {
+        mapper.tick();
+        mapper.tick();
+        mapper.write_u8(0xe000, 3 >> bit);
+    }
+    assert_eq!(mapper.read_u8(0x8000), 3);
+}
+
+// SUROM selects the 256K half of its 512K prg with chr bit 4, SXROM adds
+// 32K of prg ram banked by chr bits 2-3
+#[test]
+fn mmc1_surom_outer_banks() {
+    let mut mapper = MMC1::new(banked(32, 0x4000), Memory::new(0x2000), MirroMode::Horizontal, 0x8000);
+    mmc1_load(&mut mapper, 0xe000, 3);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0xc000), 15);
+    mmc1_load(&mut mapper, 0xa000, 0x10);
+    assert_eq!(mapper.read_u8(0x8000), 19);
+    assert_eq!(mapper.read_u8(0xc000), 31);
+    // prg ram banks
+    mapper.write_u8(0x6000, 0x11);
+    mmc1_load(&mut mapper, 0xa000, 0x14);
+    assert_eq!(mapper.read_u8(0x6000), 0);
+    mapper.write_u8(0x6000, 0x22);
+    mmc1_load(&mut mapper, 0xa000, 0x10);
+    assert_eq!(mapper.read_u8(0x6000), 0x11);
+    assert_eq!(mapper.prg_ram().unwrap()[0x2000], 0x22);
+}
+
+// SNROM gates the prg ram with chr bit 4 as well as the $E000 enable
+#[test]
+fn mmc1_snrom_prg_ram_disable() {
+    let mut mapper = MMC1::new(banked(16, 0x4000), Memory::new(0x2000), MirroMode::Horizontal, 0x2000).with_snrom();
+    mapper.write_u8(0x6000, 0x55);
+    assert_eq!(mapper.read_u8(0x6000), 0x55);
+    mmc1_load(&mut mapper, 0xa000, 0x10);
+    assert_eq!(mapper.read_u8(0x6000), 0);
+    mapper.write_u8(0x6000, 0x66);
+    mmc1_load(&mut mapper, 0xa000, 0x00);
+    assert_eq!(mapper.read_u8(0x6000), 0x55);
+    // in 4K mode the register of the pattern table half last read drives the line
+    mmc1_load(&mut mapper, 0x8000, 0x10);
+    mmc1_load(&mut mapper, 0xc000, 0x10);
+    mapper.read_u8(0x1000);
+    assert_eq!(mapper.read_u8(0x6000), 0);
+    mapper.read_u8(0x0000);
+    assert_eq!(mapper.read_u8(0x6000), 0x55);
+    // other 8K boards ignore the bit
+    let mut mapper = MMC1::new(banked(16, 0x4000), Memory::new(0x2000), MirroMode::Horizontal, 0x2000);
+    mapper.write_u8(0x6000, 0x55);
+    mmc1_load(&mut mapper, 0xa000, 0x10);
+    assert_eq!(mapper.read_u8(0x6000), 0x55);
+}
+
+#[test]
+fn cnrom_switches_chr() {
+    let mut mapper = CNRom::new(banked(2, 0x4000), banked(4, 0x2000), MirroMode::Vertical, 0, false);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    assert_eq!(mapper.read_u8(0xc000), 1);
+    assert_eq!(mapper.read_u8(0x0000), 0);
+    mapper.write_u8(0x8000, 2);
+    assert_eq!(mapper.read_u8(0x0000), 2);
+    assert_eq!(mapper.read_u8(0x1fff), 2);
+    // prg is fixed
+    assert_eq!(mapper.read_u8(0x8000), 0);
+}
+
+#[test]
+fn cnrom_mirrors_16k_prg() {
+    let mut mapper = CNRom::new(banked(1, 0x4000), banked(1, 0x2000), MirroMode::Vertical, 0, false);
+    mapper.write_u8(0x2000, 0x55);
+    assert_eq!(mapper.read_u8(0xc000), mapper.read_u8(0x8000));
+    // vertical mirroring
+    assert_eq!(mapper.read_u8(0x2800), 0x55);
+    assert_eq!(mapper.read_u8(0x2400), 0);
+}
+
+#[test]
+fn cnrom_bus_conflicts() {
+    let mut mapper = CNRom::new(filled(0x8000, 0x01), banked(4, 0x2000), MirroMode::Vertical, 0, true);
+    mapper.write_u8(0x8000, 3);
+    assert_eq!(mapper.read_u8(0x0000), 1);
+}
+
+#[test]
+fn axrom_switches_prg_and_screen() {
+    let mut mapper = AxRom::new(banked(8, 0x8000), Memory::new(0x2000), 0, false);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    mapper.write_u8(0x8000, 0x05);
+    assert_eq!(mapper.read_u8(0x8000), 5);
+    assert_eq!(mapper.read_u8(0xffff), 5);
+    // lower single screen for every quadrant
+    mapper.write_u8(0x2000, 0x11);
+    assert_eq!(mapper.read_u8(0x2c00), 0x11);
+    // upper page
+    mapper.write_u8(0x8000, 0x15);
+    assert_eq!(mapper.read_u8(0x2000), 0);
+    mapper.write_u8(0x2400, 0x22);
+    assert_eq!(mapper.read_u8(0x2800), 0x22);
+    mapper.write_u8(0x8000, 0x05);
+    assert_eq!(mapper.read_u8(0x2400), 0x11);
+}
+
+#[test]
+fn axrom_chr_ram() {
+    let mut mapper = AxRom::new(banked(2, 0x8000), Memory::new(0x2000), 0, false);
+    mapper.write_u8(0x1234, 0x42);
+    assert_eq!(mapper.read_u8(0x1234), 0x42);
+}
+
+#[test]
+fn axrom_bus_conflicts() {
+    let mut mapper = AxRom::new(marked(8, 0x8000), Memory::new(0x2000), 0, true);
+    mapper.write_u8(0x2000, 0x77);
+    // the rom byte at $8000 is 0, so neither the bank nor the screen changes
+    mapper.write_u8(0x8000, 0x15);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    assert_eq!(mapper.read_u8(0x2400), 0x77);
+    // $8001 holds 0xff
+    mapper.write_u8(0x8001, 0x15);
+    assert_eq!(mapper.read_u8(0x8000), 5);
+    assert_eq!(mapper.read_u8(0x2400), 0);
+}
+
+#[test]
+fn gxrom_switches_prg_and_chr() {
+    let mut mapper = GxRom::new(marked(4, 0x8000), banked(4, 0x2000), MirroMode::Horizontal, 0);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    mapper.write_u8(0x8001, 0x21);
+    assert_eq!(mapper.read_u8(0x8000), 2);
+    assert_eq!(mapper.read_u8(0x0000), 1);
+    assert_eq!(mapper.read_u8(0x1fff), 1);
+    mapper.write_u8(0x8001, 0x13);
+    assert_eq!(mapper.read_u8(0x8000), 1);
+    assert_eq!(mapper.read_u8(0x0000), 3);
+}
+
+#[test]
+fn gxrom_bus_conflicts() {
+    let mut mapper = GxRom::new(banked(4, 0x8000), banked(4, 0x2000), MirroMode::Horizontal, 0);
+    // bank 0 holds zeros, so the latch is cleared
+    mapper.write_u8(0x8000, 0x33);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    assert_eq!(mapper.read_u8(0x0000), 0);
+}
+
+#[test]
+fn color_dreams_switches_prg_and_chr() {
+    let mut mapper = ColorDreams::new(marked(4, 0x8000), banked(16, 0x2000), MirroMode::Vertical, 0);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    mapper.write_u8(0x8001, 0x72);
+    assert_eq!(mapper.read_u8(0x8000), 2);
+    assert_eq!(mapper.read_u8(0xffff), 0xff);
+    assert_eq!(mapper.read_u8(0x0000), 7);
+    mapper.write_u8(0x8001, 0xf3);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0x0000), 15);
+}
+
+#[test]
+fn color_dreams_bus_conflicts() {
+    let mut mapper = ColorDreams::new(marked(4, 0x8000), banked(16, 0x2000), MirroMode::Vertical, 0);
+    // the first byte of bank 0 is 0
+    mapper.write_u8(0x8000, 0x72);
+    assert_eq!(mapper.read_u8(0x8000), 0);
+    assert_eq!(mapper.read_u8(0x0000), 0);
+}
+
+#[test]
+fn mmc2_latches_on_tile_fetch() {
+    let mut mapper = MMC2::new(banked(16, 0x2000), banked(32, 0x1000), MirroMode::Vertical, 0, false);
+    // $0000 half: FD bank 4, FE bank 5
+    mapper.write_u8(0xb000, 4);
+    mapper.write_u8(0xc000, 5);
+    // $1000 half: FD bank 6, FE bank 7
+    mapper.write_u8(0xd000, 6);
+    mapper.write_u8(0xe000, 7);
+    assert_eq!(mapper.read_u8(0x0000), 5);
+    assert_eq!(mapper.read_u8(0x1000), 7);
+    // the triggering fetch still reads the old bank
+    assert_eq!(mapper.read_u8(0x0fd8), 5);
+    assert_eq!(mapper.read_u8(0x0000), 4);
+    // only the exact address trips the $0000 latch on MMC2
+    mapper.read_u8(0x0fe9);
+    assert_eq!(mapper.read_u8(0x0000), 4);
+    mapper.read_u8(0x0fe8);
+    assert_eq!(mapper.read_u8(0x0000), 5);
+    // the $1000 latch takes the whole row
+    mapper.read_u8(0x1fdf);
+    assert_eq!(mapper.read_u8(0x1000), 6);
+    assert_eq!(mapper.read_u8(0x0000), 5);
+}
+
+#[test]
+fn mmc2_prg_banks() {
+    let mut mapper = MMC2::new(banked(16, 0x2000), banked(32, 0x1000), MirroMode::Vertical, 0, false);
+    mapper.write_u8(0xa000, 3);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0xa000), 13);
+    assert_eq!(mapper.read_u8(0xc000), 14);
+    assert_eq!(mapper.read_u8(0xe000), 15);
+    // mirroring control
+    mapper.write_u8(0xf000, 1);
+    mapper.write_u8(0x2000, 0x42);
+    assert_eq!(mapper.read_u8(0x2400), 0x42);
+}
+
+#[test]
+fn mmc4_prg_banks_and_latch() {
+    let mut mapper = MMC2::new(banked(8, 0x4000), banked(32, 0x1000), MirroMode::Vertical, 0x2000, true);
+    mapper.write_u8(0xa000, 2);
+    assert_eq!(mapper.read_u8(0x8000), 2);
+    assert_eq!(mapper.read_u8(0xc000), 7);
+    mapper.write_u8(0xb000, 4);
+    mapper.write_u8(0xc000, 5);
+    mapper.read_u8(0x0fdc);
+    assert_eq!(mapper.read_u8(0x0000), 4);
+    // prg ram
+    mapper.write_u8(0x6000, 0x99);
+    assert_eq!(mapper.read_u8(0x6000), 0x99);
+}
+
+#[test]
+fn mmc5_prg_modes() {
+    let mut mapper = MMC5::new(banked(16, 0x2000), banked(8, 0x2000), MirroMode::Vertical, 0x8000, Signal::default());
+    // power up maps the last bank at $E000
+    assert_eq!(mapper.read_u8(0xe000), 15);
+    // 8K mode
+    mapper.write_u8(0x5114, 0x81);
+    mapper.write_u8(0x5115, 0x82);
+    mapper.write_u8(0x5116, 0x83);
+    mapper.write_u8(0x5117, 0x84);
+    assert_eq!(mapper.read_u8(0x8000), 1);
+    assert_eq!(mapper.read_u8(0xa000), 2);
+    assert_eq!(mapper.read_u8(0xc000), 3);
+    assert_eq!(mapper.read_u8(0xe000), 4);
+    // 16K + 8K + 8K
+    mapper.write_u8(0x5100, 2);
+    assert_eq!(mapper.read_u8(0x8000), 2);
+    assert_eq!(mapper.read_u8(0xa000), 3);
+    assert_eq!(mapper.read_u8(0xc000), 3);
+    // 32K
+    mapper.write_u8(0x5100, 0);
+    assert_eq!(mapper.read_u8(0x8000), 4);
+    assert_eq!(mapper.read_u8(0xe000), 7);
+}
+
+#[test]
+fn mmc5_prg_ram_protect_and_banking() {
+    let mut mapper = MMC5::new(banked(16, 0x2000), banked(8, 0x2000), MirroMode::Vertical, 0x8000, Signal::default());
+    mapper.write_u8(0x6000, 0x11);
+    assert_eq!(mapper.read_u8(0x6000), 0);
+    mapper.write_u8(0x5102, 2);
+    mapper.write_u8(0x5103, 1);
+    mapper.write_u8(0x6000, 0x11);
+    mapper.write_u8(0x5113, 1);
+    mapper.write_u8(0x6000, 0x22);
+    assert_eq!(mapper.read_u8(0x6000), 0x22);
+    // ram bank 0 mapped at $8000
+    mapper.write_u8(0x5114, 0x00);
+    assert_eq!(mapper.read_u8(0x8000), 0x11);
+}
+
+#[test]
+fn mmc5_multiplier() {
+    let mut mapper = MMC5::new(banked(16, 0x2000), banked(8, 0x2000), MirroMode::Vertical, 0, Signal::default());
+    mapper.write_u8(0x5205, 200);
+    mapper.write_u8(0x5206, 100);
+    assert_eq!(mapper.read_u8(0x5205), (20000 & 0xff) as u8);
+    assert_eq!(mapper.read_u8(0x5206), (20000 >> 8) as u8);
+}
+
+#[test]
+fn mmc5_sprite_and_background_chr_sets() {
+    let mut mapper = MMC5::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, Signal::default());
+    // 1K banks
+    mapper.write_u8(0x5101, 3);
+    mapper.write_u8(0x5120, 10);
+    mapper.write_u8(0x5128, 20);
+    mapper.write_u8(0x5129, 21);
+    // 8x16 sprites with rendering on
+    mapper.ppu_register_write(0x2000, 0x20);
+    mapper.ppu_register_write(0x2001, 0x18);
+    assert_eq!(mapper.ppu_fetch(0x0000, PpuFetch::Sprite), 10);
+    assert_eq!(mapper.ppu_fetch(0x0000, PpuFetch::Background), 20);
+    // the background set repeats in the upper half
+    assert_eq!(mapper.ppu_fetch(0x1400, PpuFetch::Background), 21);
+}
+
+#[test]
+fn mmc5_scanline_irq() {
+    let irq = Signal::default();
+    let mut mapper = MMC5::new(banked(16, 0x2000), banked(8, 0x2000), MirroMode::Vertical, 0, irq.clone());
+    mapper.ppu_register_write(0x2001, 0x18);
+    mapper.write_u8(0x5203, 2);
+    mapper.write_u8(0x5204, 0x80);
+    // three reads of the same nametable byte mark a new scanline
+    let line = |mapper: &mut MMC5, addr: u16| {
+        for _ in 0..3 {
+            mapper.ppu_fetch(addr, PpuFetch::NameTable);
+        }
+    };
+    line(&mut mapper, 0x2000);
+    assert_eq!(mapper.read_u8(0x5204) & 0x40, 0x40);
+    line(&mut mapper, 0x2020);
+    assert_eq!(*irq.borrow(), 0);
+    line(&mut mapper, 0x2040);
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+    assert_eq!(mapper.read_u8(0x5204) & 0x80, 0x80);
+    // reading the status acknowledges it
+    assert_eq!(*irq.borrow(), 0);
+    assert_eq!(mapper.read_u8(0x5204) & 0x80, 0);
+}
+
+// a real ppu drives the scanline counter, the odd frame skip on the pre-render
+// line must not delay the start of the frame
+#[test]
+fn mmc5_scanline_irq_on_even_and_odd_frames() {
+    let irq = Signal::default();
+    let mapper: Box<dyn Mapper> = Box::new(MMC5::new(banked(16, 0x2000), banked(8, 0x2000), MirroMode::Vertical, 0, irq.clone()));
+    let mapper = Rc::new(RefCell::new(mapper));
+    let mut ppu = PPU::new(Rc::clone(&mapper), Signal::default());
+    ppu.write_u8(0x2001, 0x18);
+    mapper.borrow_mut().ppu_register_write(0x2001, 0x18);
+    mapper.borrow_mut().write_u8(0x5203, 20);
+    mapper.borrow_mut().write_u8(0x5204, 0x80);
+    // dots from the start of each frame to the irq
+    let mut dots = Vec::new();
+    let mut dot = 0;
+    let mut irq_dot = None;
+    for cycle in 0..(89342 * 6) {
+        if cycle % 3 == 0 {
+            mapper.borrow_mut().tick();
+        }
+        dot += 1;
+        if *irq.borrow() != 0 && irq_dot.is_none() {
+            irq_dot = Some(dot);
+            mapper.borrow_mut().read_u8(0x5204);
+        }
+        if ppu.tick() != 0 {
+            dots.extend(irq_dot.take());
+            dot = 0;
+        }
+    }
+    // the first frame has no pre-render line ahead of it
+    assert_eq!(dots.len(), 6);
+    assert!(dots[1..].iter().all(|x| *x == dots[1]), "{:?}", dots);
+}
+
+#[test]
+fn mmc5_fill_and_exram_nametables() {
+    let mut mapper = MMC5::new(banked(16, 0x2000), banked(8, 0x2000), MirroMode::Vertical, 0, Signal::default());
+    // $2000 ciram 0, $2400 exram, $2800 fill, $2C00 ciram 1
+    mapper.write_u8(0x5105, 0b01_11_10_00);
+    mapper.write_u8(0x5106, 0x42);
+    mapper.write_u8(0x5107, 0x02);
+    assert_eq!(mapper.read_u8(0x2800), 0x42);
+    assert_eq!(mapper.read_u8(0x2bc0), 0xaa);
+    mapper.write_u8(0x2400, 0x33);
+    assert_eq!(mapper.read_u8(0x2400), 0x33);
+    // exram is ram for the cpu in mode 2
+    mapper.write_u8(0x5104, 2);
+    assert_eq!(mapper.read_u8(0x5c00), 0x33);
+    mapper.write_u8(0x5c01, 0x44);
+    assert_eq!(mapper.read_u8(0x5c01), 0x44);
+}
+
+// the ppu reads the third tile's nametable byte three times from the end of
+// one line into the next, that fetch starts the frame or the next scanline
+fn mmc5_line_start(mapper: &mut MMC5, addr: u16) -> u8 {
+    mapper.ppu_fetch(addr, PpuFetch::NameTable);
+    mapper.ppu_fetch(addr, PpuFetch::NameTable);
+    mapper.ppu_fetch(addr, PpuFetch::NameTable)
+}
+
+// the nametable, attribute and both pattern fetches of one tile
+fn mmc5_tile(mapper: &mut MMC5, nt_addr: u16, at_addr: u16, pattern_addr: u16) -> [u8; 4] {
+    [
+        mapper.ppu_fetch(nt_addr, PpuFetch::NameTable),
+        mapper.ppu_fetch(at_addr, PpuFetch::Attribute),
+        mapper.ppu_fetch(pattern_addr, PpuFetch::Background),
+        mapper.ppu_fetch(pattern_addr + 8, PpuFetch::Background),
+    ]
+}
+
+// exram mode 1 gives every tile its own 4K chr bank and palette
+#[test]
+fn mmc5_extended_attributes() {
+    let mut mapper = MMC5::new(banked(16, 0x2000), banked(128, 0x1000), MirroMode::Vertical, 0, Signal::default());
+    mapper.write_u8(0x5104, 1);
+    mapper.write_u8(0x5130, 1);
+    mapper.write_u8(0x2005, 0x77);
+    mapper.write_u8(0x23c1, 0x1b);
+    mapper.ppu_register_write(0x2001, 0x18);
+    mmc5_line_start(&mut mapper, 0x2002);
+    // palette 2 and bank 5, with the upper bits from $5130
+    mapper.write_u8(0x5c05, 0b10_000101);
+    assert_eq!(mmc5_tile(&mut mapper, 0x2005, 0x23c1, 0x0123), [0x77, 0xaa, 69, 69]);
+    // the pattern table half doesn't matter
+    assert_eq!(mmc5_tile(&mut mapper, 0x2005, 0x23c1, 0x1123), [0x77, 0xaa, 69, 69]);
+    // back in mode 0 the attribute byte and chr banks come from the usual places
+    mapper.write_u8(0x5104, 0);
+    mapper.write_u8(0x5101, 0);
+    mapper.write_u8(0x5127, 1);
+    assert_eq!(mmc5_tile(&mut mapper, 0x2005, 0x23c1, 0x0123), [0x77, 0x1b, 2, 2]);
+}
+
+// the split shows exram tiles, its own attributes and a 4K chr bank on one
+// side of the threshold, scrolled by $5201
+#[test]
+fn mmc5_vertical_split() {
+    // every byte holds its 4K bank in the high nibble and its low address bits
+    let mut chr = Memory::new(0x8000);
+    for (i, x) in chr.iter_mut().enumerate() {
+        *x = ((i >> 12) << 4 | (i & 0x0f)) as u8;
+    }
+    let mut mapper = MMC5::new(banked(16, 0x2000), chr, MirroMode::Vertical, 0, Signal::default());
+    mapper.write_u8(0x5101, 0);
+    mapper.write_u8(0x5127, 1);
+    mapper.write_u8(0x2003, 0x55);
+    mapper.write_u8(0x2004, 0x66);
+    mapper.write_u8(0x23c1, 0xe4);
+    // left of tile 4, split y 16 and bank 3
+    mapper.write_u8(0x5200, 0x84);
+    mapper.write_u8(0x5201, 16);
+    mapper.write_u8(0x5202, 3);
+    mapper.ppu_register_write(0x2001, 0x18);
+    mmc5_line_start(&mut mapper, 0x2002);
+    // tile 3 of split rows 1 and 2, palette 1 top right and 2 bottom right
+    mapper.write_u8(0x5c00 + 32 + 3, 0x11);
+    mapper.write_u8(0x5c00 + 64 + 3, 0x22);
+    mapper.write_u8(0x5fc0, 0b10_00_01_00);
+    // the first line is split row 15, tile 3 is in the split and tile 4 isn't
+    assert_eq!(mmc5_tile(&mut mapper, 0x2003, 0x23c0, 0x0110), [0x11, 0x55, 0x37, 0x3f]);
+    assert_eq!(mmc5_tile(&mut mapper, 0x2004, 0x23c1, 0x0110), [0x66, 0xe4, 0x20, 0x28]);
+    // the next line is split row 16, fine y 0 of coarse row 2
+    mmc5_line_start(&mut mapper, 0x2002);
+    assert_eq!(mmc5_tile(&mut mapper, 0x2003, 0x23c0, 0x0110), [0x22, 0xaa, 0x30, 0x38]);
+    // on the right side the threshold tile is the first split one
+    mapper.write_u8(0x5200, 0xc4);
+    mapper.write_u8(0x23c0, 0x1b);
+    mapper.write_u8(0x5fc1, 0b00_11_00_00);
+    mmc5_line_start(&mut mapper, 0x2002);
+    assert_eq!(mmc5_tile(&mut mapper, 0x2003, 0x23c0, 0x0110), [0x55, 0x1b, 0x20, 0x28]);
+    assert_eq!(mmc5_tile(&mut mapper, 0x2004, 0x23c0, 0x0110), [0x00, 0xff, 0x31, 0x39]);
+}
+
+// rising A12 edges for the MMC3 irq counter
+fn clock_a12<M: Mapper>(mapper: &mut M, times: usize) {
+    for _ in 0..times {
+        mapper.read_u8(0x0000);
+        mapper.read_u8(0x1000);
+    }
+}
+
+#[test]
+fn mmc3_revisions_differ_on_zero_latch() {
+    for (revision, fires) in [(MMC3Revision::A, 0), (MMC3Revision::B, IRQ_MAPPER)] {
+        let irq = Signal::default();
+        let mut mapper = MMC3::new(banked(8, 0x2000), banked(8, 0x400), MirroMode::Vertical, 0, irq.clone())
+            .with_revision(revision);
+        mapper.write_u8(0xc000, 0);
+        mapper.write_u8(0xc001, 0);
+        mapper.write_u8(0xe001, 0);
+        clock_a12(&mut mapper, 1);
+        // revision A only fires on the reload clock
+        assert_eq!(*irq.borrow(), IRQ_MAPPER);
+        // disabling acknowledges the irq
+        mapper.write_u8(0xe000, 0);
+        mapper.write_u8(0xe001, 0);
+        assert_eq!(*irq.borrow(), 0);
+        clock_a12(&mut mapper, 1);
+        assert_eq!(*irq.borrow(), fires);
+    }
+}
+
+#[test]
+fn namco_108_ignores_mmc3_only_registers() {
+    let irq = Signal::default();
+    // bank counts that aren't powers of two, so unmasked bank bits would show
+    let mut mapper = MMC3::new(banked(12, 0x2000), banked(48, 0x400), MirroMode::Vertical, 0, irq.clone())
+        .with_board(MMC3Board::DxROM);
+    // prg mode and chr inversion bits are not connected
+    mapper.write_u8(0x8000, 0xc6);
+    mapper.write_u8(0x8001, 3);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0xc000), 10);
+    mapper.write_u8(0x8000, 0xc2);
+    mapper.write_u8(0x8001, 9);
+    assert_eq!(mapper.read_u8(0x1000), 9);
+    assert_eq!(mapper.read_u8(0x0000), 0);
+    // 4 prg and 6 chr bank bits
+    mapper.write_u8(0x8000, 0xc7);
+    mapper.write_u8(0x8001, 0x15);
+    assert_eq!(mapper.read_u8(0xa000), 5);
+    mapper.write_u8(0x8000, 0xc2);
+    mapper.write_u8(0x8001, 0x47);
+    assert_eq!(mapper.read_u8(0x1000), 7);
+    // no mirroring control or irq
+    mapper.write_u8(0x2000, 0x55);
+    mapper.write_u8(0xa000, 1);
+    assert_eq!(mapper.read_u8(0x2800), 0x55);
+    mapper.write_u8(0xc000, 1);
+    mapper.write_u8(0xe001, 0);
+    clock_a12(&mut mapper, 4);
+    assert_eq!(*irq.borrow(), 0);
+}
+
+#[test]
+fn txsrom_maps_nametables_from_chr_banks() {
+    let mut mapper = MMC3::new(banked(8, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0, Signal::default())
+        .with_board(MMC3Board::TxSROM);
+    // R0 covers $2000/$2400, R1 covers $2800/$2C00
+    mapper.write_u8(0x8000, 0);
+    mapper.write_u8(0x8001, 0x80);
+    mapper.write_u8(0x8000, 1);
+    mapper.write_u8(0x8001, 0x00);
+    mapper.write_u8(0x2000, 0x11);
+    mapper.write_u8(0x2800, 0x22);
+    assert_eq!(mapper.read_u8(0x2400), 0x11);
+    assert_eq!(mapper.read_u8(0x2c00), 0x22);
+    // mirroring writes are ignored
+    mapper.write_u8(0xa000, 1);
+    assert_eq!(mapper.read_u8(0x2400), 0x11);
+}
+
+#[test]
+fn tqrom_switches_chr_ram() {
+    let mut mapper = MMC3::new(banked(8, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, Signal::default())
+        .with_board(MMC3Board::TQROM);
+    mapper.write_u8(0x8000, 2);
+    mapper.write_u8(0x8001, 5);
+    assert_eq!(mapper.read_u8(0x1000), 5);
+    // rom ignores writes
+    mapper.write_u8(0x1000, 0xaa);
+    assert_eq!(mapper.read_u8(0x1000), 5);
+    mapper.write_u8(0x8001, 0x41);
+    mapper.write_u8(0x1000, 0xaa);
+    assert_eq!(mapper.read_u8(0x1000), 0xaa);
+    mapper.write_u8(0x8001, 5);
+    assert_eq!(mapper.read_u8(0x1000), 5);
+}
+
+#[test]
+fn vrc6_prg_and_chr_banks() {
+    let mut mapper = VRC6::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0x2000, false, Signal::default());
+    mapper.write_u8(0x8000, 2);
+    mapper.write_u8(0xc000, 7);
+    assert_eq!(mapper.read_u8(0x8000), 4);
+    assert_eq!(mapper.read_u8(0xa000), 5);
+    assert_eq!(mapper.read_u8(0xc000), 7);
+    assert_eq!(mapper.read_u8(0xe000), 15);
+    mapper.write_u8(0xd002, 9);
+    mapper.write_u8(0xe001, 33);
+    assert_eq!(mapper.read_u8(0x0800), 9);
+    assert_eq!(mapper.read_u8(0x1400), 33);
+    // prg ram is enabled by $B003, which also sets horizontal mirroring
+    mapper.write_u8(0x6000, 0x12);
+    assert_eq!(mapper.read_u8(0x6000), 0);
+    mapper.write_u8(0xb003, 0x84);
+    mapper.write_u8(0x6000, 0x12);
+    assert_eq!(mapper.read_u8(0x6000), 0x12);
+    mapper.write_u8(0x2000, 0x55);
+    assert_eq!(mapper.read_u8(0x2400), 0x55);
+}
+
+#[test]
+fn vrc6_swapped_register_lines() {
+    let mut mapper = VRC6::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, true, Signal::default());
+    // $D001 is R2 on mapper 26
+    mapper.write_u8(0xd001, 9);
+    assert_eq!(mapper.read_u8(0x0800), 9);
+}
+
+#[test]
+fn vrc6_cycle_irq() {
+    let irq = Signal::default();
+    let mut mapper = VRC6::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, false, irq.clone());
+    mapper.write_u8(0xf000, 0xfc);
+    mapper.write_u8(0xf001, 0x06);
+    for _ in 0..3 {
+        mapper.tick();
+    }
+    assert_eq!(*irq.borrow(), 0);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+    // the line stays asserted until acknowledged
+    mapper.tick();
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+    mapper.write_u8(0xf002, 0);
+    assert_eq!(*irq.borrow(), 0);
+}
+
+#[test]
+fn vrc6_scanline_irq() {
+    let irq = Signal::default();
+    let mut mapper = VRC6::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, false, irq.clone());
+    mapper.write_u8(0xf000, 0xfe);
+    mapper.write_u8(0xf001, 0x02);
+    // two scanlines of 113 2/3 cpu cycles
+    for _ in 0..227 {
+        mapper.tick();
+    }
+    assert_eq!(*irq.borrow(), 0);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+}
+
+#[test]
+fn vrc6_expansion_audio() {
+    let mut mapper = VRC6::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, false, Signal::default());
+    assert_eq!(mapper.audio_output(), 0.0);
+    // constant volume pulse
+    mapper.write_u8(0x9000, 0x8f);
+    mapper.write_u8(0x9002, 0x80);
+    let pulse = mapper.audio_output();
+    assert!(pulse > 0.0);
+    // the saw rises with every second clock of its timer
+    mapper.write_u8(0xb000, 0x3f);
+    mapper.write_u8(0xb001, 0);
+    mapper.write_u8(0xb002, 0x80);
+    mapper.tick();
+    mapper.tick();
+    assert!(mapper.audio_output() > pulse);
+    // halt stops the timers
+    mapper.write_u8(0x9003, 0x01);
+    let level = mapper.audio_output();
+    for _ in 0..32 {
+        mapper.tick();
+    }
+    assert_eq!(mapper.audio_output(), level);
+}
+
+#[test]
+fn vrc4_prg_swap_and_chr_banks() {
+    let mut mapper = VRC4::new(banked(16, 0x2000), banked(512, 0x400), MirroMode::Vertical, 0x2000, Signal::default());
+    mapper.write_u8(0x8000, 3);
+    mapper.write_u8(0xa000, 5);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0xa000), 5);
+    assert_eq!(mapper.read_u8(0xc000), 14);
+    assert_eq!(mapper.read_u8(0xe000), 15);
+    mapper.write_u8(0x9002, 0x02);
+    assert_eq!(mapper.read_u8(0x8000), 14);
+    assert_eq!(mapper.read_u8(0xc000), 3);
+    // 9 bit chr banks from a low and a high nibble
+    mapper.write_u8(0xd002, 0x04);
+    mapper.write_u8(0xd003, 0x12);
+    assert_eq!(mapper.read_u8(0x1400), 0x24);
+    assert_eq!(mapper.read_u8(0x1800), 0);
+}
+
+// $9002 bit 0 gates the wram, disabled it reads as open bus and drops writes
+#[test]
+fn vrc4_wram_enable() {
+    let mut mapper = VRC4::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0x2000, Signal::default());
+    mapper.write_u8(0x9002, 0x01);
+    mapper.write_u8(0x6000, 0x55);
+    mapper.write_u8(0x7fff, 0xaa);
+    assert_eq!((mapper.read_u8(0x6000), mapper.read_u8(0x7fff)), (0x55, 0xaa));
+    mapper.write_u8(0x9002, 0x00);
+    assert_eq!(mapper.read_u8(0x6000), 0);
+    mapper.write_u8(0x6000, 0x66);
+    // the swap mode is written along with it
+    mapper.write_u8(0x9002, 0x03);
+    assert_eq!(mapper.read_u8(0x6000), 0x55);
+    assert_eq!(mapper.read_u8(0xc000), 0);
+}
+
+#[test]
+fn vrc4_address_line_variants() {
+    // VRC4e selects registers with A2 and A3
+    let mut mapper = VRC4::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, Signal::default())
+        .with_lines(0x04, 0x08);
+    mapper.write_u8(0xb008, 7);
+    assert_eq!(mapper.read_u8(0x0400), 7);
+    mapper.write_u8(0x9000, 1);
+    mapper.write_u8(0x2000, 0x55);
+    assert_eq!(mapper.read_u8(0x2400), 0x55);
+    // or'ed lines accept either wiring
+    let mut mapper = VRC4::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, Signal::default())
+        .with_lines(0x05, 0x0a);
+    mapper.write_u8(0xc002, 3);
+    assert_eq!(mapper.read_u8(0x0c00), 3);
+    mapper.write_u8(0xc008, 4);
+    assert_eq!(mapper.read_u8(0x0c00), 4);
+}
+
+#[test]
+fn vrc2a_shifts_chr_banks() {
+    let mut mapper = VRC4::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, Signal::default())
+        .with_lines(0x02, 0x01)
+        .with_vrc2(true);
+    mapper.write_u8(0xb000, 0x0a);
+    assert_eq!(mapper.read_u8(0x0000), 5);
+    // $9000-$9003 all set the mirroring, only one bit of it
+    mapper.write_u8(0x9002, 0x03);
+    mapper.write_u8(0x2000, 0x55);
+    assert_eq!(mapper.read_u8(0x2400), 0x55);
+    // the 1 bit latch stands in for missing prg ram
+    mapper.write_u8(0x6000, 0xff);
+    assert_eq!(mapper.read_u8(0x6000), 0x01);
+}
+
+#[test]
+fn vrc4_irq_latch_nibbles() {
+    let irq = Signal::default();
+    let mut mapper = VRC4::new(banked(16, 0x2000), banked(64, 0x400), MirroMode::Vertical, 0, irq.clone());
+    mapper.write_u8(0xf000, 0x0e);
+    mapper.write_u8(0xf001, 0x0f);
+    mapper.write_u8(0xf002, 0x06);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), 0);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+}
+
+#[test]
+fn fme7_banks_through_commands() {
+    let mut mapper = FME7::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0x2000, Signal::default());
+    let command = |mapper: &mut FME7, command: u8, val: u8| {
+        mapper.write_u8(0x8000, command);
+        mapper.write_u8(0xa000, val);
+    };
+    command(&mut mapper, 9, 3);
+    command(&mut mapper, 10, 4);
+    command(&mut mapper, 11, 5);
+    command(&mut mapper, 7, 200);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0xa000), 4);
+    assert_eq!(mapper.read_u8(0xc000), 5);
+    assert_eq!(mapper.read_u8(0xe000), 31);
+    assert_eq!(mapper.read_u8(0x1c00), 200);
+    // $6000 holds a rom bank until ram is selected and enabled
+    command(&mut mapper, 8, 6);
+    assert_eq!(mapper.read_u8(0x6000), 6);
+    command(&mut mapper, 8, 0xc0);
+    mapper.write_u8(0x6000, 0x42);
+    assert_eq!(mapper.read_u8(0x6000), 0x42);
+    command(&mut mapper, 8, 0x40);
+    assert_eq!(mapper.read_u8(0x6000), 0);
+}
+
+#[test]
+fn fme7_cycle_irq() {
+    let irq = Signal::default();
+    let mut mapper = FME7::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0, irq.clone());
+    for (command, val) in [(14, 2), (15, 0), (13, 0x81)] {
+        mapper.write_u8(0x8000, command);
+        mapper.write_u8(0xa000, val);
+    }
+    // fires when the counter wraps below 0
+    for _ in 0..2 {
+        mapper.tick();
+    }
+    assert_eq!(*irq.borrow(), 0);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+}
+
+#[test]
+fn sunsoft_5b_audio() {
+    let mut mapper = FME7::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0, Signal::default());
+    let write = |mapper: &mut FME7, reg: u8, val: u8| {
+        mapper.write_u8(0xc000, reg);
+        mapper.write_u8(0xe000, val);
+    };
+    // everything off
+    write(&mut mapper, 7, 0x3f);
+    assert_eq!(mapper.audio_output(), 0.0);
+    // tone and noise disabled lets the volume through
+    write(&mut mapper, 8, 0x0f);
+    let full = mapper.audio_output();
+    assert!(full > 0.0);
+    write(&mut mapper, 8, 0x07);
+    assert!(mapper.audio_output() < full);
+    // a square on channel A flips every period of 16 cpu cycles
+    write(&mut mapper, 0, 1);
+    write(&mut mapper, 7, 0x3e);
+    let mut levels = Vec::new();
+    for _ in 0..4 {
+        for _ in 0..16 {
+            mapper.tick();
+        }
+        levels.push(mapper.audio_output());
+    }
+    assert!(levels[0] != levels[1]);
+    assert_eq!(levels[0], levels[2]);
+    // the envelope ramps up from 0
+    write(&mut mapper, 7, 0x3f);
+    write(&mut mapper, 8, 0x10);
+    write(&mut mapper, 11, 1);
+    write(&mut mapper, 13, 0x0d);
+    let start = mapper.audio_output();
+    for _ in 0..16 * 8 {
+        mapper.tick();
+    }
+    assert!(mapper.audio_output() > start);
+}
+
+#[test]
+fn namco_163_banks_and_ciram() {
+    let mut mapper = Namco163::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0x2000, Signal::default());
+    mapper.write_u8(0xe000, 3);
+    mapper.write_u8(0xe800, 4);
+    mapper.write_u8(0xf000, 5);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0xa000), 4);
+    assert_eq!(mapper.read_u8(0xc000), 5);
+    assert_eq!(mapper.read_u8(0xe000), 31);
+    // $E0 and up map ciram into the pattern table
+    mapper.write_u8(0x9000, 0xe1);
+    mapper.write_u8(0x0800, 0x77);
+    assert_eq!(mapper.read_u8(0x0800), 0x77);
+    assert_eq!(mapper.read_u8(0x2400), 0x77);
+    // unless $E800 keeps ciram out of that half
+    mapper.write_u8(0xe800, 0x40);
+    assert_eq!(mapper.read_u8(0x0800), 0xe1);
+    // nametables can come from chr rom
+    mapper.write_u8(0xc000, 9);
+    assert_eq!(mapper.read_u8(0x2000), 9);
+}
+
+#[test]
+fn namco_163_sound_ram_and_irq() {
+    let irq = Signal::default();
+    let mut mapper = Namco163::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0, irq.clone());
+    mapper.write_u8(0xf800, 0x80 | 0x10);
+    mapper.write_u8(0x4800, 0x12);
+    mapper.write_u8(0x4800, 0x34);
+    mapper.write_u8(0xf800, 0x11);
+    assert_eq!(mapper.read_u8(0x4800), 0x34);
+    assert_eq!(mapper.read_u8(0x4800), 0x34);
+    // counts up to $7FFF
+    mapper.write_u8(0x5000, 0xfd);
+    mapper.write_u8(0x5800, 0xff);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), 0);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+    assert_eq!(mapper.read_u8(0x5000), 0xff);
+    mapper.tick();
+    assert_eq!(mapper.read_u8(0x5800), 0xff);
+}
+
+#[test]
+fn namco_163_multiplexes_channels() {
+    let mut mapper = Namco163::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0, Signal::default());
+    let write = |mapper: &mut Namco163, addr: u8, val: u8| {
+        mapper.write_u8(0xf800, addr);
+        mapper.write_u8(0x4800, val);
+    };
+    // a wave of constant 15 at address 0, on channel 7 only
+    write(&mut mapper, 0x00, 0xff);
+    write(&mut mapper, 0x7c, 0xfc);
+    write(&mut mapper, 0x7e, 0x00);
+    write(&mut mapper, 0x7f, 0x0f);
+    let levels = |mapper: &mut Namco163| -> Vec<f32> {
+        (0..4).map(|_| {
+            for _ in 0..15 {
+                mapper.tick();
+            }
+            mapper.audio_output()
+        }).collect()
+    };
+    // one channel is heard all the time
+    let single = levels(&mut mapper);
+    assert!(single.iter().all(|x| *x > 0.0));
+    // with two channels, channel 7 is heard every second update
+    write(&mut mapper, 0x7f, 0x1f);
+    let double = levels(&mut mapper);
+    assert_eq!(double.iter().filter(|x| **x > 0.0).count(), 2);
+}
+
+#[test]
+fn vrc7_banks_and_register_lines() {
+    // VRC7a picks the second register of each pair with A4
+    let mut mapper = VRC7::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0x2000, 0x10, Signal::default());
+    mapper.write_u8(0x8000, 3);
+    mapper.write_u8(0x8010, 4);
+    mapper.write_u8(0x9000, 5);
+    assert_eq!(mapper.read_u8(0x8000), 3);
+    assert_eq!(mapper.read_u8(0xa000), 4);
+    assert_eq!(mapper.read_u8(0xc000), 5);
+    assert_eq!(mapper.read_u8(0xe000), 31);
+    mapper.write_u8(0xa010, 7);
+    mapper.write_u8(0xd010, 200);
+    assert_eq!(mapper.read_u8(0x0400), 7);
+    assert_eq!(mapper.read_u8(0x1c00), 200);
+    // $E000 enables prg ram and sets the mirroring
+    mapper.write_u8(0xe000, 0x81);
+    mapper.write_u8(0x6000, 0x42);
+    assert_eq!(mapper.read_u8(0x6000), 0x42);
+    mapper.write_u8(0x2000, 0x55);
+    assert_eq!(mapper.read_u8(0x2400), 0x55);
+    // VRC7b uses A3
+    let mut mapper = VRC7::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0, 0x08, Signal::default());
+    mapper.write_u8(0x8008, 6);
+    assert_eq!(mapper.read_u8(0xa000), 6);
+}
+
+#[test]
+fn vrc7_irq_and_audio() {
+    let irq = Signal::default();
+    let mut mapper = VRC7::new(banked(32, 0x2000), banked(256, 0x400), MirroMode::Vertical, 0, 0x10, irq.clone());
+    mapper.write_u8(0xe010, 0xff);
+    mapper.write_u8(0xf000, 0x06);
+    mapper.tick();
+    assert_eq!(*irq.borrow(), IRQ_MAPPER);
+    // key on a rom patch through the $9010/$9030 ports
+    for (reg, val) in [(0x30, 0x30), (0x10, 0x00), (0x20, 0x19)] {
+        mapper.write_u8(0x9010, reg);
+        mapper.write_u8(0x9030, val);
+    }
+    let heard = (0..36 * 64).any(|_| {
+        mapper.tick();
+        mapper.audio_output() != 0.0
+    });
+    assert!(heard);
+    // audio reset silences it
+    mapper.write_u8(0xe000, 0x40);
+    mapper.tick();
+    assert_eq!(mapper.audio_output(), 0.0);
+}
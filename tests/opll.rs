@@ -0,0 +1,227 @@
+use std::f64::consts::PI;
+use std::fs;
+use nes::opll::{Opll, VRC7_PATCHES};
+
+
+// custom patch: a pure carrier with instant attack and sustain, the modulator never sounds
+const SINE_PATCH: [u8; 8] = [0x00, 0x21, 0x3f, 0x00, 0x00, 0xf0, 0x00, 0x00];
+
+// as above with a full level modulator at the same frequency
+const FM_PATCH: [u8; 8] = [0x21, 0x21, 0x00, 0x00, 0xf0, 0xf0, 0x00, 0x00];
+
+
+fn load_patch(opll: &mut Opll, patch: &[u8; 8]) {
+    for (reg, val) in patch.iter().enumerate() {
+        opll.write(reg as u8, *val);
+    }
+}
+
+// fnum 256 at block 4 steps the 10 bit sine index by 8, a 128 sample period
+fn key_on(opll: &mut Opll, channel: u8, instrument: u8, volume: u8) {
+    opll.write(0x30 + channel, instrument << 4 | volume);
+    opll.write(0x10 + channel, 0x00);
+    opll.write(0x20 + channel, 0x10 | 4 << 1 | 0x01);
+}
+
+fn samples(opll: &mut Opll, count: usize) -> Vec<i32> {
+    (0..count).map(|_| opll.clock()).collect()
+}
+
+// operator output for a sine index, as the chip's log-sin and exp tables approximate it
+fn reference(index: f64, attenuation_db: f64) -> f64 {
+    4096.0 * (2.0 * PI * (index + 0.5) / 1024.0).sin() * 10f64.powf(-attenuation_db / 20.0)
+}
+
+// register write scripts and the samples emu2413 plays for them, dumped by
+// dump.c in the same directory
+const FIXTURES: &str = "tests/fixtures/opll";
+
+// samples further apart than this part of the sine's peak fail
+const TOLERANCE: f64 = 1.0 / 128.0;
+
+fn play_script(name: &str) -> Vec<i32> {
+    let script = fs::read_to_string(format!("{}/{}.txt", FIXTURES, name)).unwrap();
+    let mut opll = Opll::new();
+    let mut out = vec![];
+    for line in script.lines().filter(|line| !line.starts_with('#')) {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args[..] {
+            ["w", reg, val] => opll.write(u8::from_str_radix(reg, 16).unwrap(), u8::from_str_radix(val, 16).unwrap()),
+            ["r", count] => out.extend(samples(&mut opll, count.parse().unwrap())),
+            _ => panic!("{}: bad script line {:?}", name, line),
+        }
+    }
+    out
+}
+
+fn read_dump(name: &str) -> Vec<i32> {
+    let dump = fs::read_to_string(format!("{}/{}.dump", FIXTURES, name))
+        .unwrap_or_else(|_| panic!("no {}.dump, run dump.c on the scripts", name));
+    dump.lines().map(|line| line.trim().parse().unwrap()).collect()
+}
+
+fn peak(samples: &[i32]) -> f64 {
+    samples.iter().map(|x| x.abs()).max().unwrap() as f64
+}
+
+// emu2413 mixes at its own scale, the sine sets the gain between the two
+fn matches_dump(name: &str) {
+    let full_scale = peak(&play_script("sine"));
+    let gain = full_scale / peak(&read_dump("sine"));
+    let out = play_script(name);
+    let dump = read_dump(name);
+    assert_eq!(out.len(), dump.len(), "{}", name);
+    for (n, (out, dump)) in out.iter().zip(dump.iter()).enumerate() {
+        let expected = *dump as f64 * gain;
+        assert!((*out as f64 - expected).abs() <= full_scale * TOLERANCE, "{} sample {}: {} != {:.1}", name, n, out, expected);
+    }
+}
+
+
+#[test]
+fn silent_until_key_on() {
+    let mut opll = Opll::new();
+    load_patch(&mut opll, &SINE_PATCH);
+    opll.write(0x30, 0x00);
+    opll.write(0x10, 0x00);
+    opll.write(0x20, 0x09);
+    assert!(samples(&mut opll, 256).iter().all(|x| *x == 0));
+}
+
+#[test]
+fn carrier_matches_reference_sine() {
+    let mut opll = Opll::new();
+    load_patch(&mut opll, &SINE_PATCH);
+    key_on(&mut opll, 0, 0, 0);
+    for (n, out) in samples(&mut opll, 256).iter().enumerate() {
+        let expected = reference((n * 8 % 1024) as f64, 0.0);
+        assert!((*out as f64 - expected).abs() <= 8.0, "sample {}: {} != {:.1}", n, out, expected);
+    }
+}
+
+#[test]
+fn volume_steps_are_3_db() {
+    let mut opll = Opll::new();
+    load_patch(&mut opll, &SINE_PATCH);
+    key_on(&mut opll, 0, 0, 4);
+    // the peak of the wave, 12 dB down
+    let out = samples(&mut opll, 128);
+    let peak = *out.iter().max().unwrap() as f64;
+    assert!((peak - reference(255.5, 12.0)).abs() <= 8.0, "peak {}", peak);
+}
+
+#[test]
+fn modulator_matches_reference_fm() {
+    let mut opll = Opll::new();
+    load_patch(&mut opll, &FM_PATCH);
+    key_on(&mut opll, 0, 0, 0);
+    for (n, out) in samples(&mut opll, 256).iter().enumerate() {
+        let index = (n * 8 % 1024) as f64;
+        // full scale modulation moves the carrier by 4 sine periods
+        let modulation = reference(index, 0.0).round();
+        let expected = reference(index + modulation, 0.0);
+        // table rounding in the modulator moves the carrier by a few sine steps
+        assert!((*out as f64 - expected).abs() <= 128.0, "sample {}: {} != {:.1}", n, out, expected);
+    }
+}
+
+#[test]
+fn feedback_changes_the_modulator() {
+    let mut plain = Opll::new();
+    load_patch(&mut plain, &FM_PATCH);
+    key_on(&mut plain, 0, 0, 0);
+    let mut patch = FM_PATCH;
+    patch[3] |= 0x07;
+    let mut feedback = Opll::new();
+    load_patch(&mut feedback, &patch);
+    key_on(&mut feedback, 0, 0, 0);
+    // the first sample comes before any output was fed back
+    let plain = samples(&mut plain, 128);
+    let feedback = samples(&mut feedback, 128);
+    assert_eq!(plain[0], feedback[0]);
+    assert_ne!(plain, feedback);
+}
+
+#[test]
+fn rom_patches_match_custom_registers() {
+    for (i, patch) in VRC7_PATCHES.iter().enumerate() {
+        let mut rom = Opll::new();
+        key_on(&mut rom, 0, i as u8 + 1, 0);
+        let mut custom = Opll::new();
+        load_patch(&mut custom, patch);
+        key_on(&mut custom, 0, 0, 0);
+        assert_eq!(samples(&mut rom, 512), samples(&mut custom, 512), "patch {}", i + 1);
+    }
+}
+
+#[test]
+fn key_off_releases_to_silence() {
+    let mut opll = Opll::new();
+    let mut patch = SINE_PATCH;
+    patch[7] = 0x0f;
+    load_patch(&mut opll, &patch);
+    key_on(&mut opll, 0, 0, 0);
+    samples(&mut opll, 64);
+    opll.write(0x20, 0x09);
+    samples(&mut opll, 512);
+    assert!(samples(&mut opll, 128).iter().all(|x| *x == 0));
+}
+
+#[test]
+fn channels_are_summed() {
+    let mut one = Opll::new();
+    load_patch(&mut one, &SINE_PATCH);
+    key_on(&mut one, 0, 0, 0);
+    let mut six = Opll::new();
+    load_patch(&mut six, &SINE_PATCH);
+    for channel in 0..6 {
+        key_on(&mut six, channel, 0, 0);
+    }
+    let one = samples(&mut one, 128);
+    let six = samples(&mut six, 128);
+    for (a, b) in one.iter().zip(six.iter()) {
+        assert_eq!(a * 6, *b);
+    }
+}
+
+#[test]
+#[ignore = "needs the emu2413 dumps, see tests/fixtures/opll/dump.c"]
+fn sine_matches_emu2413() {
+    matches_dump("sine");
+}
+
+#[test]
+#[ignore = "needs the emu2413 dumps, see tests/fixtures/opll/dump.c"]
+fn volume_steps_match_emu2413() {
+    matches_dump("volume");
+}
+
+#[test]
+#[ignore = "needs the emu2413 dumps, see tests/fixtures/opll/dump.c"]
+fn fm_matches_emu2413() {
+    matches_dump("fm");
+}
+
+#[test]
+#[ignore = "needs the emu2413 dumps, see tests/fixtures/opll/dump.c"]
+fn feedback_matches_emu2413() {
+    matches_dump("feedback");
+}
+
+#[test]
+#[ignore = "needs the emu2413 dumps, see tests/fixtures/opll/dump.c"]
+fn release_matches_emu2413() {
+    matches_dump("release");
+}
+
+#[test]
+#[ignore = "needs the emu2413 dumps, see tests/fixtures/opll/dump.c"]
+fn channel_sum_matches_emu2413() {
+    matches_dump("channels");
+}
+
+#[test]
+#[ignore = "needs the emu2413 dumps, see tests/fixtures/opll/dump.c"]
+fn rom_patches_match_emu2413() {
+    matches_dump("rom_patches");
+}
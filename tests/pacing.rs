@@ -0,0 +1,51 @@
+use nes::pacing::{Pacer, PacingMode};
+
+
+const FRAME_RATE: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44_100.0;
+// three frames of audio
+const TARGET: usize = 2205;
+
+
+#[test]
+fn fixed_modes_keep_the_sample_rate() {
+    for mode in [PacingMode::Audio, PacingMode::Vsync] {
+        let pacer = Pacer::new(mode, FRAME_RATE, SAMPLE_RATE);
+        for queued in [0, TARGET, TARGET * 10] {
+            assert_eq!(pacer.sample_rate(queued), SAMPLE_RATE);
+        }
+    }
+}
+
+// the rate is nudged up below the target and down above it, never more than half a percent
+#[test]
+fn dynamic_rate_stays_within_bounds() {
+    let pacer = Pacer::new(PacingMode::DynamicRate, FRAME_RATE, SAMPLE_RATE);
+    assert_eq!(pacer.sample_rate(TARGET), SAMPLE_RATE);
+    assert!((pacer.sample_rate(0) - SAMPLE_RATE * 1.005).abs() < 1e-6);
+    assert!((pacer.sample_rate(TARGET * 2) - SAMPLE_RATE * 0.995).abs() < 1e-6);
+    // a queue far past the target doesn't push the rate further
+    assert_eq!(pacer.sample_rate(TARGET * 10), pacer.sample_rate(TARGET * 2));
+    let mut last = f64::MAX;
+    for queued in (0..TARGET * 3).step_by(100) {
+        let rate = pacer.sample_rate(queued);
+        assert!(rate <= last, "{} samples queued", queued);
+        assert!((SAMPLE_RATE * 0.995..=SAMPLE_RATE * 1.005).contains(&rate));
+        if queued < TARGET {
+            assert!(rate > SAMPLE_RATE);
+        } else if queued > TARGET {
+            assert!(rate < SAMPLE_RATE);
+        }
+        last = rate;
+    }
+}
+
+#[test]
+fn audio_overrun_past_four_times_the_target() {
+    for mode in [PacingMode::Audio, PacingMode::Vsync, PacingMode::DynamicRate] {
+        let pacer = Pacer::new(mode, FRAME_RATE, SAMPLE_RATE);
+        assert!(!pacer.audio_overrun(0));
+        assert!(!pacer.audio_overrun(TARGET * 4));
+        assert!(pacer.audio_overrun(TARGET * 4 + 1));
+    }
+}
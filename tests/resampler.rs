@@ -0,0 +1,93 @@
+use nes::resampler::Resampler;
+
+
+const CLOCK_RATE: f64 = 1_789_773.0;
+const SAMPLE_RATE: f64 = 44_100.0;
+
+
+fn read_all(resampler: &mut Resampler) -> Vec<i16> {
+    let mut out = vec![0; resampler.samples_avail()];
+    let count = resampler.read_samples(&mut out);
+    out.truncate(count);
+    out
+}
+
+// square wave of the given period in clocks and amplitude, over `clocks`
+fn square(resampler: &mut Resampler, period: u32, amplitude: f32, clocks: u32) {
+    let mut level = 0.0;
+    for time in (0..clocks).step_by(period as usize / 2) {
+        let target = if level > 0.0 { -amplitude } else { amplitude };
+        resampler.add_delta(time, target - level);
+        level = target;
+    }
+    resampler.end_frame(clocks);
+}
+
+
+#[test]
+fn output_length_follows_the_rate_ratio() {
+    let mut resampler = Resampler::new(CLOCK_RATE, SAMPLE_RATE);
+    // a frame's worth of clocks, then a second of them in uneven frames
+    resampler.end_frame(29781);
+    assert_eq!(resampler.samples_avail(), (29781.0 * SAMPLE_RATE / CLOCK_RATE) as usize);
+    read_all(&mut resampler);
+    let mut total = 0;
+    let mut clocks = 0;
+    for frame in 0..60 {
+        let length = 29781 + frame % 2;
+        resampler.end_frame(length);
+        clocks += length;
+        total += read_all(&mut resampler).len();
+    }
+    let expected = clocks as f64 * SAMPLE_RATE / CLOCK_RATE;
+    assert!((total as f64 - expected).abs() <= 1.0, "{} samples, expected {:.1}", total, expected);
+}
+
+#[test]
+fn silence_stays_silent() {
+    let mut resampler = Resampler::new(CLOCK_RATE, SAMPLE_RATE);
+    resampler.end_frame(29781);
+    assert!(read_all(&mut resampler).iter().all(|x| *x == 0));
+}
+
+// a step reaches its height within the kernel width, overshooting by no more
+// than the ripple of a band-limited step. the dc blocker then pulls the
+// constant level back to zero
+#[test]
+fn step_response() {
+    let mut resampler = Resampler::new(CLOCK_RATE, SAMPLE_RATE);
+    resampler.add_delta(100, 0.5);
+    resampler.end_frame(CLOCK_RATE as u32);
+    let out: Vec<f32> = read_all(&mut resampler).iter().map(|x| *x as f32 / 32767.0).collect();
+    // nothing ahead of the kernel
+    assert!(out[..4].iter().all(|x| x.abs() < 0.002));
+    let peak = out.iter().cloned().fold(0.0, f32::max);
+    assert!(peak > 0.5 && peak < 0.5 * 1.15, "peak {}", peak);
+    assert!(out[12..16].iter().all(|x| (x - 0.5).abs() < 0.02), "{:?}", &out[12..16]);
+    // steady at zero after a second
+    assert!(out[out.len() - 100..].iter().all(|x| x.abs() < 0.001));
+}
+
+// a 1 kHz square keeps its amplitude, far above the dc blocker and below nyquist
+#[test]
+fn square_wave_gain() {
+    let mut resampler = Resampler::new(CLOCK_RATE, SAMPLE_RATE);
+    square(&mut resampler, (CLOCK_RATE / 1000.0) as u32, 0.25, CLOCK_RATE as u32 / 10);
+    let out = read_all(&mut resampler);
+    // the flat tops of the settled half, past the dc blocker's start
+    let mut high: Vec<i16> = out[out.len() / 2..].iter().cloned().filter(|x| *x > 0).collect();
+    high.sort();
+    let level = high[high.len() / 2] as f32 / 32767.0;
+    assert!((level - 0.25).abs() < 0.02, "level {}", level);
+}
+
+#[test]
+fn clear_drops_pending_samples() {
+    let mut resampler = Resampler::new(CLOCK_RATE, SAMPLE_RATE);
+    resampler.add_delta(0, 0.5);
+    resampler.end_frame(29781);
+    resampler.clear();
+    assert_eq!(resampler.samples_avail(), 0);
+    resampler.end_frame(29781);
+    assert!(read_all(&mut resampler).iter().all(|x| *x == 0));
+}
@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use nes::board::Memory;
+use nes::mapper::{Mapper, NRom, MirroMode};
+use nes::save::SaveFile;
+
+
+// an empty directory of its own for every test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nes-save-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn save_for(dir: &Path) -> SaveFile {
+    SaveFile::for_rom(dir.join("game.nes").to_str().unwrap())
+}
+
+fn nrom() -> NRom {
+    NRom::new(Memory::new(0x8000), Memory::new(0x2000), MirroMode::Vertical, 0x2000)
+}
+
+
+#[test]
+fn save_file_sits_next_to_the_rom() {
+    assert_eq!(SaveFile::for_rom("roms/Some Game (U).nes").path(), PathBuf::from("roms/Some Game (U).sav"));
+    assert_eq!(SaveFile::for_rom("game").path(), PathBuf::from("game.sav"));
+}
+
+#[test]
+fn missing_save_leaves_ram_alone() {
+    let dir = temp_dir("missing");
+    let mut ram = vec![0x12; 16];
+    assert!(!save_for(&dir).load(&mut ram).unwrap());
+    assert_eq!(ram, vec![0x12; 16]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// prg ram written through $6000 comes back in a new mapper after a flush
+#[test]
+fn prg_ram_round_trip() {
+    let dir = temp_dir("round-trip");
+    let mut mapper = nrom();
+    let mut save = save_for(&dir);
+    assert!(!save.load(mapper.prg_ram_mut().unwrap()).unwrap());
+    for addr in 0x6000..0x8000u16 {
+        mapper.write_u8(addr, addr as u8 ^ (addr >> 8) as u8);
+    }
+    save.flush(mapper.prg_ram().unwrap()).unwrap();
+    // written through a temporary file that is renamed over the save
+    assert_eq!(fs::read(dir.join("game.sav")).unwrap().len(), 0x2000);
+    assert!(!dir.join("game.sav.tmp").exists());
+
+    let mut mapper = nrom();
+    assert!(save_for(&dir).load(mapper.prg_ram_mut().unwrap()).unwrap());
+    for addr in 0x6000..0x8000u16 {
+        assert_eq!(mapper.read_u8(addr), addr as u8 ^ (addr >> 8) as u8);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// a save of another size loads as much as fits
+#[test]
+fn load_size_mismatch() {
+    let dir = temp_dir("size");
+    fs::write(dir.join("game.sav"), [1, 2, 3, 4]).unwrap();
+    let mut ram = vec![0; 2];
+    assert!(save_for(&dir).load(&mut ram).unwrap());
+    assert_eq!(ram, [1, 2]);
+    let mut ram = vec![9; 6];
+    assert!(save_for(&dir).load(&mut ram).unwrap());
+    assert_eq!(ram, [1, 2, 3, 4, 9, 9]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// changed ram is written every 300 frames, unchanged ram never
+#[test]
+fn autosave_every_300_frames() {
+    let dir = temp_dir("autosave");
+    let path = dir.join("game.sav");
+    let mut save = save_for(&dir);
+    let mut ram = vec![0; 16];
+    save.load(&mut ram).unwrap();
+    for _ in 0..300 {
+        save.autosave(&ram).unwrap();
+    }
+    assert!(!path.exists());
+    ram[3] = 0x33;
+    for _ in 0..299 {
+        save.autosave(&ram).unwrap();
+    }
+    assert!(!path.exists());
+    save.autosave(&ram).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), ram);
+    // flush writes right away, only when something changed
+    fs::remove_file(&path).unwrap();
+    save.flush(&ram).unwrap();
+    assert!(!path.exists());
+    ram[4] = 0x44;
+    save.flush(&ram).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), ram);
+    fs::remove_dir_all(&dir).unwrap();
+}